@@ -282,7 +282,8 @@ impl BenchmarkRepoFactory {
     ) -> Result<ArcPushrebaseMutationMapping> {
         Ok(Arc::new(
             SqlPushrebaseMutationMappingConnection::with_sqlite_in_memory()?
-                .with_repo_id(repo_identity.id()),
+                .with_repo_id(repo_identity.id())
+                .with_repo_name(repo_identity.name().to_string()),
         ))
     }
 
@@ -806,3 +806,99 @@ async fn setup_inner_blobstores<'a>(
     }
     Ok((normal_components, write_only_components))
 }
+
+#[cfg(test)]
+mod test {
+    use context::CoreContext;
+    use fbinit::FacebookInit;
+    use metaconfig_types::LocalDatabaseConfig;
+    use mononoke_types::BlobstoreBytes;
+    use multiplexedblob::scrub::default_scrub_handler;
+    use sql_ext::facebook::PoolConfig;
+    use sql_ext::facebook::ReadConnectionType;
+    use sql_ext::facebook::SharedConnectionPool;
+    use sqlblob::get_test_config_store;
+
+    use super::*;
+
+    fn test_blobstore_options() -> BlobstoreOptions {
+        BlobstoreOptions::new(
+            ChaosOptions::new(None, None),
+            DelayOptions::default(),
+            ThrottleOptions::default(),
+            #[cfg(fbcode_build)]
+            ManifoldOptions::default(),
+            PackOptions::default(),
+            CachelibBlobstoreOptions::default(),
+            None,
+            test_mysql_options(),
+        )
+    }
+
+    fn test_mysql_options() -> MysqlOptions {
+        MysqlOptions {
+            pool: SharedConnectionPool::new(),
+            pool_config: PoolConfig::default(),
+            read_connection_type: ReadConnectionType::Master,
+        }
+    }
+
+    #[fbinit::test]
+    async fn test_make_scrub_multiplexed_wal(fb: FacebookInit) -> Result<(), Error> {
+        let ctx = CoreContext::test_mock(fb);
+        let dir = tempfile::tempdir()?;
+        let blobconfig = BlobConfig::MultiplexedWal {
+            multiplex_id: MultiplexId::new(1),
+            blobstores: (0..2)
+                .map(|id| {
+                    (
+                        BlobstoreId::new(id),
+                        MultiplexedStoreType::Normal,
+                        BlobConfig::Files {
+                            path: dir.path().join(format!("store{}", id)),
+                        },
+                    )
+                })
+                .collect(),
+            write_quorum: 1,
+            queue_db: ShardedDatabaseConfig::Local(LocalDatabaseConfig {
+                path: dir.path().to_owned(),
+            }),
+            inner_blobstores_scuba_table: None,
+            multiplex_scuba_table: None,
+            scuba_sample_rate: NonZeroU64::new(1).unwrap(),
+        };
+        let mut blobstore_options = test_blobstore_options();
+        blobstore_options.set_scrub_options(ScrubOptions::default());
+        let mysql_options = test_mysql_options();
+        let logger = Logger::root(slog::Discard, slog::o!());
+        let (_test_source, config_store) = get_test_config_store();
+        let scrub_handler = default_scrub_handler();
+
+        let blobstore = make_blobstore(
+            fb,
+            blobconfig,
+            &mysql_options,
+            ReadOnlyStorage(false),
+            &blobstore_options,
+            &logger,
+            &config_store,
+            &scrub_handler,
+            None,
+        )
+        .await?;
+        assert!(blobstore.to_string().starts_with("WalScrubBlobstore"));
+
+        let value = BlobstoreBytes::from_bytes(b"value".to_vec());
+        blobstore.put(&ctx, "key".to_owned(), value.clone()).await?;
+        assert_eq!(
+            blobstore
+                .get(&ctx, "key")
+                .await?
+                .map(|data| data.into_bytes()),
+            Some(value)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Compares the read strategies of the WAL multiplexed blobstore, with one local blobstore and
+//! two remote ones that all have the blobs. Reports the latency of the gets, and how many of them
+//! reached the remote blobstores.
+
+use std::fmt;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use blobstore::Blobstore;
+use blobstore::BlobstoreGetData;
+use blobstore::BlobstoreIsPresent;
+use blobstore::BlobstorePutOps;
+use blobstore::OverwriteStatus;
+use blobstore::PutBehaviour;
+use blobstore_sync_queue::SqlBlobstoreWal;
+use context::CoreContext;
+use delayblob::DelayedBlobstore;
+use delayblob::Normal;
+use fbinit::FacebookInit;
+use futures::future;
+use memblob::Memblob;
+use metaconfig_types::BlobstoreId;
+use metaconfig_types::MultiplexId;
+use mononoke_types::BlobstoreBytes;
+use multiplexedblob_wal::MultiplexReadStrategy;
+use multiplexedblob_wal::Scuba;
+use multiplexedblob_wal::WalMultiplexedBlobstore;
+use nonzero_ext::nonzero;
+use scuba_ext::MononokeScubaSampleBuilder;
+use sql_construct::SqlConstruct;
+
+const NUM_KEYS: usize = 1000;
+const CONCURRENCY: usize = 50;
+const LOCAL_ID: u64 = 0;
+
+/// Counts the gets reaching the inner blobstore.
+#[derive(Debug)]
+struct CountingBlobstore {
+    inner: Arc<dyn BlobstorePutOps>,
+    gets: AtomicU64,
+}
+
+impl fmt::Display for CountingBlobstore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CountingBlobstore<{}>", self.inner)
+    }
+}
+
+#[async_trait]
+impl Blobstore for CountingBlobstore {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        self.gets.fetch_add(1, Ordering::Relaxed);
+        self.inner.get(ctx, key).await
+    }
+
+    async fn is_present<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<BlobstoreIsPresent> {
+        self.inner.is_present(ctx, key).await
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        self.inner.put(ctx, key, value).await
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for CountingBlobstore {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        self.inner
+            .put_explicit(ctx, key, value, put_behaviour)
+            .await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        self.inner.put_with_status(ctx, key, value).await
+    }
+}
+
+async fn populated_store(
+    ctx: &CoreContext,
+    get_latency: Option<Duration>,
+) -> Result<Arc<CountingBlobstore>> {
+    let inner: Arc<dyn BlobstorePutOps> = match get_latency {
+        None => Arc::new(Memblob::default()),
+        Some(latency) => Arc::new(DelayedBlobstore::new(
+            Memblob::default(),
+            Normal::new(latency.as_secs_f64(), latency.as_secs_f64() / 10.0)?,
+            Normal::new(0.0, 0.0)?,
+        )),
+    };
+    for i in 0..NUM_KEYS {
+        inner
+            .put(
+                ctx,
+                format!("key{}", i),
+                BlobstoreBytes::from_bytes(vec![0; 1024]),
+            )
+            .await?;
+    }
+    Ok(Arc::new(CountingBlobstore {
+        inner,
+        gets: AtomicU64::new(0),
+    }))
+}
+
+async fn bench_read_strategy(
+    ctx: &CoreContext,
+    name: &str,
+    read_strategy: MultiplexReadStrategy,
+) -> Result<()> {
+    let local = populated_store(ctx, None).await?;
+    let remotes = vec![
+        populated_store(ctx, Some(Duration::from_millis(20))).await?,
+        populated_store(ctx, Some(Duration::from_millis(20))).await?,
+    ];
+
+    let blobstores = std::iter::once((BlobstoreId::new(LOCAL_ID), local.clone()))
+        .chain(
+            remotes
+                .iter()
+                .enumerate()
+                .map(|(i, bs)| (BlobstoreId::new(i as u64 + 1), bs.clone())),
+        )
+        .map(|(id, bs)| (id, bs as Arc<dyn BlobstorePutOps>))
+        .collect();
+    let scuba = Scuba::new(
+        MononokeScubaSampleBuilder::with_discard(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )?;
+    let multiplex = WalMultiplexedBlobstore::new(
+        MultiplexId::new(1),
+        Arc::new(SqlBlobstoreWal::with_sqlite_in_memory()?),
+        blobstores,
+        vec![],
+        2,
+        None,
+        scuba,
+    )?
+    .with_read_strategy(read_strategy)?;
+
+    let keys: Vec<_> = (0..NUM_KEYS).map(|i| format!("key{}", i)).collect();
+    let start = Instant::now();
+    for chunk in keys.chunks(CONCURRENCY) {
+        future::try_join_all(chunk.iter().map(|key| multiplex.get(ctx, key))).await?;
+    }
+    let elapsed = start.elapsed();
+
+    let remote_gets: u64 = remotes
+        .iter()
+        .map(|bs| bs.gets.load(Ordering::Relaxed))
+        .sum();
+    println!(
+        "{}: {} gets in {:?}, {} local gets, {} remote gets",
+        name,
+        NUM_KEYS,
+        elapsed,
+        local.gets.load(Ordering::Relaxed),
+        remote_gets,
+    );
+    Ok(())
+}
+
+#[fbinit::main]
+async fn main(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+
+    bench_read_strategy(&ctx, "all", MultiplexReadStrategy::All).await?;
+    bench_read_strategy(
+        &ctx,
+        "hedged",
+        MultiplexReadStrategy::Hedged {
+            preferred: vec![BlobstoreId::new(LOCAL_ID)],
+            delay: Duration::from_millis(5),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
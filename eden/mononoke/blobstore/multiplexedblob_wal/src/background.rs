@@ -0,0 +1,173 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::VecDeque;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::Future;
+use futures::FutureExt;
+use tokio::sync::oneshot;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// The tasks completing the writes that a put no longer waits for, counted so that they can be
+/// waited for before shutting down.
+#[derive(Clone)]
+pub(crate) struct BackgroundWrites {
+    pending: Arc<watch::Sender<usize>>,
+    /// Bounds the number of writes running at once across all the puts, the others wait for
+    /// their turn.
+    limit: Option<Arc<Mutex<WriteQueue>>>,
+}
+
+/// The writes waiting for one of the slots, which are each run by a task going through the
+/// queue until it's empty. The waiting writes aren't spawned, so that a burst of puts doesn't
+/// leave as many tasks parked on the runtime.
+struct WriteQueue {
+    slots: usize,
+    running: usize,
+    waiting: VecDeque<BoxFuture<'static, ()>>,
+}
+
+/// Marks a task as completed when dropped, whether it ran to completion, panicked or was
+/// cancelled.
+struct PendingGuard(Arc<watch::Sender<usize>>);
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        self.0.send_modify(|pending| *pending -= 1);
+    }
+}
+
+impl BackgroundWrites {
+    pub(crate) fn new() -> Self {
+        let (pending, _) = watch::channel(0);
+        Self {
+            pending: Arc::new(pending),
+            limit: None,
+        }
+    }
+
+    /// Run at most `max_concurrent` of the writes spawned with `spawn_write` at once.
+    pub(crate) fn with_limit(max_concurrent: usize) -> Self {
+        Self {
+            limit: Some(Arc::new(Mutex::new(WriteQueue {
+                slots: max_concurrent,
+                running: 0,
+                waiting: VecDeque::new(),
+            }))),
+            ..Self::new()
+        }
+    }
+
+    /// Spawn `fut` on the runtime, keeping track of it until it completes.
+    pub(crate) fn spawn<T: Send + 'static>(
+        &self,
+        fut: impl Future<Output = T> + Send + 'static,
+    ) -> JoinHandle<T> {
+        self.pending.send_modify(|pending| *pending += 1);
+        let guard = PendingGuard(self.pending.clone());
+        tokio::spawn(async move {
+            let _guard = guard;
+            fut.await
+        })
+    }
+
+    /// Run the write `fut` in the background, keeping track of it until it completes. It is
+    /// queued until fewer writes than the limit are running. The returned receiver gets its
+    /// output, or is cancelled if it panicked.
+    ///
+    /// Tasks waiting for writes must be spawned with `spawn` instead, so that they don't hold
+    /// a slot the writes they wait for need.
+    pub(crate) fn spawn_write<T: Send + 'static>(
+        &self,
+        fut: impl Future<Output = T> + Send + 'static,
+    ) -> oneshot::Receiver<T> {
+        let (sender, receiver) = oneshot::channel();
+        self.pending.send_modify(|pending| *pending += 1);
+        let guard = PendingGuard(self.pending.clone());
+        let write = async move {
+            let _guard = guard;
+            // Nobody may be waiting for the output.
+            let _ = sender.send(fut.await);
+        }
+        .boxed();
+
+        match &self.limit {
+            None => {
+                tokio::spawn(write);
+            }
+            Some(queue) => {
+                let start = {
+                    let mut queue = queue.lock().expect("lock poisoned");
+                    if queue.running < queue.slots {
+                        queue.running += 1;
+                        Some(write)
+                    } else {
+                        queue.waiting.push_back(write);
+                        None
+                    }
+                };
+                if let Some(write) = start {
+                    tokio::spawn(run_writes(queue.clone(), write));
+                }
+            }
+        }
+        receiver
+    }
+
+    /// Number of the spawned tasks that haven't completed yet.
+    pub(crate) fn pending(&self) -> usize {
+        *self.pending.borrow()
+    }
+
+    /// Number of the writes still waiting for a slot.
+    pub(crate) fn queued(&self) -> usize {
+        self.limit.as_ref().map_or(0, |queue| {
+            queue.lock().expect("lock poisoned").waiting.len()
+        })
+    }
+
+    /// Wait up to `timeout` for all the spawned tasks to complete, including the ones spawned
+    /// while waiting. Returns whether they did.
+    pub(crate) async fn drain(&self, timeout: Duration) -> bool {
+        let mut receiver = self.pending.subscribe();
+        let all_completed = async move {
+            while *receiver.borrow_and_update() > 0 {
+                // The sender is owned by `self`, so it can't be dropped while waiting.
+                if receiver.changed().await.is_err() {
+                    break;
+                }
+            }
+        };
+        tokio::time::timeout(timeout, all_completed).await.is_ok()
+    }
+}
+
+/// Run `write`, then the queued writes until there are none left, and free the slot.
+async fn run_writes(queue: Arc<Mutex<WriteQueue>>, mut write: BoxFuture<'static, ()>) {
+    loop {
+        // A write that panics only cancels its own output, the next ones still run.
+        let _ = AssertUnwindSafe(write).catch_unwind().await;
+        let next = {
+            let mut queue = queue.lock().expect("lock poisoned");
+            let next = queue.waiting.pop_front();
+            if next.is_none() {
+                queue.running -= 1;
+            }
+            next
+        };
+        match next {
+            Some(next) => write = next,
+            None => return,
+        }
+    }
+}
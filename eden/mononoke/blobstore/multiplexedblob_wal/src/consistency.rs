@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use blobstore_stats::OperationType;
+use context::CoreContext;
+use futures::future;
+use futures::StreamExt;
+use metaconfig_types::BlobstoreId;
+use mononoke_types::hash::Blake2;
+use mononoke_types::hash::Context;
+
+use crate::multiplex;
+use crate::WalMultiplexedBlobstore;
+
+const CONSISTENCY_CHECK_HASH_KEY: &[u8] = b"multiplexedblob_wal.consistency_check";
+
+/// What one of the underlying blobstores holds for a key.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum KeyState {
+    /// The blobstore has the key. The hash identifies the content, so that different contents
+    /// can be told apart without keeping them around.
+    Present { content_hash: Blake2 },
+    /// The blobstore doesn't have the key.
+    Absent,
+    /// The blobstore failed to answer.
+    Failed(String),
+}
+
+/// What each normal and write-mostly blobstore holds for a key, as reported by
+/// `WalMultiplexedBlobstore::check_consistency`.
+#[derive(Clone, Debug)]
+pub struct ConsistencyReport {
+    pub key: String,
+    /// The state of the key in each blobstore, the normal ones first, in configuration order.
+    pub stores: Vec<(BlobstoreId, KeyState)>,
+}
+
+impl ConsistencyReport {
+    /// The state held by most of the blobstores that answered, ties going to the state of the
+    /// first of them. `None` if none of them answered.
+    pub fn majority(&self) -> Option<&KeyState> {
+        let mut counts: HashMap<&KeyState, usize> = HashMap::new();
+        let answered = self
+            .stores
+            .iter()
+            .map(|(_id, state)| state)
+            .filter(|state| !matches!(state, KeyState::Failed(_)));
+        let mut majority: Option<(&KeyState, usize)> = None;
+        for state in answered {
+            let count = counts.entry(state).or_default();
+            *count += 1;
+            if majority.map_or(true, |(_state, max)| *count > max) {
+                majority = Some((state, *count));
+            }
+        }
+        majority.map(|(state, _count)| state)
+    }
+
+    /// The blobstores that answered with a different state than the majority.
+    pub fn disagreeing(&self) -> Vec<BlobstoreId> {
+        match self.majority() {
+            Some(majority) => self
+                .stores
+                .iter()
+                .filter(|(_id, state)| !matches!(state, KeyState::Failed(_)) && state != majority)
+                .map(|(id, _state)| *id)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The blobstores that failed to answer.
+    pub fn failed(&self) -> Vec<BlobstoreId> {
+        self.stores
+            .iter()
+            .filter(|(_id, state)| matches!(state, KeyState::Failed(_)))
+            .map(|(id, _state)| *id)
+            .collect()
+    }
+
+    /// Whether all the blobstores answered, and all of them with the same state.
+    pub fn is_consistent(&self) -> bool {
+        self.failed().is_empty() && self.disagreeing().is_empty()
+    }
+}
+
+impl WalMultiplexedBlobstore {
+    /// Read `key` from each normal and write-mostly blobstore and report what each of them holds.
+    /// This is a read-only diagnostic: it waits for all the blobstores regardless of the quorum,
+    /// and doesn't repair anything.
+    pub async fn check_consistency(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+    ) -> Result<ConsistencyReport> {
+        let mut scuba = self.scuba.clone();
+        scuba.sampled();
+
+        let (normal, write_only) = future::join(
+            multiplex::inner_multi_get(
+                ctx,
+                self.blobstores.clone(),
+                key,
+                OperationType::ScrubGet,
+                &scuba,
+                self.inflight_ops_counter.clone(),
+            )
+            .collect::<Vec<_>>(),
+            multiplex::inner_multi_get(
+                ctx,
+                self.write_only_blobstores.clone(),
+                key,
+                OperationType::ScrubGet,
+                &scuba,
+                self.inflight_ops_counter.clone(),
+            )
+            .collect::<Vec<_>>(),
+        )
+        .await;
+
+        let mut results: HashMap<_, _> = normal.into_iter().chain(write_only).collect();
+        let stores = self
+            .blobstores
+            .iter()
+            .chain(self.write_only_blobstores.iter())
+            .filter_map(|bs| {
+                let state = match results.remove(bs.id())? {
+                    Ok(Some(data)) => {
+                        let mut hash = Context::new(CONSISTENCY_CHECK_HASH_KEY);
+                        hash.update(data.as_raw_bytes());
+                        KeyState::Present {
+                            content_hash: hash.finish(),
+                        }
+                    }
+                    Ok(None) => KeyState::Absent,
+                    Err(err) => KeyState::Failed(format!("{:#}", err)),
+                };
+                Some((*bs.id(), state))
+            })
+            .collect();
+
+        Ok(ConsistencyReport {
+            key: key.to_owned(),
+            stores,
+        })
+    }
+}
@@ -0,0 +1,113 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use std::time::Instant;
+
+use blobstore::BlobstoreIsPresent;
+use context::CoreContext;
+use futures::future;
+use metaconfig_types::BlobstoreId;
+use tokio::sync::Mutex;
+
+use crate::timed::TimedStore;
+use crate::WalMultiplexedBlobstore;
+
+const HEALTH_CHECK_SENTINEL_KEY: &str = "multiplexedblob_wal.health_check_sentinel";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlobstoreHealth {
+    /// The blobstore answered the probe promptly.
+    Healthy,
+    /// The blobstore answered the probe, but slowly or without being sure of the answer.
+    Degraded,
+    /// The blobstore failed the probe or didn't answer it in time.
+    Down,
+}
+
+/// Configuration of the probes checking the health of the underlying blobstores.
+#[derive(Clone, Debug)]
+pub struct HealthCheckConfig {
+    /// Key looked up by the probes. Whether it exists doesn't matter.
+    pub sentinel_key: String,
+    /// Probes taking longer than this report the blobstore as down.
+    pub timeout: Duration,
+    /// Probes taking longer than this report the blobstore as degraded.
+    pub degraded_latency: Duration,
+    /// Minimum interval between two rounds of probes. In between, the last snapshot is reused.
+    pub min_interval: Duration,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            sentinel_key: HEALTH_CHECK_SENTINEL_KEY.to_owned(),
+            timeout: Duration::from_secs(1),
+            degraded_latency: Duration::from_millis(200),
+            min_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+pub(crate) struct HealthCheck {
+    config: HealthCheckConfig,
+    /// The last snapshot along with the time it was taken. Held while probing, so concurrent
+    /// callers wait for the round in progress instead of starting their own.
+    last: Mutex<Option<(Instant, Vec<(BlobstoreId, BlobstoreHealth)>)>>,
+}
+
+impl HealthCheck {
+    pub(crate) fn new(config: HealthCheckConfig) -> Self {
+        Self {
+            config,
+            last: Mutex::new(None),
+        }
+    }
+}
+
+impl WalMultiplexedBlobstore {
+    /// Report the health of each normal and write-only blobstore, by probing them with an
+    /// `is_present` call on a sentinel key. The probes bypass the retries and the quorum logic,
+    /// and are run at most once per `HealthCheckConfig::min_interval`.
+    pub async fn blobstore_health(&self, ctx: &CoreContext) -> Vec<(BlobstoreId, BlobstoreHealth)> {
+        let mut last = self.health_check.last.lock().await;
+        if let Some((taken_at, snapshot)) = &*last {
+            if taken_at.elapsed() < self.health_check.config.min_interval {
+                return snapshot.clone();
+            }
+        }
+
+        let stores = self
+            .blobstores
+            .iter()
+            .chain(self.write_only_blobstores.iter());
+        let snapshot = future::join_all(stores.map(|bs| self.probe(ctx, bs))).await;
+        *last = Some((Instant::now(), snapshot.clone()));
+        snapshot
+    }
+
+    async fn probe(&self, ctx: &CoreContext, bs: &TimedStore) -> (BlobstoreId, BlobstoreHealth) {
+        let config = &self.health_check.config;
+        let start = Instant::now();
+
+        self.inflight_ops_counter.fetch_add(1, Ordering::Relaxed);
+        let result = bs.probe(ctx, &config.sentinel_key, config.timeout).await;
+        self.inflight_ops_counter.fetch_sub(1, Ordering::Relaxed);
+
+        let health = match result {
+            Ok(BlobstoreIsPresent::Present | BlobstoreIsPresent::Absent)
+                if start.elapsed() <= config.degraded_latency =>
+            {
+                BlobstoreHealth::Healthy
+            }
+            Ok(_) => BlobstoreHealth::Degraded,
+            Err(_) => BlobstoreHealth::Down,
+        };
+        (*bs.id(), health)
+    }
+}
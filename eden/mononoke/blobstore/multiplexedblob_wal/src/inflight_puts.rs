@@ -0,0 +1,130 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Error;
+use anyhow::Result;
+use blobstore::OverwriteStatus;
+use blobstore::PutBehaviour;
+use mononoke_types::BlobstoreBytes;
+use tokio::sync::watch;
+
+type SharedResult = Result<OverwriteStatus, Arc<Error>>;
+
+struct InflightPut {
+    value: BlobstoreBytes,
+    put_behaviour: Option<PutBehaviour>,
+    result: watch::Receiver<Option<SharedResult>>,
+}
+
+/// The puts in progress, by key, so that the identical puts started meanwhile wait for their
+/// result rather than writing the same blob again.
+#[derive(Default)]
+pub(crate) struct InflightPuts {
+    puts: Mutex<HashMap<String, InflightPut>>,
+}
+
+/// How a put relates to the puts in progress.
+pub(crate) enum CoalescedPut<'a> {
+    /// No put of the key is in progress: this one does the writes and shares its result.
+    Leader(PutLeader<'a>),
+    /// The same value is being put with the same behaviour: this one waits for its result.
+    Follower(PutFollower),
+    /// Another value, or the same value with another behaviour, is being put: this one does
+    /// the writes on its own.
+    Uncoalesced,
+}
+
+impl InflightPuts {
+    pub(crate) fn join(
+        &self,
+        key: &str,
+        value: &BlobstoreBytes,
+        put_behaviour: Option<PutBehaviour>,
+    ) -> CoalescedPut<'_> {
+        let mut puts = self.puts.lock().expect("lock poisoned");
+        match puts.entry(key.to_owned()) {
+            Entry::Occupied(entry) => {
+                let put = entry.get();
+                if put.put_behaviour == put_behaviour && &put.value == value {
+                    CoalescedPut::Follower(PutFollower {
+                        result: put.result.clone(),
+                    })
+                } else {
+                    CoalescedPut::Uncoalesced
+                }
+            }
+            Entry::Vacant(entry) => {
+                let (sender, receiver) = watch::channel(None);
+                entry.insert(InflightPut {
+                    value: value.clone(),
+                    put_behaviour,
+                    result: receiver,
+                });
+                CoalescedPut::Leader(PutLeader {
+                    puts: self,
+                    key: key.to_owned(),
+                    result: sender,
+                })
+            }
+        }
+    }
+}
+
+/// Stops coalescing the puts of the key when dropped, e.g. when the put is cancelled.
+pub(crate) struct PutLeader<'a> {
+    puts: &'a InflightPuts,
+    key: String,
+    result: watch::Sender<Option<SharedResult>>,
+}
+
+impl PutLeader<'_> {
+    /// Share the result of the put with the puts waiting for it.
+    pub(crate) fn complete(self, result: &Result<OverwriteStatus>) {
+        let result = match result {
+            Ok(status) => Ok(*status),
+            Err(err) => Err(Arc::new(anyhow!("{:#}", err))),
+        };
+        // The send only fails if no put is waiting.
+        let _ = self.result.send(Some(result));
+    }
+}
+
+impl Drop for PutLeader<'_> {
+    fn drop(&mut self) {
+        self.puts
+            .puts
+            .lock()
+            .expect("lock poisoned")
+            .remove(&self.key);
+    }
+}
+
+pub(crate) struct PutFollower {
+    result: watch::Receiver<Option<SharedResult>>,
+}
+
+impl PutFollower {
+    /// The result of the put in progress, or `None` if it was cancelled before completing.
+    pub(crate) async fn wait(mut self) -> Option<Result<OverwriteStatus>> {
+        loop {
+            let result = self.result.borrow().clone();
+            if let Some(result) = result {
+                return Some(result.map_err(|err| anyhow!("Coalesced put failed: {:#}", err)));
+            }
+            // The sender is only dropped without a value when the put was cancelled.
+            if self.result.changed().await.is_err() {
+                return None;
+            }
+        }
+    }
+}
@@ -5,13 +5,39 @@
  * GNU General Public License version 2.
  */
 
+mod background;
+mod consistency;
+mod health;
+mod inflight_puts;
 pub(crate) mod multiplex;
+mod recent_puts;
+mod recent_sizes;
+mod recent_writes;
+mod retry;
 pub mod scrub;
 #[cfg(test)]
 mod test;
 mod timed;
+mod trace;
+mod verify;
 
+pub use consistency::ConsistencyReport;
+pub use consistency::KeyState;
+pub use health::BlobstoreHealth;
+pub use health::HealthCheckConfig;
+pub use multiplex::ErrorKind;
+pub use multiplex::GetFailures;
 pub use multiplex::MultiplexQuorum;
+pub use multiplex::MultiplexReadStrategy;
+pub use multiplex::OverwriteStatusPolicy;
 pub use multiplex::Scuba;
+pub use multiplex::WalFailureMode;
 pub use multiplex::WalMultiplexedBlobstore;
+pub use recent_puts::PutDedupConfig;
+pub use recent_sizes::BlobSizeCheckConfig;
+pub use recent_writes::RecentWritesConfig;
+pub use retry::MultiplexRetry;
+pub use retry::TransientErrorClassifier;
 pub use timed::MultiplexTimeout;
+pub use trace::TraceEntry;
+pub use trace::TraceOutcome;
@@ -6,12 +6,14 @@
  */
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
 use std::num::NonZeroU64;
 use std::num::NonZeroUsize;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::anyhow;
 use anyhow::Context as _;
@@ -25,15 +27,22 @@ use blobstore::BlobstorePutOps;
 use blobstore::OverwriteStatus;
 use blobstore::PutBehaviour;
 use blobstore_stats::OperationType;
+use blobstore_stats::BLOBSTORE_ID;
+use blobstore_stats::KEY;
 use blobstore_sync_queue::BlobstoreWal;
 use blobstore_sync_queue::BlobstoreWalEntry;
+use blobstore_sync_queue::BlobstoreWalPriority;
 use cloned::cloned;
 use context::CoreContext;
 use context::PerfCounterType;
+use context::SessionClass;
 use fbinit::FacebookInit;
 use futures::future;
+use futures::future::BoxFuture;
+use futures::stream;
 use futures::stream::FuturesUnordered;
 use futures::Future;
+use futures::FutureExt;
 use futures::Stream;
 use futures::StreamExt;
 use futures::TryStreamExt;
@@ -46,11 +55,27 @@ use multiplexedblob::scuba;
 use scuba_ext::MononokeScubaSampleBuilder;
 use thiserror::Error;
 use time_ext::DurationExt;
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
 
+use crate::background::BackgroundWrites;
+use crate::health::HealthCheck;
+use crate::health::HealthCheckConfig;
+use crate::inflight_puts::CoalescedPut;
+use crate::inflight_puts::InflightPuts;
+use crate::recent_puts::PutDedupConfig;
+use crate::recent_puts::RecentPuts;
+use crate::recent_sizes::BlobSizeCheckConfig;
+use crate::recent_sizes::RecentSizes;
+use crate::recent_writes::RecentWrites;
+use crate::recent_writes::RecentWritesConfig;
+use crate::retry::MultiplexRetry;
+use crate::timed::with_retried_stores;
 use crate::timed::with_timed_stores;
 use crate::timed::MultiplexTimeout;
 use crate::timed::TimedStore;
+use crate::trace::KeyTracer;
+use crate::verify::verify_content_hash;
 type BlobstoresReturnedError = HashMap<BlobstoreId, Error>;
 
 #[derive(Error, Debug, Clone)]
@@ -59,12 +84,25 @@ pub enum ErrorKind {
     AllFailed(Arc<BlobstoresReturnedError>),
     #[error("Failures on put in underlying single blobstores: {0:?}")]
     SomePutsFailed(Arc<BlobstoresReturnedError>),
-    #[error("Failures on get in underlying single blobstores: {0:?}")]
-    SomeGetsFailed(Arc<BlobstoresReturnedError>),
+    #[error(
+        "Failures on get in underlying single blobstores: {:?}, {} absent",
+        .0.errors,
+        .0.absent
+    )]
+    SomeGetsFailed(Arc<GetFailures>),
     #[error("Failures on is_present in underlying single blobstores: {0:?}")]
     SomeIsPresentsFailed(Arc<BlobstoresReturnedError>),
 }
 
+/// The outcome of a `get` that reached neither the blob nor the read quorum.
+#[derive(Debug)]
+pub struct GetFailures {
+    /// The errors of the blobstores that failed.
+    pub errors: BlobstoresReturnedError,
+    /// How many blobstores reported the blob as absent, short of the read quorum.
+    pub absent: usize,
+}
+
 #[derive(Clone, Debug)]
 pub struct MultiplexQuorum {
     pub(crate) read: NonZeroUsize,
@@ -88,6 +126,137 @@ impl MultiplexQuorum {
     }
 }
 
+/// How the normal blobstores are read from on `get`.
+#[derive(Clone, Debug, Default)]
+pub enum MultiplexReadStrategy {
+    /// Read from all the blobstores at once.
+    #[default]
+    All,
+    /// Read from the preferred blobstores first (e.g. the local or faster ones), and only read
+    /// from the rest if the preferred ones didn't return the blob nor reach the read quorum
+    /// within `delay`.
+    Hedged {
+        preferred: Vec<BlobstoreId>,
+        delay: Duration,
+    },
+}
+
+impl MultiplexReadStrategy {
+    fn validate(&self, blobstores: &[TimedStore]) -> Result<()> {
+        if let Self::Hedged { preferred, .. } = self {
+            for id in preferred {
+                if !blobstores.iter().any(|bs| bs.id() == id) {
+                    return Err(anyhow!(
+                        "Preferred blobstore {} is not one of the normal blobstores",
+                        id
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Split the blobstores between the ones to read from first, and the ones to read from
+    /// after the hedging delay, if any.
+    fn split(
+        &self,
+        blobstores: &Arc<[TimedStore]>,
+    ) -> (Arc<[TimedStore]>, Option<(Arc<[TimedStore]>, Duration)>) {
+        match self {
+            Self::All => (blobstores.clone(), None),
+            Self::Hedged { preferred, delay } => {
+                let (first, rest): (Vec<_>, Vec<_>) = blobstores
+                    .iter()
+                    .cloned()
+                    .partition(|bs| preferred.contains(bs.id()));
+                (first.into(), Some((rest.into(), *delay)))
+            }
+        }
+    }
+}
+
+/// What `put` does when the write to the WAL fails.
+#[derive(Clone, Debug, Default)]
+pub enum WalFailureMode {
+    /// Fail the put, as the blobstores missing the blob couldn't be healed.
+    #[default]
+    Fail,
+    /// Write to the blobstores anyway, waiting for all the writes to complete. The put succeeds
+    /// if `write_quorum` normal blobstores, at least as many as usual, succeeded. This trades
+    /// the healing of the blobstores that missed the blob for availability.
+    WriteWithoutWal { write_quorum: usize },
+}
+
+impl WalFailureMode {
+    fn validate(&self, num_stores: usize, quorum: &MultiplexQuorum) -> Result<()> {
+        if let Self::WriteWithoutWal { write_quorum } = self {
+            if *write_quorum < quorum.write.get() || *write_quorum > num_stores {
+                return Err(anyhow!(
+                    "Write quorum without the WAL must be between {} and {}, got {}",
+                    quorum.write,
+                    num_stores,
+                    write_quorum,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the overwrite status returned by `put` is derived from the ones reported by the normal
+/// blobstores.
+#[derive(Clone, Debug, Default)]
+pub enum OverwriteStatusPolicy {
+    /// Combine the statuses of the blobstores that completed before the write quorum was reached,
+    /// see `aggregate_overwrite_status`.
+    #[default]
+    Quorum,
+    /// Report the status of most of the blobstores that checked whether the key was present,
+    /// ties being reported as `Overwrote`, and `Prevented` still winning over everything.
+    ///
+    /// If none of the blobstores that reached the write quorum checked, wait up to `timeout`
+    /// for one of the remaining writes to do so, returning `NotChecked` if none did.
+    Majority { timeout: Duration },
+}
+
+impl OverwriteStatusPolicy {
+    /// Whether `statuses` are enough to decide the overwrite status of the put.
+    fn has_verdict(&self, statuses: &[OverwriteStatus]) -> bool {
+        match self {
+            Self::Quorum => true,
+            Self::Majority { .. } => statuses
+                .iter()
+                .any(|status| *status != OverwriteStatus::NotChecked),
+        }
+    }
+
+    fn verdict(&self, statuses: &[OverwriteStatus]) -> OverwriteStatus {
+        match self {
+            Self::Quorum => statuses
+                .iter()
+                .fold(None, |acc, status| {
+                    Some(aggregate_overwrite_status(acc, *status))
+                })
+                .unwrap_or(OverwriteStatus::NotChecked),
+            Self::Majority { .. } => {
+                if statuses.contains(&OverwriteStatus::Prevented) {
+                    return OverwriteStatus::Prevented;
+                }
+                let count = |expected| statuses.iter().filter(|s| **s == expected).count();
+                let new = count(OverwriteStatus::New);
+                let overwrote = count(OverwriteStatus::Overwrote);
+                if new == 0 && overwrote == 0 {
+                    OverwriteStatus::NotChecked
+                } else if new > overwrote {
+                    OverwriteStatus::New
+                } else {
+                    OverwriteStatus::Overwrote
+                }
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Scuba {
     pub(crate) inner_blobstores_scuba: MononokeScubaSampleBuilder,
@@ -142,18 +311,52 @@ pub struct WalMultiplexedBlobstore {
     pub(crate) wal_queue: Arc<dyn BlobstoreWal>,
 
     pub(crate) quorum: MultiplexQuorum,
+    /// Order in which the normal blobstores are read from on `get`.
+    pub(crate) read_strategy: MultiplexReadStrategy,
+    /// Whether `put` can proceed when the WAL is unavailable.
+    pub(crate) wal_failure_mode: WalFailureMode,
+    /// Whether `get` checks the blobs against the content hash encoded in their key, treating
+    /// a mismatch as a failure of the blobstore that returned the blob.
+    pub(crate) verify_content_hashes: bool,
     /// These are the "normal" blobstores, which are read from on `get`, and written to on `put`
     /// as part of normal operation.
     pub(crate) blobstores: Arc<[TimedStore]>,
     /// Write-mostly blobstores are not normally read from on `get`, but take part in writes
     /// like a normal blobstore.
     pub(crate) write_only_blobstores: Arc<[TimedStore]>,
+    /// Number of write-mostly blobstore writes that must succeed, on top of the write quorum,
+    /// before a write succeeds.
+    pub(crate) write_mostly_quorum: usize,
+    /// How the overwrite status of a put is derived from the ones of the blobstores.
+    pub(crate) overwrite_status_policy: OverwriteStatusPolicy,
 
     /// Scuba table to log status of the underlying single blobstore queries.
     pub(crate) scuba: Scuba,
 
     /// Counter keeping track of the yet-to-complete blobstore operations in flight.
     pub(crate) inflight_ops_counter: Arc<AtomicU64>,
+
+    /// Filter of the keys written by this process, used to answer `is_present` without
+    /// querying the blobstores.
+    pub(crate) recent_writes: Option<Arc<RecentWrites>>,
+
+    /// The recent puts confirmed by all the blobstores, used to skip the identical ones.
+    pub(crate) recent_puts: Option<Arc<RecentPuts>>,
+
+    /// The puts in progress, shared with the identical puts started meanwhile.
+    pub(crate) inflight_puts: Option<Arc<InflightPuts>>,
+
+    /// The sizes of the blobs put by this process, checked against the blobs read by `get`.
+    pub(crate) recent_sizes: Option<Arc<RecentSizes>>,
+
+    /// Probing of the underlying blobstores backing `blobstore_health`.
+    pub(crate) health_check: Arc<HealthCheck>,
+
+    /// The writes completing in the background after a put returned, waited for by `drain`.
+    pub(crate) background_writes: BackgroundWrites,
+
+    /// The calls to the underlying blobstores recorded by `trace_key`.
+    pub(crate) key_tracer: Arc<KeyTracer>,
 }
 
 impl Drop for WalMultiplexedBlobstore {
@@ -203,20 +406,285 @@ impl WalMultiplexedBlobstore {
         let quorum = MultiplexQuorum::new(blobstores.len(), write_quorum)?;
 
         let to = timeout.unwrap_or_default();
-        let blobstores = with_timed_stores(blobstores, to.clone()).into();
-        let write_only_blobstores = with_timed_stores(write_only_blobstores, to).into();
+        let key_tracer = Arc::new(KeyTracer::default());
+        let blobstores = with_timed_stores(blobstores, to.clone(), &key_tracer).into();
+        let write_only_blobstores =
+            with_timed_stores(write_only_blobstores, to, &key_tracer).into();
         let inflight_ops_counter = Arc::new(AtomicU64::new(0));
         Ok(Self {
             multiplex_id,
             wal_queue,
             blobstores,
             write_only_blobstores,
+            write_mostly_quorum: 0,
+            overwrite_status_policy: OverwriteStatusPolicy::default(),
             quorum,
+            read_strategy: MultiplexReadStrategy::default(),
+            wal_failure_mode: WalFailureMode::default(),
+            verify_content_hashes: false,
             scuba,
             inflight_ops_counter,
+            recent_writes: None,
+            recent_puts: None,
+            inflight_puts: None,
+            recent_sizes: None,
+            health_check: Arc::new(HealthCheck::new(HealthCheckConfig::default())),
+            background_writes: BackgroundWrites::new(),
+            key_tracer,
         })
     }
 
+    /// Read from the normal blobstores according to `read_strategy` on `get`, rather than from
+    /// all of them at once.
+    pub fn with_read_strategy(mut self, read_strategy: MultiplexReadStrategy) -> Result<Self> {
+        read_strategy.validate(&self.blobstores)?;
+        self.read_strategy = read_strategy;
+        Ok(self)
+    }
+
+    /// Decide what `put` does when the write to the WAL fails, rather than failing.
+    pub fn with_wal_failure_mode(mut self, wal_failure_mode: WalFailureMode) -> Result<Self> {
+        wal_failure_mode.validate(self.blobstores.len(), &self.quorum)?;
+        self.wal_failure_mode = wal_failure_mode;
+        Ok(self)
+    }
+
+    /// Check the blobs returned by `get` against the content hash encoded in their key, treating
+    /// a mismatch as a failure of the blobstore that returned the blob.
+    pub fn with_content_hash_verification(mut self) -> Self {
+        self.verify_content_hashes = true;
+        self
+    }
+
+    /// Retry the transient failures of the underlying blobstores before counting them
+    /// as failed towards the quorum.
+    pub fn with_retry(mut self, retry: MultiplexRetry) -> Self {
+        self.blobstores = with_retried_stores(&self.blobstores, Some(retry.clone())).into();
+        self.write_only_blobstores =
+            with_retried_stores(&self.write_only_blobstores, Some(retry)).into();
+        self
+    }
+
+    /// Require `write_mostly_quorum` writes to the write-mostly blobstores to succeed, along with
+    /// the write quorum of the normal blobstores, before a write succeeds. By default, writes to
+    /// the write-mostly blobstores complete in the background.
+    pub fn with_write_mostly_quorum(mut self, write_mostly_quorum: usize) -> Result<Self> {
+        if write_mostly_quorum > self.write_only_blobstores.len() {
+            return Err(anyhow!(
+                "Not enough write-mostly blobstores for the write-mostly quorum. Have {}, need {}",
+                self.write_only_blobstores.len(),
+                write_mostly_quorum,
+            ));
+        }
+        self.write_mostly_quorum = write_mostly_quorum;
+        Ok(self)
+    }
+
+    /// Derive the overwrite status returned by `put` according to `policy` rather than from the
+    /// blobstores that reached the write quorum.
+    pub fn with_overwrite_status_policy(mut self, policy: OverwriteStatusPolicy) -> Self {
+        self.overwrite_status_policy = policy;
+        self
+    }
+
+    /// Keep track of the keys written by this process. If the filter is configured to cover all
+    /// the writes, `is_present` on a key that was never written returns `Absent` straight away.
+    pub fn with_recent_writes(mut self, config: RecentWritesConfig) -> Result<Self> {
+        self.recent_writes = Some(Arc::new(RecentWrites::new(&config)?));
+        Ok(self)
+    }
+
+    /// Skip the puts identical to a recent one that was confirmed by every blobstore, returning
+    /// the status of the latter. Puts that were only written to the quorum are never skipped.
+    pub fn with_put_dedup(mut self, config: PutDedupConfig) -> Self {
+        self.recent_puts = Some(Arc::new(RecentPuts::new(config)));
+        self
+    }
+
+    /// Have the puts of a key and value identical to a put in progress wait for its result
+    /// instead of writing to the WAL and the blobstores again, e.g. when many requests
+    /// backfill the same blob at once. Puts of another value for the same key are not
+    /// coalesced.
+    pub fn with_put_coalescing(mut self) -> Self {
+        self.inflight_puts = Some(Arc::new(InflightPuts::default()));
+        self
+    }
+
+    /// Remember the size of the blobs put by this process, and treat a blob read with another
+    /// size as a corrupt response of the blobstore that returned it, reading from another one.
+    pub fn with_blob_size_check(mut self, config: BlobSizeCheckConfig) -> Self {
+        self.recent_sizes = Some(Arc::new(RecentSizes::new(config)));
+        self
+    }
+
+    /// Configure the probes reporting the health of the underlying blobstores.
+    pub fn with_health_check(mut self, config: HealthCheckConfig) -> Self {
+        self.health_check = Arc::new(HealthCheck::new(config));
+        self
+    }
+
+    /// Run at most `max_concurrent` of the blobstore writes that puts leave in the background at
+    /// once, across all the puts, so that a burst of puts doesn't flood the runtime. Each write
+    /// to a blobstore that wasn't started yet counts as one, and the writes a put left in
+    /// progress once it reached its quorum count as one together. The others are queued until
+    /// one completes. By default, they all run at once.
+    pub fn with_max_background_writes(mut self, max_concurrent: usize) -> Result<Self> {
+        if max_concurrent == 0 {
+            return Err(anyhow!(
+                "At least one background write must be allowed to run"
+            ));
+        }
+        self.background_writes = BackgroundWrites::with_limit(max_concurrent);
+        Ok(self)
+    }
+
+    /// Wait up to `timeout` for the writes that puts left to complete in the background, e.g.
+    /// before shutting down, so that they don't have to be healed from the WAL after a restart.
+    /// Fails if some of them are still pending after `timeout`.
+    pub async fn drain(&self, timeout: Duration) -> Result<()> {
+        if self.background_writes.drain(timeout).await {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "WAL Multiplexed Blobstore: {} background writes still pending after {:?}, {} queued",
+                self.background_writes.pending(),
+                timeout,
+                self.background_writes.queued()
+            ))
+        }
+    }
+
+    /// Like `get`, but checks whether `key` is present first, for the callers that often look
+    /// for missing keys: when the read quorum confirms the key is absent, `None` is returned
+    /// without reading the blob. An inconclusive presence check falls back to `get`.
+    pub async fn get_if_present(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        match self.is_present(ctx, key).await? {
+            BlobstoreIsPresent::Absent => Ok(None),
+            BlobstoreIsPresent::Present | BlobstoreIsPresent::ProbablyNotPresent(_) => {
+                self.get(ctx, key).await
+            }
+        }
+    }
+
+    /// Put `value` to every normal blobstore, as well as to the write-mostly ones if
+    /// `include_write_mostly`, waiting up to `deadline` for all of them to confirm the write
+    /// instead of only the write quorum. Returns the blobstores that didn't confirm it in time,
+    /// because they failed or were too slow. Their writes carry on in the background, and the WAL
+    /// entry is kept for the healer to complete them.
+    ///
+    /// This is as slow as the slowest blobstore, up to `deadline`, so it's meant for the few
+    /// writes that need more durability than the quorum. The WAL must be written to, whatever the
+    /// `WalFailureMode`.
+    pub async fn put_all_with_deadline(
+        &self,
+        ctx: &CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        deadline: Duration,
+        include_write_mostly: bool,
+    ) -> Result<HashSet<BlobstoreId>> {
+        let deadline = tokio::time::Instant::now() + deadline;
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::BlobPuts);
+
+        let blob_size = value.len() as u64;
+        if let Some(recent_writes) = &self.recent_writes {
+            recent_writes.insert(&key);
+        }
+        if let Some(recent_sizes) = &self.recent_sizes {
+            recent_sizes.insert(&key, blob_size);
+        }
+
+        let log_entry =
+            BlobstoreWalEntry::new(key.clone(), self.multiplex_id, Timestamp::now(), blob_size)
+                .with_priority(BlobstoreWalPriority::from_ctx(ctx));
+        let entry = self.wal_queue.log(ctx, log_entry).await.with_context(|| {
+            format!(
+                "WAL Multiplexed Blobstore: Failed writing to the WAL: key {}",
+                key
+            )
+        })?;
+
+        let waited_write_only: &[TimedStore] = if include_write_mostly {
+            &self.write_only_blobstores
+        } else {
+            &[]
+        };
+        let waited_stores = || self.blobstores.iter().chain(waited_write_only);
+        let mut unconfirmed: HashSet<_> = waited_stores().map(|bs| *bs.id()).collect();
+        let mut writes: FuturesUnordered<_> = waited_stores()
+            .map(|bs| {
+                let key = key.clone();
+                cloned!(
+                    bs,
+                    ctx,
+                    value,
+                    self.scuba.inner_blobstores_scuba,
+                    self.inflight_ops_counter
+                );
+                async move {
+                    inflight_ops_counter.fetch_add(1, Ordering::Relaxed);
+                    let result = bs.put(&ctx, key, value, None, inner_blobstores_scuba).await;
+                    inflight_ops_counter.fetch_sub(1, Ordering::Relaxed);
+                    (*bs.id(), result)
+                }
+            })
+            .collect();
+        // The write-mostly blobstores that aren't waited for are written to in the background,
+        // as they would be by `put`.
+        let write_only_writes = (!include_write_mostly).then(|| {
+            spawn_writes(
+                &self.background_writes,
+                inner_multi_put(
+                    &background_ctx(ctx),
+                    self.write_only_blobstores.clone(),
+                    &key,
+                    &value,
+                    None,
+                    &self.scuba,
+                    self.inflight_ops_counter.clone(),
+                ),
+            )
+        });
+
+        while !writes.is_empty() {
+            match tokio::time::timeout_at(deadline, writes.next()).await {
+                Ok(Some((bs_id, Ok(_status)))) => {
+                    unconfirmed.remove(&bs_id);
+                }
+                // The failure was logged to scuba by the blobstore.
+                Ok(Some((_bs_id, Err(_err)))) => {}
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        if unconfirmed.is_empty() {
+            // Every blobstore has the blob, unless some of the write-mostly ones that weren't
+            // waited for fail.
+            cloned!(ctx, self.wal_queue);
+            self.background_writes.spawn(async move {
+                if let Some(write_only_writes) = write_only_writes {
+                    write_only_writes.await??;
+                }
+                wal_queue.delete_by_key(&ctx, &[entry]).await?;
+                anyhow::Ok(())
+            });
+        } else if !writes.is_empty() {
+            spawn_stream_completion(
+                &self.background_writes,
+                writes.map(|(_bs_id, result)| result.map(|_status| ()).map_err(|(_id, err)| err)),
+            );
+        }
+
+        ctx.perf_counters()
+            .add_to_counter(PerfCounterType::BlobPutsTotalSize, blob_size as i64);
+        Ok(unconfirmed)
+    }
+
     async fn put_impl<'a>(
         &'a self,
         ctx: &'a CoreContext,
@@ -228,16 +696,68 @@ impl WalMultiplexedBlobstore {
         ctx.perf_counters()
             .increment_counter(PerfCounterType::BlobPuts);
 
+        if let Some(recent_puts) = &self.recent_puts {
+            if let Some(status) = recent_puts.get(&key, &value, put_behaviour) {
+                return Ok(status);
+            }
+        }
+
+        let leader = match self
+            .inflight_puts
+            .as_ref()
+            .map(|inflight_puts| inflight_puts.join(&key, &value, put_behaviour))
+        {
+            Some(CoalescedPut::Leader(leader)) => Some(leader),
+            Some(CoalescedPut::Follower(follower)) => {
+                if let Some(result) = follower.wait().await {
+                    ctx.perf_counters()
+                        .increment_counter(PerfCounterType::BlobPutsDeduplicated);
+                    return result;
+                }
+                // The put waited for was cancelled, write the blob instead.
+                None
+            }
+            Some(CoalescedPut::Uncoalesced) | None => None,
+        };
+
+        let result = self.write_impl(ctx, key, value, put_behaviour, scuba).await;
+        if let Some(leader) = leader {
+            leader.complete(&result);
+        }
+        result
+    }
+
+    async fn write_impl<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: Option<PutBehaviour>,
+        scuba: &Scuba,
+    ) -> Result<OverwriteStatus> {
         let blob_size = value.len() as u64;
 
+        // Record the key before any blobstore can have it, so that a concurrent `is_present`
+        // never reports it as absent once it's been written.
+        if let Some(recent_writes) = &self.recent_writes {
+            recent_writes.insert(&key);
+        }
+        if let Some(recent_sizes) = &self.recent_sizes {
+            recent_sizes.insert(&key, blob_size);
+        }
+
         // Log the blobstore key and wait till it succeeds
         let ts = Timestamp::now();
-        let log_entry = BlobstoreWalEntry::new(key.clone(), self.multiplex_id, ts, blob_size);
+        let priority = BlobstoreWalPriority::from_ctx(ctx);
+        let log_entry = BlobstoreWalEntry::new(key.clone(), self.multiplex_id, ts, blob_size)
+            .with_priority(priority);
         let (stats, result) = self.wal_queue.log(ctx, log_entry).timed().await;
 
+        let mut queue_scuba = scuba.multiplex_scuba.clone();
+        queue_scuba.add("priority", priority.as_str());
         scuba::record_queue_stats(
             ctx,
-            &mut scuba.multiplex_scuba.clone(),
+            &mut queue_scuba,
             &key,
             stats,
             None,
@@ -245,15 +765,24 @@ impl WalMultiplexedBlobstore {
             result.as_ref().map(|_| &()),
         );
 
-        let entry = result.with_context(|| {
-            format!(
-                "WAL Multiplexed Blobstore: Failed writing to the WAL: key {}",
-                key
-            )
-        })?;
+        let entry = match (result, &self.wal_failure_mode) {
+            (Ok(entry), _) => Some(entry),
+            (Err(err), WalFailureMode::Fail) => {
+                return Err(err.context(format!(
+                    "WAL Multiplexed Blobstore: Failed writing to the WAL: key {}",
+                    key
+                )));
+            }
+            (Err(_), WalFailureMode::WriteWithoutWal { .. }) => {
+                // The failure was logged to scuba along with the queue stats above.
+                ctx.perf_counters()
+                    .increment_counter(PerfCounterType::BlobPutsWalFailures);
+                None
+            }
+        };
 
         // Prepare underlying main blobstores puts
-        let mut put_futs = inner_multi_put(
+        let put_futs = inner_multi_put(
             ctx,
             self.blobstores.clone(),
             &key,
@@ -262,60 +791,51 @@ impl WalMultiplexedBlobstore {
             scuba,
             self.inflight_ops_counter.clone(),
         );
+        let write_only_put_futs = |ctx: &CoreContext| {
+            inner_multi_put(
+                ctx,
+                self.write_only_blobstores.clone(),
+                &key,
+                &value,
+                put_behaviour,
+                scuba,
+                self.inflight_ops_counter.clone(),
+            )
+        };
 
-        // Wait for the quorum successful writes
-        let mut quorum: usize = self.quorum.write.get();
-        let mut put_errors = HashMap::new();
-        let (stats, result) = async move {
-            while let Some(result) = put_futs.next().await {
-                match result {
-                    Ok(_overwrite_status) => {
-                        quorum = quorum.saturating_sub(1);
-                        if quorum == 0 {
-                            // Quorum blobstore writes succeeded, we can spawn the rest
-                            // of the writes and not wait for them.
-                            let main_puts =
-                                spawn_stream_completion(put_futs.map_err(|(_id, err)| err));
-
-                            // Spawn the write-only blobstore writes, we don't want to wait for them
-                            let write_only_puts = inner_multi_put(
-                                ctx,
-                                self.write_only_blobstores.clone(),
-                                &key,
-                                &value,
-                                put_behaviour,
-                                scuba,
-                                self.inflight_ops_counter.clone(),
-                            );
-                            let write_only_puts =
-                                spawn_stream_completion(write_only_puts.map_err(|(_id, err)| err));
-
-                            cloned!(ctx, self.wal_queue);
-                            if put_errors.is_empty() {
-                                // Optimisation: It put fully succeeded on all blobstores, we can remove
-                                // it from queue and healer doesn't need to deal with it.
-                                tokio::spawn(async move {
-                                    let (r1, r2) = futures::join!(main_puts, write_only_puts);
-                                    r1??;
-                                    r2??;
-                                    // TODO(yancouto): Batch deletes together.
-                                    wal_queue.delete_by_key(&ctx, &[entry]).await?;
-                                    anyhow::Ok(())
-                                });
+        let (stats, result) = match (entry, &self.wal_failure_mode) {
+            (Some(entry), _) => {
+                self.wait_for_write_quorum(
+                    ctx,
+                    entry,
+                    put_futs,
+                    write_only_put_futs,
+                    &self.overwrite_status_policy,
+                    {
+                        cloned!(key, value, self.recent_puts);
+                        move |_status| {
+                            if let Some(recent_puts) = recent_puts {
+                                recent_puts.insert(key, value, put_behaviour);
                             }
-
-                            return Ok(OverwriteStatus::NotChecked);
                         }
-                    }
-                    Err((bs_id, err)) => {
-                        put_errors.insert(bs_id, err);
-                    }
-                }
+                    },
+                )
+                .timed()
+                .await
             }
-            Err(put_errors)
-        }
-        .timed()
-        .await;
+            (None, WalFailureMode::WriteWithoutWal { write_quorum }) => {
+                wait_for_all_writes(
+                    put_futs,
+                    write_only_put_futs(ctx),
+                    *write_quorum,
+                    self.write_mostly_quorum,
+                    &self.overwrite_status_policy,
+                )
+                .timed()
+                .await
+            }
+            (None, WalFailureMode::Fail) => unreachable!("put without the WAL is not allowed"),
+        };
 
         ctx.perf_counters().set_max_counter(
             PerfCounterType::BlobPutsMaxLatency,
@@ -324,17 +844,195 @@ impl WalMultiplexedBlobstore {
         ctx.perf_counters()
             .add_to_counter(PerfCounterType::BlobPutsTotalSize, blob_size as i64);
 
-        result.map_err(|put_errors| {
-            let errors = Arc::new(put_errors);
-            let result_err = if errors.len() == self.blobstores.len() {
-                // all main writes failed
-                ErrorKind::AllFailed(errors)
-            } else {
-                // some main writes failed
-                ErrorKind::SomePutsFailed(errors)
-            };
-            result_err.into()
-        })
+        result.map_err(|put_errors| self.write_error(put_errors))
+    }
+
+    async fn copy_impl<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        old_key: &'a str,
+        new_key: String,
+    ) -> Result<()> {
+        if let Some(recent_sizes) = &self.recent_sizes {
+            match recent_sizes.get(old_key) {
+                Some(size) => recent_sizes.insert(&new_key, size),
+                None => recent_sizes.remove(&new_key),
+            }
+        }
+
+        // The size of the blob isn't known without fetching it, which copying avoids.
+        let log_entry =
+            BlobstoreWalEntry::new(new_key.clone(), self.multiplex_id, Timestamp::now(), 0)
+                .with_priority(BlobstoreWalPriority::from_ctx(ctx));
+        let entry = self.wal_queue.log(ctx, log_entry).await.with_context(|| {
+            format!(
+                "WAL Multiplexed Blobstore: Failed writing to the WAL: key {}",
+                new_key
+            )
+        })?;
+
+        let copy_futs = inner_multi_copy(
+            ctx,
+            self.blobstores.clone(),
+            old_key,
+            &new_key,
+            self.inflight_ops_counter.clone(),
+        );
+
+        self.wait_for_write_quorum(
+            ctx,
+            entry,
+            copy_futs,
+            |ctx| {
+                inner_multi_copy(
+                    ctx,
+                    self.write_only_blobstores.clone(),
+                    old_key,
+                    &new_key,
+                    self.inflight_ops_counter.clone(),
+                )
+            },
+            // Copies don't report whether the key was present
+            &OverwriteStatusPolicy::Quorum,
+            |_status| {},
+        )
+        .await
+        .map(|_| ())
+        .map_err(|copy_errors| self.write_error(copy_errors))
+    }
+
+    /// Wait for the write quorum of the main blobstore writes to succeed, as well as the
+    /// write-mostly quorum of the write-only blobstore writes. The rest of the writes then
+    /// complete in the background. If every write succeeds, the WAL entry is removed as there is nothing to heal,
+    /// and `on_all_written` is called with the status returned to the caller.
+    ///
+    /// The write-only blobstore writes that aren't waited for are started along with the main
+    /// ones for interactive puts, so that the write-mostly blobstores get the blob as soon as
+    /// possible, and once the quorum is reached for background ones, so that they don't compete
+    /// with the writes someone waits for. Either way, they are written to as background work.
+    async fn wait_for_write_quorum<F, W>(
+        &self,
+        ctx: &CoreContext,
+        entry: BlobstoreWalEntry,
+        write_futs: FuturesUnordered<F>,
+        write_only_futs: impl FnOnce(&CoreContext) -> FuturesUnordered<W>,
+        overwrite_status_policy: &OverwriteStatusPolicy,
+        on_all_written: impl FnOnce(OverwriteStatus) + Send + 'static,
+    ) -> Result<OverwriteStatus, BlobstoresReturnedError>
+    where
+        F: Future<Output = Result<OverwriteStatus, (BlobstoreId, Error)>> + Send + 'static,
+        W: Future<Output = Result<OverwriteStatus, (BlobstoreId, Error)>> + Send + Unpin + 'static,
+    {
+        let mut quorum: usize = self.quorum.write.get();
+        let mut write_mostly_quorum = self.write_mostly_quorum;
+        let mut write_errors = HashMap::new();
+        let mut overwrite_statuses = Vec::new();
+
+        // The write-only blobstore writes are only waited for if some of them must succeed.
+        let mut write_only_futs = Some(write_only_futs);
+        let waited_write_only_futs = match write_mostly_quorum {
+            0 => FuturesUnordered::new(),
+            _ => write_only_futs
+                .take()
+                .map_or_else(FuturesUnordered::new, |futs| futs(ctx)),
+        };
+        let mut write_only_writes = match entry.priority {
+            BlobstoreWalPriority::Interactive => write_only_futs
+                .take()
+                .map(|futs| spawn_writes(&self.background_writes, futs(&background_ctx(ctx)))),
+            BlobstoreWalPriority::Background => None,
+        };
+        let mut writes = stream::select(
+            write_futs.map(|result| (false, result)),
+            waited_write_only_futs.map(|result| (true, result)),
+        );
+
+        while let Some((is_write_only, result)) = writes.next().await {
+            match result {
+                Ok(status) => {
+                    if is_write_only {
+                        write_mostly_quorum = write_mostly_quorum.saturating_sub(1);
+                    } else {
+                        overwrite_statuses.push(status);
+                        quorum = quorum.saturating_sub(1);
+                    }
+                    if quorum == 0 && write_mostly_quorum == 0 {
+                        if let OverwriteStatusPolicy::Majority { timeout } = overwrite_status_policy
+                        {
+                            // None of the blobstores checked whether the key was present,
+                            // give the remaining writes some time to do so.
+                            let deadline = tokio::time::Instant::now() + *timeout;
+                            while !overwrite_status_policy.has_verdict(&overwrite_statuses) {
+                                match tokio::time::timeout_at(deadline, writes.next()).await {
+                                    Ok(Some((false, Ok(status)))) => {
+                                        overwrite_statuses.push(status)
+                                    }
+                                    Ok(Some((true, Ok(_status)))) => {}
+                                    Ok(Some((_is_write_only, Err((bs_id, err))))) => {
+                                        write_errors.insert(bs_id, err);
+                                    }
+                                    Ok(None) | Err(_) => break,
+                                }
+                            }
+                        }
+
+                        // Quorum blobstore writes succeeded, we can spawn the rest
+                        // of the writes and not wait for them.
+                        let main_writes = spawn_stream_completion(
+                            &self.background_writes,
+                            writes.map(|(_is_write_only, result)| result.map_err(|(_id, err)| err)),
+                        );
+
+                        // Spawn the write-only blobstore writes that weren't started yet,
+                        // we don't want to wait for them
+                        let write_only_writes = write_only_writes.take().unwrap_or_else(|| {
+                            spawn_writes(
+                                &self.background_writes,
+                                write_only_futs
+                                    .take()
+                                    .map_or_else(FuturesUnordered::new, |futs| {
+                                        futs(&background_ctx(ctx))
+                                    }),
+                            )
+                        });
+
+                        let status = overwrite_status_policy.verdict(&overwrite_statuses);
+                        cloned!(ctx, self.wal_queue);
+                        if write_errors.is_empty() {
+                            // Optimisation: It put fully succeeded on all blobstores, we can remove
+                            // it from queue and healer doesn't need to deal with it.
+                            self.background_writes.spawn(async move {
+                                let (r1, r2) = futures::join!(main_writes, write_only_writes);
+                                r1??;
+                                r2??;
+                                // TODO(yancouto): Batch deletes together.
+                                wal_queue.delete_by_key(&ctx, &[entry]).await?;
+                                on_all_written(status);
+                                anyhow::Ok(())
+                            });
+                        }
+
+                        return Ok(status);
+                    }
+                }
+                Err((bs_id, err)) => {
+                    write_errors.insert(bs_id, err);
+                }
+            }
+        }
+        Err(write_errors)
+    }
+
+    fn write_error(&self, write_errors: BlobstoresReturnedError) -> Error {
+        let errors = Arc::new(write_errors);
+        let result_err = if errors.len() == self.blobstores.len() {
+            // all main writes failed
+            ErrorKind::AllFailed(errors)
+        } else {
+            // some main writes failed
+            ErrorKind::SomePutsFailed(errors)
+        };
+        result_err.into()
     }
 
     async fn get_impl<'a>(
@@ -346,26 +1044,84 @@ impl WalMultiplexedBlobstore {
         ctx.perf_counters()
             .increment_counter(PerfCounterType::BlobGets);
 
+        let (blobstores, hedge) = self.read_strategy.split(&self.blobstores);
         let mut get_futs = inner_multi_get(
             ctx,
-            self.blobstores.clone(),
+            blobstores,
             key,
             OperationType::Get,
             scuba,
             self.inflight_ops_counter.clone(),
         );
+        let mut hedge = hedge.map(|(rest, delay)| (rest, tokio::time::Instant::now() + delay));
 
         // Wait for the quorum successful "Not Found" reads before
         // returning Ok(None).
         let mut quorum: usize = self.quorum.read.get();
-        let mut get_errors = HashMap::with_capacity(get_futs.len());
+        let mut get_errors = HashMap::with_capacity(self.blobstores.len());
+        let mut absent = 0;
         let (stats, result) = async move {
-            while let Some((bs_id, result)) = get_futs.next().await {
+            loop {
+                let next = match &hedge {
+                    Some((_rest, deadline)) if !get_futs.is_empty() => {
+                        tokio::select! {
+                            next = get_futs.next() => next,
+                            _ = tokio::time::sleep_until(*deadline) => None,
+                        }
+                    }
+                    _ => get_futs.next().await,
+                };
+                let (bs_id, result) = match next {
+                    Some(next) => next,
+                    None => match hedge.take() {
+                        Some((rest, _deadline)) => {
+                            // The preferred blobstores couldn't answer in time,
+                            // read from the rest as well.
+                            get_futs.extend(inner_multi_get(
+                                ctx,
+                                rest,
+                                key,
+                                OperationType::Get,
+                                scuba,
+                                self.inflight_ops_counter.clone(),
+                            ));
+                            continue;
+                        }
+                        None => break,
+                    },
+                };
                 match result {
                     Ok(Some(get_data)) => {
+                        if self.verify_content_hashes {
+                            if let Err(err) = verify_content_hash(key, &get_data) {
+                                scuba
+                                    .multiplex_scuba
+                                    .clone()
+                                    .unsampled()
+                                    .add(KEY, key)
+                                    .add(BLOBSTORE_ID, bs_id)
+                                    .log_with_msg("Corrupt blob", format!("{:#}", err));
+                                get_errors.insert(bs_id, err);
+                                continue;
+                            }
+                        }
+                        if let Some(recent_sizes) = &self.recent_sizes {
+                            if let Err(err) = recent_sizes.verify(key, get_data.len() as u64) {
+                                scuba
+                                    .multiplex_scuba
+                                    .clone()
+                                    .unsampled()
+                                    .add(KEY, key)
+                                    .add(BLOBSTORE_ID, bs_id)
+                                    .log_with_msg("Blob size mismatch", format!("{:#}", err));
+                                get_errors.insert(bs_id, err);
+                                continue;
+                            }
+                        }
                         return Ok(Some(get_data));
                     }
                     Ok(None) => {
+                        absent += 1;
                         quorum = quorum.saturating_sub(1);
                         if quorum == 0 {
                             // quorum blobstores couldn't find the given key in the blobstores
@@ -378,7 +1134,10 @@ impl WalMultiplexedBlobstore {
                     }
                 }
             }
-            Err(get_errors)
+            Err(GetFailures {
+                errors: get_errors,
+                absent,
+            })
         }
         .timed()
         .await;
@@ -388,14 +1147,13 @@ impl WalMultiplexedBlobstore {
             stats.completion_time.as_millis_unchecked() as i64,
         );
 
-        let result = result.map_err(|get_errors| {
-            let errors = Arc::new(get_errors);
-            let result_err = if errors.len() == self.blobstores.len() {
+        let result = result.map_err(|failures| {
+            let result_err = if failures.errors.len() == self.blobstores.len() {
                 // all main reads failed
-                ErrorKind::AllFailed(errors)
+                ErrorKind::AllFailed(Arc::new(failures.errors))
             } else {
                 // some main reads failed
-                ErrorKind::SomeGetsFailed(errors)
+                ErrorKind::SomeGetsFailed(Arc::new(failures))
             };
             result_err.into()
         });
@@ -423,6 +1181,12 @@ impl WalMultiplexedBlobstore {
         ctx.perf_counters()
             .increment_counter(PerfCounterType::BlobPresenceChecks);
 
+        if let Some(recent_writes) = &self.recent_writes {
+            if recent_writes.is_known_absent(key) {
+                return Ok(BlobstoreIsPresent::Absent);
+            }
+        }
+
         let mut futs = inner_multi_is_present(
             ctx,
             self.blobstores.clone(),
@@ -541,6 +1305,21 @@ impl Blobstore for WalMultiplexedBlobstore {
         BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
         Ok(())
     }
+
+    /// Copy the blob on each of the underlying blobstores, which avoids transferring it when
+    /// they support copying natively and falls back to their own `get` and `put` otherwise.
+    /// The copy is logged in the WAL and succeeds on the write quorum, like a `put`.
+    async fn copy<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        old_key: &'a str,
+        new_key: String,
+    ) -> Result<()> {
+        if let Some(recent_writes) = &self.recent_writes {
+            recent_writes.insert(&new_key);
+        }
+        self.copy_impl(ctx, old_key, new_key).await
+    }
 }
 
 #[async_trait]
@@ -593,10 +1372,133 @@ impl BlobstorePutOps for WalMultiplexedBlobstore {
     }
 }
 
+/// Combine the overwrite status reported by one more underlying blobstore into the status
+/// accumulated so far, so the multiplexed put reports a single coherent result.
+///
+/// If any store reports the key was already present, the combined status says so as well:
+/// `Prevented` wins over everything (with `PutBehaviour::IfAbsent` at least one store already
+/// had the key), then `Overwrote`. `New` is only reported when every store agreed the key was
+/// new, any store that didn't check makes the whole result `NotChecked`.
+///
+/// Only the statuses of the stores that completed before the write quorum was reached are
+/// taken into account, the rest of the writes complete in the background. This means that
+/// while stores are being healed (the key was written to some stores but not yet to all of
+/// them), two `IfAbsent` puts of the same key can observe different results: one can see
+/// `New` from the stores missing the key, while another one gets `Prevented` from the stores
+/// that already have it. The blob is the same in both cases, as keys are content addressed.
+pub(crate) fn aggregate_overwrite_status(
+    acc: Option<OverwriteStatus>,
+    status: OverwriteStatus,
+) -> OverwriteStatus {
+    match (acc, status) {
+        (None, status) => status,
+        (Some(OverwriteStatus::Prevented), _) | (_, OverwriteStatus::Prevented) => {
+            OverwriteStatus::Prevented
+        }
+        (Some(OverwriteStatus::Overwrote), _) | (_, OverwriteStatus::Overwrote) => {
+            OverwriteStatus::Overwrote
+        }
+        (Some(OverwriteStatus::New), OverwriteStatus::New) => OverwriteStatus::New,
+        _ => OverwriteStatus::NotChecked,
+    }
+}
+
+/// The context of the writes that no put waits for, so that the blobstores that support QoS
+/// schedule them as background work, whatever the session class of the put.
+fn background_ctx(ctx: &CoreContext) -> CoreContext {
+    let mut ctx = ctx.clone();
+    ctx.session_mut()
+        .override_session_class(SessionClass::Background);
+    ctx
+}
+
+/// Wait for all the writes to complete, as there is no WAL entry to heal the blobstores that
+/// missed them. Succeeds if at least `write_quorum` of the main blobstore writes did.
+async fn wait_for_all_writes<F, W>(
+    write_futs: FuturesUnordered<F>,
+    write_only_futs: FuturesUnordered<W>,
+    write_quorum: usize,
+    write_mostly_quorum: usize,
+    overwrite_status_policy: &OverwriteStatusPolicy,
+) -> Result<OverwriteStatus, BlobstoresReturnedError>
+where
+    F: Future<Output = Result<OverwriteStatus, (BlobstoreId, Error)>>,
+    W: Future<Output = Result<OverwriteStatus, (BlobstoreId, Error)>>,
+{
+    let (results, write_only_results): (Vec<_>, Vec<_>) =
+        future::join(write_futs.collect(), write_only_futs.collect()).await;
+
+    let mut successes = 0;
+    let mut write_errors = HashMap::new();
+    let mut overwrite_statuses = Vec::new();
+    for result in results {
+        match result {
+            Ok(status) => {
+                overwrite_statuses.push(status);
+                successes += 1;
+            }
+            Err((bs_id, err)) => {
+                write_errors.insert(bs_id, err);
+            }
+        }
+    }
+
+    // Failed write-only writes only matter when some of them must succeed, and are logged to
+    // scuba already.
+    let mut write_only_successes = 0;
+    let mut write_only_errors = HashMap::new();
+    for result in write_only_results {
+        match result {
+            Ok(_status) => write_only_successes += 1,
+            Err((bs_id, err)) => {
+                write_only_errors.insert(bs_id, err);
+            }
+        }
+    }
+    if write_only_successes < write_mostly_quorum {
+        write_errors.extend(write_only_errors);
+        return Err(write_errors);
+    }
+
+    if successes >= write_quorum {
+        Ok(overwrite_status_policy.verdict(&overwrite_statuses))
+    } else {
+        Err(write_errors)
+    }
+}
+
+/// Complete in the background the writes of `s`, which are already in progress. They take a
+/// single slot of the background writes, and aren't polled until they get it.
 fn spawn_stream_completion<T>(
+    background_writes: &BackgroundWrites,
     s: impl Stream<Item = Result<T>> + Send + 'static,
-) -> JoinHandle<Result<()>> {
-    tokio::spawn(s.try_for_each(|_| future::ok(())))
+) -> oneshot::Receiver<Result<()>> {
+    background_writes.spawn_write(s.try_for_each(|_| future::ok(())))
+}
+
+/// Start `writes` in the background, each one once a slot of the background writes is free.
+/// The returned task completes once they all have, failing if any of them did.
+fn spawn_writes<W, T>(
+    background_writes: &BackgroundWrites,
+    writes: FuturesUnordered<W>,
+) -> JoinHandle<Result<()>>
+where
+    W: Future<Output = Result<T, (BlobstoreId, Error)>> + Send + Unpin + 'static,
+    T: Send + 'static,
+{
+    let writes: Vec<_> = writes
+        .into_iter()
+        .map(|write| background_writes.spawn_write(write))
+        .collect();
+    background_writes.spawn(async move {
+        let mut first_err = None;
+        for write in writes {
+            if let Err((_id, err)) = write.await? {
+                first_err.get_or_insert(err);
+            }
+        }
+        first_err.map_or(Ok(()), Err)
+    })
 }
 
 fn inner_multi_put(
@@ -607,7 +1509,7 @@ fn inner_multi_put(
     put_behaviour: Option<PutBehaviour>,
     scuba: &Scuba,
     counter: Arc<AtomicU64>,
-) -> FuturesUnordered<impl Future<Output = Result<OverwriteStatus, (BlobstoreId, Error)>>> {
+) -> FuturesUnordered<BoxFuture<'static, Result<OverwriteStatus, (BlobstoreId, Error)>>> {
     let put_futs: FuturesUnordered<_> = blobstores
         .iter()
         .map(|bs| {
@@ -630,11 +1532,36 @@ fn inner_multi_put(
                 counter.fetch_sub(1, Ordering::Relaxed);
                 result
             }
+            .boxed()
         })
         .collect();
     put_futs
 }
 
+fn inner_multi_copy(
+    ctx: &CoreContext,
+    blobstores: Arc<[TimedStore]>,
+    old_key: &str,
+    new_key: &str,
+    counter: Arc<AtomicU64>,
+) -> FuturesUnordered<BoxFuture<'static, Result<OverwriteStatus, (BlobstoreId, Error)>>> {
+    blobstores
+        .iter()
+        .map(|bs| {
+            let old_key = old_key.to_string();
+            let new_key = new_key.to_string();
+            cloned!(bs, ctx, counter);
+            async move {
+                counter.fetch_add(1, Ordering::Relaxed);
+                let result = bs.copy(&ctx, &old_key, new_key).await;
+                counter.fetch_sub(1, Ordering::Relaxed);
+                result
+            }
+            .boxed()
+        })
+        .collect()
+}
+
 pub(crate) type GetResult = (BlobstoreId, Result<Option<BlobstoreGetData>, Error>);
 
 pub(crate) fn inner_multi_get<'a>(
@@ -0,0 +1,94 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use blobstore::OverwriteStatus;
+use blobstore::PutBehaviour;
+use mononoke_types::BlobstoreBytes;
+
+/// Configuration of the deduplication of the puts repeated shortly after each other.
+#[derive(Clone, Debug)]
+pub struct PutDedupConfig {
+    /// How long a completed put can be used to skip an identical one.
+    pub ttl: Duration,
+    /// Maximum number of puts remembered at once.
+    pub capacity: usize,
+}
+
+struct RecentPut {
+    value: BlobstoreBytes,
+    put_behaviour: Option<PutBehaviour>,
+    written_at: Instant,
+}
+
+/// The puts that were confirmed by every blobstore, normal and write-only, in the last `ttl`.
+pub(crate) struct RecentPuts {
+    config: PutDedupConfig,
+    puts: Mutex<HashMap<String, RecentPut>>,
+}
+
+impl RecentPuts {
+    pub(crate) fn new(config: PutDedupConfig) -> Self {
+        Self {
+            config,
+            puts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The status of a put of exactly the same value with the same behaviour as a recent one, if
+    /// any. The recent put wrote the key, so the repeated one finds it there.
+    pub(crate) fn get(
+        &self,
+        key: &str,
+        value: &BlobstoreBytes,
+        put_behaviour: Option<PutBehaviour>,
+    ) -> Option<OverwriteStatus> {
+        let puts = self.puts.lock().expect("lock poisoned");
+        let put = puts.get(key)?;
+        if put.written_at.elapsed() < self.config.ttl
+            && put.put_behaviour == put_behaviour
+            && &put.value == value
+        {
+            Some(match put_behaviour {
+                Some(PutBehaviour::IfAbsent) => OverwriteStatus::Prevented,
+                Some(PutBehaviour::OverwriteAndLog) => OverwriteStatus::Overwrote,
+                Some(PutBehaviour::Overwrite) | None => OverwriteStatus::NotChecked,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn insert(
+        &self,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: Option<PutBehaviour>,
+    ) {
+        let mut puts = self.puts.lock().expect("lock poisoned");
+        if puts.len() >= self.config.capacity && !puts.contains_key(&key) {
+            let ttl = self.config.ttl;
+            puts.retain(|_key, put| put.written_at.elapsed() < ttl);
+            if puts.len() >= self.config.capacity {
+                // Not remembering a put only means it won't be deduplicated.
+                return;
+            }
+        }
+        puts.insert(
+            key,
+            RecentPut {
+                value,
+                put_behaviour,
+                written_at: Instant::now(),
+            },
+        );
+    }
+}
@@ -0,0 +1,82 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// Configuration of the check of the blobs read against the size they were written with.
+#[derive(Clone, Debug)]
+pub struct BlobSizeCheckConfig {
+    /// Maximum number of blob sizes remembered at once, the oldest ones are forgotten first.
+    pub capacity: usize,
+}
+
+#[derive(Default)]
+struct Sizes {
+    sizes: HashMap<String, u64>,
+    /// The keys in the order they were first recorded in, for eviction.
+    order: VecDeque<String>,
+}
+
+/// The sizes of the blobs put by this process, as recorded in their WAL entries, used to detect
+/// the blobstores returning truncated or otherwise damaged blobs.
+pub(crate) struct RecentSizes {
+    config: BlobSizeCheckConfig,
+    sizes: Mutex<Sizes>,
+}
+
+impl RecentSizes {
+    pub(crate) fn new(config: BlobSizeCheckConfig) -> Self {
+        Self {
+            config,
+            sizes: Mutex::new(Sizes::default()),
+        }
+    }
+
+    pub(crate) fn insert(&self, key: &str, size: u64) {
+        let mut sizes = self.sizes.lock().expect("lock poisoned");
+        if sizes.sizes.insert(key.to_owned(), size).is_none() {
+            sizes.order.push_back(key.to_owned());
+            while sizes.order.len() > self.config.capacity {
+                if let Some(oldest) = sizes.order.pop_front() {
+                    sizes.sizes.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    /// Forget the size of `key`, e.g. because it's about to be written with an unknown size.
+    pub(crate) fn remove(&self, key: &str) {
+        let mut sizes = self.sizes.lock().expect("lock poisoned");
+        if sizes.sizes.remove(key).is_some() {
+            sizes.order.retain(|k| k != key);
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<u64> {
+        let sizes = self.sizes.lock().expect("lock poisoned");
+        sizes.sizes.get(key).copied()
+    }
+
+    /// Check that a blob read for `key` has the size it was written with, if known.
+    pub(crate) fn verify(&self, key: &str, size: u64) -> Result<()> {
+        match self.get(key) {
+            Some(expected) if expected != size => Err(anyhow!(
+                "Blob {} has size {} instead of {} (delta {})",
+                key,
+                size,
+                expected,
+                size as i64 - expected as i64
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+use anyhow::anyhow;
+use anyhow::Result;
+
+/// Configuration of the filter of the keys written by this process.
+#[derive(Clone, Debug)]
+pub struct RecentWritesConfig {
+    /// Number of keys the filter is sized for. Writing more keys than that is safe, but
+    /// increases the false positive rate.
+    pub capacity: usize,
+    /// Probability for a key that was never written to be reported as maybe written.
+    pub false_positive_rate: f64,
+    /// Whether every key in the underlying blobstores was written by this process, for
+    /// instance because they were empty when it started. Only then can a key missing from the
+    /// filter be reported as absent without asking the blobstores.
+    pub covers_all_writes: bool,
+}
+
+/// Bloom filter of the keys written through the multiplexed blobstore since startup.
+///
+/// Keys are never removed, so the filter has no false negatives: a key it doesn't contain was
+/// definitely not written by this process.
+pub(crate) struct RecentWrites {
+    bits: Box<[AtomicU64]>,
+    num_bits: u64,
+    num_hashes: u32,
+    covers_all_writes: bool,
+}
+
+impl RecentWrites {
+    pub(crate) fn new(config: &RecentWritesConfig) -> Result<Self> {
+        let rate = config.false_positive_rate;
+        if rate == 0.0 || !(0.0..1.0).contains(&rate) {
+            return Err(anyhow!(
+                "Recent writes filter false positive rate must be in (0, 1), got {}",
+                rate
+            ));
+        }
+
+        // Optimal bloom filter parameters for the given capacity and false positive rate.
+        let capacity = config.capacity.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-capacity * rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / capacity) * ln2).round().max(1.0) as u32;
+
+        let words = num_bits.div_ceil(64) as usize;
+        Ok(Self {
+            bits: (0..words).map(|_| AtomicU64::new(0)).collect(),
+            num_bits: words as u64 * 64,
+            num_hashes,
+            covers_all_writes: config.covers_all_writes,
+        })
+    }
+
+    pub(crate) fn insert(&self, key: &str) {
+        for bit in self.bit_indices(key) {
+            self.bits[(bit / 64) as usize].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn may_contain(&self, key: &str) -> bool {
+        self.bit_indices(key).all(|bit| {
+            self.bits[(bit / 64) as usize].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0
+        })
+    }
+
+    /// Whether the key is known not to be in the underlying blobstores.
+    pub(crate) fn is_known_absent(&self, key: &str) -> bool {
+        self.covers_all_writes && !self.may_contain(key)
+    }
+
+    fn bit_indices(&self, key: &str) -> impl Iterator<Item = u64> {
+        // Double hashing: the i-th index is h1 + i * h2.
+        let h1 = hash_with_seed(key, 0);
+        let h2 = hash_with_seed(key, 1) | 1;
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+}
+
+fn hash_with_seed(key: &str, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    key.hash(&mut hasher);
+    hasher.finish()
+}
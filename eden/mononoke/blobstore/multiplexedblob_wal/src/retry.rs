@@ -0,0 +1,80 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Error;
+use anyhow::Result;
+use futures::Future;
+use retry::retry;
+use retry::RetryLogic;
+
+/// Tells whether an error returned by an underlying blobstore is transient (e.g. throttling)
+/// and the operation is likely to succeed if retried.
+pub type TransientErrorClassifier = Arc<dyn Fn(&Error) -> bool + Send + Sync>;
+
+/// Retries of the operations on the underlying blobstores, applied before a blobstore is
+/// considered failed for the purpose of the quorum.
+#[derive(Clone)]
+pub struct MultiplexRetry {
+    /// Maximum number of attempts of a single blobstore operation, including the first one.
+    pub attempts: usize,
+    /// Base of the exponential backoff between the attempts.
+    pub base_delay: Duration,
+    /// Upper bound of the random delay added on top of the exponential backoff.
+    pub jitter: Duration,
+    /// Only the errors classified as transient are retried.
+    pub is_transient: TransientErrorClassifier,
+}
+
+impl fmt::Debug for MultiplexRetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultiplexRetry")
+            .field("attempts", &self.attempts)
+            .field("base_delay", &self.base_delay)
+            .field("jitter", &self.jitter)
+            .finish()
+    }
+}
+
+impl MultiplexRetry {
+    pub fn new(
+        attempts: usize,
+        base_delay: Duration,
+        jitter: Duration,
+        is_transient: impl Fn(&Error) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            attempts,
+            base_delay,
+            jitter,
+            is_transient: Arc::new(is_transient),
+        }
+    }
+
+    pub(crate) async fn run<T, Fut>(&self, mut op: impl FnMut() -> Fut + Send) -> Result<T>
+    where
+        T: Send + 'static,
+        Fut: Future<Output = Result<T>>,
+    {
+        let (result, _attempts) = retry(
+            None,
+            |_attempt| op(),
+            |err| (self.is_transient)(err),
+            RetryLogic::ExponentialWithJitter {
+                base: self.base_delay,
+                factor: 2.0,
+                jitter: self.jitter,
+            },
+            self.attempts,
+        )
+        .await?;
+        Ok(result)
+    }
+}
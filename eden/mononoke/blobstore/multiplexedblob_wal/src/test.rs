@@ -5,6 +5,8 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::future::Future;
 use std::panic;
@@ -17,13 +19,22 @@ use blobstore::Blobstore;
 use blobstore::BlobstoreGetData;
 use blobstore::BlobstoreIsPresent;
 use blobstore::BlobstorePutOps;
+use blobstore::OverwriteStatus;
+use blobstore::PutBehaviour;
+use blobstore_stats::OperationType;
 use blobstore_sync_queue::BlobstoreWal;
 use blobstore_sync_queue::BlobstoreWalEntry;
+use blobstore_sync_queue::BlobstoreWalPriority;
 use blobstore_sync_queue::SqlBlobstoreWal;
+use blobstore_test_utils::Fault;
+use blobstore_test_utils::FaultyBlobstore;
 use blobstore_test_utils::Tickable;
 use borrowed::borrowed;
 use bytes::Bytes;
 use context::CoreContext;
+use context::PerfCounterType;
+use context::SessionClass;
+use context::SessionContainer;
 use fbinit::FacebookInit;
 use futures::future::FutureExt;
 use futures::task::Poll;
@@ -31,6 +42,13 @@ use lock_ext::LockExt;
 use metaconfig_types::BlobstoreId;
 use metaconfig_types::MultiplexId;
 use mononoke_types::BlobstoreBytes;
+use mononoke_types::BlobstoreKey;
+use mononoke_types::BlobstoreValue;
+use mononoke_types::ChunkedFileContents;
+use mononoke_types::ContentChunkId;
+use mononoke_types::ContentChunkPointer;
+use mononoke_types::ContentId;
+use mononoke_types::FileContents;
 use mononoke_types::Timestamp;
 use multiplexedblob::LoggingScrubHandler;
 use multiplexedblob::ScrubAction;
@@ -41,9 +59,22 @@ use nonzero_ext::nonzero;
 use scuba_ext::MononokeScubaSampleBuilder;
 use sql_construct::SqlConstruct;
 
+use crate::recent_writes::RecentWrites;
 use crate::scrub::WalScrubBlobstore;
+use crate::BlobSizeCheckConfig;
+use crate::BlobstoreHealth;
+use crate::ErrorKind;
+use crate::HealthCheckConfig;
+use crate::KeyState;
+use crate::MultiplexReadStrategy;
+use crate::MultiplexRetry;
 use crate::MultiplexTimeout;
+use crate::OverwriteStatusPolicy;
+use crate::PutDedupConfig;
+use crate::RecentWritesConfig;
 use crate::Scuba;
+use crate::TraceOutcome;
+use crate::WalFailureMode;
 use crate::WalMultiplexedBlobstore;
 
 #[fbinit::test]
@@ -134,6 +165,76 @@ async fn test_put_wal_fails(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_put_wal_fails_write_without_wal(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let wal_failure_mode = WalFailureMode::WriteWithoutWal { write_quorum: 3 };
+
+    // The quorum without the WAL can't be lower than the usual one
+    {
+        let invalid = WalFailureMode::WriteWithoutWal { write_quorum: 1 };
+        let (_, _, multiplex) = setup_multiplex(3, 2, None)?;
+        assert!(multiplex.with_wal_failure_mode(invalid).is_err());
+    }
+
+    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+    let multiplex = multiplex.with_wal_failure_mode(wal_failure_mode)?;
+
+    // All the blobstore puts succeed, the multiplex put waits for all of them: [ ] [ ] [ ]
+    {
+        let v = make_value("v1");
+        let k = "k1";
+
+        let mut put_fut = multiplex.put(&ctx, k.to_owned(), v.clone()).boxed();
+        assert_pending(&mut put_fut).await;
+
+        // wal queue write fails
+        tickable_queue.tick(Some("wal queue failed"));
+        assert_pending(&mut put_fut).await;
+
+        // the usual write quorum isn't enough
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[1].1.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        tickable_blobstores[2].1.tick(None);
+        assert!(put_fut.await.is_ok());
+        for (_id, store) in &tickable_blobstores {
+            assert_eq!(store.get_bytes(k), Some(v.clone()));
+        }
+        assert_eq!(
+            ctx.perf_counters()
+                .get_counter(PerfCounterType::BlobPutsWalFailures),
+            1
+        );
+        assert!(queue_keys(&ctx, &multiplex).await?.is_empty());
+    }
+
+    // One blobstore put fails, the multiplex put fails without the WAL to heal it: [ ] [ ] [x]
+    {
+        let v = make_value("v2");
+        let k = "k2";
+
+        let mut put_fut = multiplex.put(&ctx, k.to_owned(), v.clone()).boxed();
+        assert_pending(&mut put_fut).await;
+
+        tickable_queue.tick(Some("wal queue failed"));
+        assert_pending(&mut put_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[1].1.tick(None);
+        tickable_blobstores[2].1.tick(Some("bs2 failed"));
+        assert!(put_fut.await.is_err());
+        assert_eq!(
+            ctx.perf_counters()
+                .get_counter(PerfCounterType::BlobPutsWalFailures),
+            2
+        );
+    }
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn test_put_fails(fb: FacebookInit) -> Result<()> {
     let ctx = CoreContext::test_mock(fb);
@@ -370,6 +471,232 @@ async fn test_put_succeeds(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_put_write_mostly_quorum(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (tickable_queue, wal_queue) = setup_queue();
+    let (tickable_blobstores, blobstores) = setup_blobstores(3);
+    let tickable_write_only: Vec<_> = (3..5)
+        .map(|id| (BlobstoreId::new(id), Arc::new(TickableBytes::new())))
+        .collect();
+    let write_only = tickable_write_only
+        .iter()
+        .map(|(id, store)| (*id, store.clone() as Arc<dyn BlobstorePutOps>))
+        .collect();
+    let scuba = Scuba::new(
+        MononokeScubaSampleBuilder::with_discard(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )?;
+    let multiplex = WalMultiplexedBlobstore::new(
+        MultiplexId::new(1),
+        wal_queue,
+        blobstores,
+        write_only,
+        2,
+        None,
+        scuba,
+    )?;
+
+    // There are only 2 write-mostly blobstores
+    assert!(multiplex.clone().with_write_mostly_quorum(3).is_err());
+    let multiplex = multiplex.with_write_mostly_quorum(1)?;
+
+    // All main puts succeed, but the write-mostly ones fail: [ ] [ ] [ ] | [x] [x]
+    {
+        let mut put_fut = multiplex
+            .put(&ctx, "k1".to_owned(), make_value("v1"))
+            .boxed();
+        assert_pending(&mut put_fut).await;
+
+        // wal queue write succeeds
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        // the main write quorum isn't enough
+        for (_id, store) in &tickable_blobstores {
+            store.tick(None);
+        }
+        assert_pending(&mut put_fut).await;
+
+        tickable_write_only[0].1.tick(Some("bs3 failed"));
+        assert_pending(&mut put_fut).await;
+        tickable_write_only[1].1.tick(Some("bs4 failed"));
+        assert!(put_fut.await.is_err());
+    }
+
+    // The main quorum and one write-mostly put succeed: [ ] [ ] [x] | [x] [ ]
+    {
+        let v = make_value("v2");
+        let mut put_fut = multiplex.put(&ctx, "k2".to_owned(), v.clone()).boxed();
+        assert_pending(&mut put_fut).await;
+
+        // wal queue write succeeds
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[1].1.tick(None);
+        tickable_blobstores[2].1.tick(Some("bs2 failed"));
+        assert_pending(&mut put_fut).await;
+
+        tickable_write_only[0].1.tick(Some("bs3 failed"));
+        assert_pending(&mut put_fut).await;
+        tickable_write_only[1].1.tick(None);
+        assert!(put_fut.await.is_ok());
+        assert_eq!(tickable_write_only[1].1.get_bytes("k2"), Some(v));
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_put_if_absent(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+
+    // None of the blobstores have the key, the put is not prevented
+    {
+        let v = make_value("v0");
+        let k = "k0";
+
+        let mut put_fut = multiplex
+            .put_explicit(&ctx, k.to_owned(), v, PutBehaviour::IfAbsent)
+            .boxed();
+        assert_pending(&mut put_fut).await;
+
+        // wal queue write succeeds
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        for (_id, store) in tickable_blobstores.iter() {
+            store.tick(None);
+        }
+        assert_eq!(put_fut.await?, OverwriteStatus::NotChecked);
+    }
+
+    // Only the first blobstore has the key (e.g. it's not healed yet), the other
+    // blobstore taking part in the quorum doesn't: the put is reported as prevented
+    {
+        let v = make_value("v1");
+        let k = "k1";
+        tickable_blobstores[0].1.add_bytes(k.to_owned(), v.clone());
+
+        let mut put_fut = multiplex
+            .put_explicit(&ctx, k.to_owned(), v, PutBehaviour::IfAbsent)
+            .boxed();
+        assert_pending(&mut put_fut).await;
+
+        // wal queue write succeeds
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        // the store missing the key completes first
+        tickable_blobstores[1].1.tick(None);
+        assert_pending(&mut put_fut).await;
+        // the store that already has the key completes the quorum
+        tickable_blobstores[0].1.tick(None);
+        assert_eq!(put_fut.await?, OverwriteStatus::Prevented);
+
+        tickable_blobstores[2].1.tick(None);
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_put_overwrite_status_majority(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let policy = OverwriteStatusPolicy::Majority {
+        timeout: Duration::from_millis(100),
+    };
+    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+    let multiplex = multiplex.with_overwrite_status_policy(policy.clone());
+
+    // None of the blobstores have the key
+    {
+        let mut put_fut = multiplex
+            .put_explicit(
+                &ctx,
+                "k0".to_owned(),
+                make_value("v0"),
+                PutBehaviour::OverwriteAndLog,
+            )
+            .boxed();
+        assert_pending(&mut put_fut).await;
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[1].1.tick(None);
+        assert_eq!(put_fut.await?, OverwriteStatus::New);
+        tickable_blobstores[2].1.tick(None);
+    }
+
+    // The blobstores reaching the quorum disagree: the tie is reported as an overwrite
+    {
+        let k = "k1";
+        let v = make_value("v1");
+        tickable_blobstores[0].1.add_bytes(k.to_owned(), v.clone());
+
+        let mut put_fut = multiplex
+            .put_explicit(&ctx, k.to_owned(), v, PutBehaviour::OverwriteAndLog)
+            .boxed();
+        assert_pending(&mut put_fut).await;
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[1].1.tick(None);
+        assert_eq!(put_fut.await?, OverwriteStatus::Overwrote);
+        tickable_blobstores[2].1.tick(None);
+    }
+
+    // None of the blobstores check: the put waits for the last one until the timeout
+    {
+        let mut put_fut = multiplex
+            .put_explicit(
+                &ctx,
+                "k2".to_owned(),
+                make_value("v2"),
+                PutBehaviour::Overwrite,
+            )
+            .boxed();
+        assert_pending(&mut put_fut).await;
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[1].1.tick(None);
+        assert_pending(&mut put_fut).await;
+        assert_eq!(put_fut.await?, OverwriteStatus::NotChecked);
+        tickable_blobstores[2].1.tick(None);
+    }
+
+    // Only one of the three blobstores had the key: the majority says it's new
+    {
+        let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 3, None)?;
+        let multiplex = multiplex.with_overwrite_status_policy(policy);
+        let k = "k3";
+        let v = make_value("v3");
+        tickable_blobstores[0].1.add_bytes(k.to_owned(), v.clone());
+
+        let mut put_fut = multiplex
+            .put_explicit(&ctx, k.to_owned(), v, PutBehaviour::OverwriteAndLog)
+            .boxed();
+        assert_pending(&mut put_fut).await;
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        for (_id, store) in &tickable_blobstores {
+            store.tick(None);
+        }
+        assert_eq!(put_fut.await?, OverwriteStatus::New);
+    }
+
+    Ok(())
+}
+
 #[fbinit::test]
 async fn test_get_on_missing(fb: FacebookInit) -> Result<()> {
     let ctx = CoreContext::test_mock(fb);
@@ -426,73 +753,266 @@ async fn test_get_on_missing(fb: FacebookInit) -> Result<()> {
 }
 
 #[fbinit::test]
-async fn test_get_on_existing(fb: FacebookInit) -> Result<()> {
+async fn test_get_failures(fb: FacebookInit) -> Result<()> {
     let ctx = CoreContext::test_mock(fb);
-    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
-
-    // Two blobstores have the key, one failed to write: [ ] [x] [ ]
+    // The read quorum is 3
+    let (_tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(4, 2, None)?;
 
-    let v = make_value("v1");
-    let k = "k1";
-
-    let mut put_fut = multiplex.put(&ctx, k.to_owned(), v.clone()).boxed();
-    assert_pending(&mut put_fut).await;
-
-    // wal queue write succeeds
-    tickable_queue.tick(None);
-    assert_pending(&mut put_fut).await;
+    // Two blobstores don't have the blob, two fail: [ ] [ ] [x] [x]
+    let mut get_fut = multiplex.get(&ctx, "k").boxed();
+    assert_pending(&mut get_fut).await;
 
     tickable_blobstores[0].1.tick(None);
-    tickable_blobstores[1].1.tick(Some("bs1 failed"));
-    tickable_blobstores[2].1.tick(None);
+    tickable_blobstores[1].1.tick(None);
+    tickable_blobstores[2].1.tick(Some("bs2 failed"));
+    assert_pending(&mut get_fut).await;
+    tickable_blobstores[3].1.tick(Some("bs3 failed"));
+
+    let err = get_fut.await.unwrap_err();
+    match err.downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::SomeGetsFailed(failures)) => {
+            let mut failed: Vec<_> = failures.errors.keys().copied().collect();
+            failed.sort();
+            assert_eq!(failed, vec![BlobstoreId::new(2), BlobstoreId::new(3)]);
+            assert_eq!(failures.absent, 2);
+        }
+        _ => panic!("unexpected error: {:?}", err),
+    }
 
-    // multiplexed put succeeds: write quorum achieved
-    assert!(put_fut.await.is_ok());
+    Ok(())
+}
 
-    // all gets succeed, but multiplexed returns on the first successful `Some`
+#[fbinit::test]
+async fn test_get_verify_content_hashes(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let blob = FileContents::new_bytes("content").into_blob();
+    let k = format!("repo0000.{}", blob.id().blobstore_key());
+    let v = BlobstoreBytes::from(blob);
+    let corrupt = BlobstoreBytes::from(FileContents::new_bytes("corrupt").into_blob());
+
+    let setup = |verify_content_hashes| -> Result<_> {
+        let (_tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        let multiplex = if verify_content_hashes {
+            multiplex.with_content_hash_verification()
+        } else {
+            multiplex
+        };
+        // The first blobstore has a corrupt copy of the blob: [c] [ ] [ ]
+        tickable_blobstores[0]
+            .1
+            .add_bytes(k.clone(), corrupt.clone());
+        for (_id, store) in &tickable_blobstores[1..] {
+            store.add_bytes(k.clone(), v.clone());
+        }
+        Ok((tickable_blobstores, multiplex))
+    };
+
+    // Without verification, the corrupt copy is returned
     {
-        let mut get_fut = multiplex.get(&ctx, k).boxed();
+        let (tickable_blobstores, multiplex) = setup(false)?;
+        let mut get_fut = multiplex.get(&ctx, &k).boxed();
         assert_pending(&mut get_fut).await;
 
-        // first blobstore returns the blob
         tickable_blobstores[0].1.tick(None);
-        validate_blob(get_fut.await, Ok(Some(&v)));
-
-        // drain the tickables of the pending requests, as they won't be claimed
-        for (_id, store) in &tickable_blobstores[1..3] {
-            store.drain(1);
-        }
+        validate_blob(get_fut.await, Ok(Some(&corrupt)));
+        tickable_blobstores[1].1.drain(1);
+        tickable_blobstores[2].1.drain(1);
     }
 
-    // first get fails, but multiplexed returns on the third get
+    // With verification, the corrupt copy counts as a failure and a good copy is returned
     {
-        let mut get_fut = multiplex.get(&ctx, k).boxed();
+        let (tickable_blobstores, multiplex) = setup(true)?;
+        let mut get_fut = multiplex.get(&ctx, &k).boxed();
         assert_pending(&mut get_fut).await;
 
-        // first blobstore get fails
-        tickable_blobstores[0].1.tick(Some("bs1 failed!"));
+        tickable_blobstores[0].1.tick(None);
         assert_pending(&mut get_fut).await;
-
-        // second blobstore get returns None
         tickable_blobstores[1].1.tick(None);
-        assert_pending(&mut get_fut).await;
-
-        // third blobstore get returns Some
-        tickable_blobstores[2].1.tick(None);
         validate_blob(get_fut.await, Ok(Some(&v)));
+        tickable_blobstores[2].1.drain(1);
     }
 
-    // 2 first gets fail, but multiplexed returns `Some` on the third get
+    // With verification, the blob fails to be read if no blobstore has a good copy
     {
-        let mut get_fut = multiplex.get(&ctx, k).boxed();
+        let (tickable_blobstores, multiplex) = setup(true)?;
+        for (_id, store) in &tickable_blobstores {
+            store.add_bytes(k.clone(), corrupt.clone());
+        }
+        let mut get_fut = multiplex.get(&ctx, &k).boxed();
         assert_pending(&mut get_fut).await;
 
-        // first blobstore get fails
-        tickable_blobstores[0].1.tick(Some("bs1 failed!"));
-        assert_pending(&mut get_fut).await;
+        for (_id, store) in &tickable_blobstores {
+            store.tick(None);
+        }
+        validate_blob(get_fut.await, Err(anyhow!("error")));
+    }
 
-        // second blobstore get fails
-        tickable_blobstores[1].1.tick(Some("bs2 failed!"));
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_get_verify_content_hashes_unverifiable(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (_tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+    let multiplex = multiplex.with_content_hash_verification();
+
+    // A chunked file whose chunks don't exist, and a blob that isn't an alias: neither can be
+    // verified from the blob alone, so both are served.
+    let chunked = FileContents::Chunked(ChunkedFileContents::new(
+        ContentId::from_bytes([1; 32])?,
+        vec![ContentChunkPointer::new(
+            ContentChunkId::from_bytes([2; 32])?,
+            7,
+        )],
+    ))
+    .into_blob();
+    let chunked_key = format!("repo0000.{}", chunked.id().blobstore_key());
+    let chunked = BlobstoreBytes::from(chunked);
+    let alias_key = format!("repo0000.alias.sha1.{}", "1".repeat(40));
+    let alias = make_value("not an alias");
+
+    for (k, v) in [(&chunked_key, &chunked), (&alias_key, &alias)] {
+        tickable_blobstores[0].1.add_bytes(k.clone(), v.clone());
+        let mut get_fut = multiplex.get(&ctx, k).boxed();
+        assert_pending(&mut get_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        validate_blob(get_fut.await, Ok(Some(v)));
+        tickable_blobstores[1].1.drain(1);
+        tickable_blobstores[2].1.drain(1);
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_get_blob_size_check(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let k = "k";
+    let v = make_value("value");
+    let truncated = make_value("val");
+
+    let (ctx, v, truncated) = (&ctx, &v, &truncated);
+    let setup = |size_check| async move {
+        let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        let multiplex = if size_check {
+            multiplex.with_blob_size_check(BlobSizeCheckConfig { capacity: 10 })
+        } else {
+            multiplex
+        };
+
+        let mut put_fut = multiplex.put(ctx, k.to_owned(), v.clone()).boxed();
+        assert_pending(&mut put_fut).await;
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+        for (_id, store) in &tickable_blobstores {
+            store.tick(None);
+        }
+        put_fut.await?;
+
+        // The first blobstore lost the end of the blob: [t] [ ] [ ]
+        tickable_blobstores[0]
+            .1
+            .add_bytes(k.to_owned(), truncated.clone());
+        anyhow::Ok((tickable_blobstores, multiplex))
+    };
+
+    // Without the check, the truncated blob is returned
+    {
+        let (tickable_blobstores, multiplex) = setup(false).await?;
+        let mut get_fut = multiplex.get(ctx, k).boxed();
+        assert_pending(&mut get_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        validate_blob(get_fut.await, Ok(Some(truncated)));
+        tickable_blobstores[1].1.drain(1);
+        tickable_blobstores[2].1.drain(1);
+    }
+
+    // With the check, the truncated blob counts as a failure and the full one is returned
+    {
+        let (tickable_blobstores, multiplex) = setup(true).await?;
+        let mut get_fut = multiplex.get(ctx, k).boxed();
+        assert_pending(&mut get_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        assert_pending(&mut get_fut).await;
+        tickable_blobstores[1].1.tick(None);
+        validate_blob(get_fut.await, Ok(Some(v)));
+        tickable_blobstores[2].1.drain(1);
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_get_on_existing(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+
+    // Two blobstores have the key, one failed to write: [ ] [x] [ ]
+
+    let v = make_value("v1");
+    let k = "k1";
+
+    let mut put_fut = multiplex.put(&ctx, k.to_owned(), v.clone()).boxed();
+    assert_pending(&mut put_fut).await;
+
+    // wal queue write succeeds
+    tickable_queue.tick(None);
+    assert_pending(&mut put_fut).await;
+
+    tickable_blobstores[0].1.tick(None);
+    tickable_blobstores[1].1.tick(Some("bs1 failed"));
+    tickable_blobstores[2].1.tick(None);
+
+    // multiplexed put succeeds: write quorum achieved
+    assert!(put_fut.await.is_ok());
+
+    // all gets succeed, but multiplexed returns on the first successful `Some`
+    {
+        let mut get_fut = multiplex.get(&ctx, k).boxed();
+        assert_pending(&mut get_fut).await;
+
+        // first blobstore returns the blob
+        tickable_blobstores[0].1.tick(None);
+        validate_blob(get_fut.await, Ok(Some(&v)));
+
+        // drain the tickables of the pending requests, as they won't be claimed
+        for (_id, store) in &tickable_blobstores[1..3] {
+            store.drain(1);
+        }
+    }
+
+    // first get fails, but multiplexed returns on the third get
+    {
+        let mut get_fut = multiplex.get(&ctx, k).boxed();
+        assert_pending(&mut get_fut).await;
+
+        // first blobstore get fails
+        tickable_blobstores[0].1.tick(Some("bs1 failed!"));
+        assert_pending(&mut get_fut).await;
+
+        // second blobstore get returns None
+        tickable_blobstores[1].1.tick(None);
+        assert_pending(&mut get_fut).await;
+
+        // third blobstore get returns Some
+        tickable_blobstores[2].1.tick(None);
+        validate_blob(get_fut.await, Ok(Some(&v)));
+    }
+
+    // 2 first gets fail, but multiplexed returns `Some` on the third get
+    {
+        let mut get_fut = multiplex.get(&ctx, k).boxed();
+        assert_pending(&mut get_fut).await;
+
+        // first blobstore get fails
+        tickable_blobstores[0].1.tick(Some("bs1 failed!"));
+        assert_pending(&mut get_fut).await;
+
+        // second blobstore get fails
+        tickable_blobstores[1].1.tick(Some("bs2 failed!"));
         assert_pending(&mut get_fut).await;
 
         // third blobstore get returns Some
@@ -797,42 +1317,1079 @@ async fn test_timeout_on_request(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
-async fn assert_pending<T: Debug>(fut: &mut (impl Future<Output = T> + Unpin)) {
-    match futures::poll!(fut) {
-        Poll::Pending => {}
-        state => {
-            panic!("future must be pending, received: {:?}", state);
+#[fbinit::test]
+async fn test_retry_transient_failures(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let retry = MultiplexRetry::new(
+        3,
+        Duration::from_millis(1),
+        Duration::from_millis(0),
+        |err| err.to_string().contains("throttled"),
+    );
+
+    // A store failing once with a transient error still contributes to the quorum:
+    // [t, ] [ ] [x]
+    {
+        let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        let multiplex = multiplex.with_retry(retry.clone());
+
+        let v = make_value("v1");
+        let k = "k1";
+
+        let mut put_fut = multiplex.put(&ctx, k.to_owned(), v.clone()).boxed();
+        assert_pending(&mut put_fut).await;
+
+        // wal queue write succeeds
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        // first blobstore is throttled, the put is retried
+        tickable_blobstores[0].1.tick(Some("bs0 throttled"));
+        assert_pending(&mut put_fut).await;
+        // third blobstore fails with a non-transient error
+        tickable_blobstores[2].1.tick(Some("bs2 failed"));
+        assert_pending(&mut put_fut).await;
+        // second blobstore succeeds
+        tickable_blobstores[1].1.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        // the retry of the first blobstore succeeds and completes the quorum
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_pending(&mut put_fut).await;
+        tickable_blobstores[0].1.tick(None);
+        assert!(put_fut.await.is_ok());
+        assert_eq!(tickable_blobstores[0].1.get_bytes(k), Some(v));
+    }
+
+    // A store failing permanently stops after the retry budget: [t, t, t]
+    {
+        let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(1, 1, None)?;
+        let multiplex = multiplex.with_retry(retry);
+
+        let mut put_fut = multiplex
+            .put(&ctx, "k2".to_owned(), make_value("v2"))
+            .boxed();
+        assert_pending(&mut put_fut).await;
+
+        // wal queue write succeeds
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+
+        for _attempt in 0..2 {
+            tickable_blobstores[0].1.tick(Some("bs0 throttled"));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_pending(&mut put_fut).await;
         }
+
+        // the last attempt fails, the store is not retried anymore
+        tickable_blobstores[0].1.tick(Some("bs0 throttled"));
+        assert!(put_fut.await.is_err());
     }
+
+    Ok(())
 }
 
-fn setup_multiplex(
-    num: u64,
-    quorum: usize,
-    timeout: Option<MultiplexTimeout>,
-) -> Result<(
-    Arc<Tickable<BlobstoreWalEntry>>,
-    Vec<(BlobstoreId, Arc<Tickable<(BlobstoreBytes, u64)>>)>,
-    WalMultiplexedBlobstore,
-)> {
-    let (tickable_queue, wal_queue) = setup_queue();
-    let (tickable_blobstores, blobstores) = setup_blobstores(num);
-    let scuba = Scuba::new(
-        MononokeScubaSampleBuilder::with_discard(),
-        MononokeScubaSampleBuilder::with_discard(),
-        nonzero!(1u64),
-    )?;
-    let multiplex = WalMultiplexedBlobstore::new(
-        MultiplexId::new(1),
-        wal_queue,
-        blobstores,
-        vec![],
-        quorum,
-        timeout,
-        scuba,
-    )?;
+#[fbinit::test]
+async fn test_is_present_recent_writes(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let config = RecentWritesConfig {
+        capacity: 100,
+        false_positive_rate: 0.01,
+        covers_all_writes: true,
+    };
 
-    Ok((tickable_queue, tickable_blobstores, multiplex))
+    // The false positive rate must be a probability.
+    {
+        let (_tickable_queue, _tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        let invalid = RecentWritesConfig {
+            false_positive_rate: 1.0,
+            ..config.clone()
+        };
+        assert!(multiplex.with_recent_writes(invalid).is_err());
+    }
+
+    // Keys that were never written are absent without querying the blobstores
+    {
+        let (_tickable_queue, _tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        let multiplex = multiplex.with_recent_writes(config.clone())?;
+
+        let result = multiplex.is_present(&ctx, "k0").await;
+        assert_is_present_ok(result, BlobstoreIsPresent::Absent);
+    }
+
+    // Written keys are looked up in the blobstores
+    {
+        let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        let multiplex = multiplex.with_recent_writes(config.clone())?;
+
+        let k = "k1";
+        let mut put_fut = multiplex.put(&ctx, k.to_owned(), make_value("v1")).boxed();
+        assert_pending(&mut put_fut).await;
+
+        // the put fails, but the key may have been written to some blobstores
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[1].1.tick(Some("bs1 failed"));
+        tickable_blobstores[2].1.tick(Some("bs2 failed"));
+        assert!(put_fut.await.is_err());
+
+        let mut fut = multiplex.is_present(&ctx, k).boxed();
+        assert_pending(&mut fut).await;
+        tickable_blobstores[0].1.tick(None);
+        assert_is_present_ok(fut.await, BlobstoreIsPresent::Present);
+    }
+
+    // Without covering all the writes, the blobstores are always queried
+    {
+        let (_tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        let multiplex = multiplex.with_recent_writes(RecentWritesConfig {
+            covers_all_writes: false,
+            ..config
+        })?;
+
+        let mut fut = multiplex.is_present(&ctx, "k0").boxed();
+        assert_pending(&mut fut).await;
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[1].1.tick(None);
+        assert_is_present_ok(fut.await, BlobstoreIsPresent::Absent);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_recent_writes_false_positive_rate() -> Result<()> {
+    let filter = RecentWrites::new(&RecentWritesConfig {
+        capacity: 1000,
+        false_positive_rate: 0.01,
+        covers_all_writes: true,
+    })?;
+
+    for i in 0..1000 {
+        filter.insert(&format!("written{}", i));
+    }
+    // No false negatives
+    for i in 0..1000 {
+        assert!(!filter.is_known_absent(&format!("written{}", i)));
+    }
+
+    // The false positive rate stays in the ballpark of the configured one
+    let false_positives = (0..10000)
+        .filter(|i| filter.may_contain(&format!("absent{}", i)))
+        .count();
+    assert!(
+        false_positives < 300,
+        "too many false positives: {}",
+        false_positives
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_copy(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let v = make_value("v");
+
+    // The source is missing from one blobstore, the copy succeeds on the write quorum:
+    // [ ] [ ] [x]
+    {
+        let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        tickable_blobstores[0]
+            .1
+            .add_bytes("k1".to_owned(), v.clone());
+        tickable_blobstores[1]
+            .1
+            .add_bytes("k1".to_owned(), v.clone());
+
+        let mut copy_fut = multiplex.copy(&ctx, "k1", "k2".to_owned()).boxed();
+        assert_pending(&mut copy_fut).await;
+
+        // wal queue write succeeds
+        tickable_queue.tick(None);
+        assert_pending(&mut copy_fut).await;
+
+        // the blobstores copy by getting and putting the blob
+        tickable_blobstores[2].1.tick(None);
+        tickable_blobstores[0].1.tick(None);
+        assert_pending(&mut copy_fut).await;
+        tickable_blobstores[0].1.tick(None);
+        assert_pending(&mut copy_fut).await;
+        tickable_blobstores[1].1.tick(None);
+        assert_pending(&mut copy_fut).await;
+        tickable_blobstores[1].1.tick(None);
+        assert!(copy_fut.await.is_ok());
+
+        assert_eq!(tickable_blobstores[0].1.get_bytes("k2"), Some(v.clone()));
+        assert_eq!(tickable_blobstores[1].1.get_bytes("k2"), Some(v.clone()));
+        assert_eq!(tickable_blobstores[2].1.get_bytes("k2"), None);
+        // the copy is left in the WAL for the healer to complete
+        assert!(tickable_queue.storage.with(|s| s.contains_key("k2")));
+    }
+
+    // The source is missing from two blobstores, the copy fails: [x] [ ] [x]
+    {
+        let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+        tickable_blobstores[1]
+            .1
+            .add_bytes("k1".to_owned(), v.clone());
+
+        let mut copy_fut = multiplex.copy(&ctx, "k1", "k2".to_owned()).boxed();
+        assert_pending(&mut copy_fut).await;
+
+        // wal queue write succeeds
+        tickable_queue.tick(None);
+        assert_pending(&mut copy_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        tickable_blobstores[2].1.tick(None);
+        tickable_blobstores[1].1.tick(None);
+        assert_pending(&mut copy_fut).await;
+        tickable_blobstores[1].1.tick(None);
+        assert!(copy_fut.await.is_err());
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_blobstore_health(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (_tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(4, 2, None)?;
+    let multiplex = multiplex.with_health_check(HealthCheckConfig {
+        timeout: Duration::from_millis(100),
+        degraded_latency: Duration::from_millis(20),
+        min_interval: Duration::from_secs(60),
+        ..Default::default()
+    });
+
+    let mut fut = multiplex.blobstore_health(&ctx).boxed();
+    assert_pending(&mut fut).await;
+
+    // bs0 answers promptly, bs1 fails
+    tickable_blobstores[0].1.tick(None);
+    tickable_blobstores[1].1.tick(Some("bs1 failed"));
+    assert_pending(&mut fut).await;
+
+    // bs2 answers slowly, bs3 never answers and times out
+    tokio::time::sleep(Duration::from_millis(40)).await;
+    tickable_blobstores[2].1.tick(None);
+    assert_eq!(
+        fut.await,
+        vec![
+            (BlobstoreId::new(0), BlobstoreHealth::Healthy),
+            (BlobstoreId::new(1), BlobstoreHealth::Down),
+            (BlobstoreId::new(2), BlobstoreHealth::Degraded),
+            (BlobstoreId::new(3), BlobstoreHealth::Down),
+        ]
+    );
+    tickable_blobstores[3].1.drain(1);
+
+    // The probes are rate limited: the last snapshot is returned without probing again
+    let mut fut = multiplex.blobstore_health(&ctx).boxed();
+    match futures::poll!(&mut fut) {
+        Poll::Ready(snapshot) => assert_eq!(snapshot.len(), 4),
+        Poll::Pending => panic!("blobstores must not be probed again"),
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_get_hedged(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let read_strategy = MultiplexReadStrategy::Hedged {
+        preferred: vec![BlobstoreId::new(0)],
+        delay: Duration::from_millis(20),
+    };
+
+    // Preferred blobstores must be among the normal ones
+    {
+        let invalid = MultiplexReadStrategy::Hedged {
+            preferred: vec![BlobstoreId::new(5)],
+            delay: Duration::from_millis(20),
+        };
+        let (_, _, multiplex) = setup_multiplex(3, 2, None)?;
+        assert!(multiplex.with_read_strategy(invalid).is_err());
+    }
+
+    let (_tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+    let multiplex = multiplex.with_read_strategy(read_strategy)?;
+    let v = make_value("v");
+    for (_id, store) in &tickable_blobstores {
+        store.add_bytes("k1".to_owned(), v.clone());
+    }
+
+    // The preferred blobstore has the blob: the others are not read from
+    {
+        let mut get_fut = multiplex.get(&ctx, "k1").boxed();
+        assert_pending(&mut get_fut).await;
+
+        tickable_blobstores[0].1.tick(None);
+        validate_blob(get_fut.await, Ok(Some(&v)));
+        for (_id, store) in &tickable_blobstores[1..] {
+            // ticking the dropped requests would panic if the store had been read from
+            store.tick(None);
+        }
+    }
+
+    // The preferred blobstore is slow: the others are read from after the delay
+    {
+        let mut get_fut = multiplex.get(&ctx, "k1").boxed();
+        assert_pending(&mut get_fut).await;
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_pending(&mut get_fut).await;
+        tickable_blobstores[1].1.tick(None);
+        validate_blob(get_fut.await, Ok(Some(&v)));
+        tickable_blobstores[0].1.drain(1);
+        tickable_blobstores[2].1.drain(1);
+    }
+
+    // The preferred blobstore fails: the others are read from right away
+    {
+        let mut get_fut = multiplex.get(&ctx, "k2").boxed();
+        assert_pending(&mut get_fut).await;
+
+        tickable_blobstores[0].1.tick(Some("bs0 failed"));
+        assert_pending(&mut get_fut).await;
+        tickable_blobstores[1].1.tick(None);
+        tickable_blobstores[2].1.tick(None);
+        validate_blob(get_fut.await, Ok(None));
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_put_dedup(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+    let multiplex = multiplex.with_put_dedup(PutDedupConfig {
+        ttl: Duration::from_secs(60),
+        capacity: 10,
+    });
+    let v1 = make_value("v1");
+    let v2 = make_value("v2");
+
+    // The first put goes through the WAL and every blobstore
+    let mut put_fut = multiplex.put(&ctx, "k".to_owned(), v1.clone()).boxed();
+    assert_pending(&mut put_fut).await;
+    tickable_queue.tick(None);
+    assert_pending(&mut put_fut).await;
+    tickable_blobstores[0].1.tick(None);
+    tickable_blobstores[1].1.tick(None);
+    assert!(put_fut.await.is_ok());
+
+    // Until the last blobstore confirms, the put is not skipped
+    let mut put_fut = multiplex.put(&ctx, "k".to_owned(), v1.clone()).boxed();
+    assert_pending(&mut put_fut).await;
+    tickable_blobstores[2].1.tick(None);
+    tokio::task::yield_now().await;
+    // Tick the deletion of the first put from the WAL, and the WAL write of the second one
+    tickable_queue.tick(None);
+    tokio::task::yield_now().await;
+    tickable_queue.tick(None);
+    assert_pending(&mut put_fut).await;
+    for (_id, store) in &tickable_blobstores {
+        store.tick(None);
+    }
+    assert!(put_fut.await.is_ok());
+    tokio::task::yield_now().await;
+    tickable_queue.tick(None);
+    tokio::task::yield_now().await;
+    assert!(queue_keys(&ctx, &multiplex).await?.is_empty());
+
+    // The same put is now skipped, without touching the WAL or the blobstores
+    let put_fut = multiplex.put(&ctx, "k".to_owned(), v1.clone());
+    assert!(matches!(put_fut.now_or_never(), Some(Ok(()))));
+
+    // A put with a different value is not
+    let mut put_fut = multiplex.put(&ctx, "k".to_owned(), v2.clone()).boxed();
+    assert_pending(&mut put_fut).await;
+    tickable_queue.tick(None);
+    for (_id, store) in &tickable_blobstores {
+        store.tick(None);
+    }
+    assert!(put_fut.await.is_ok());
+    for (_id, store) in &tickable_blobstores {
+        assert_eq!(store.get_bytes("k"), Some(v2.clone()));
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_put_dedup_if_absent(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+    let multiplex = multiplex.with_put_dedup(PutDedupConfig {
+        ttl: Duration::from_secs(60),
+        capacity: 10,
+    });
+    let v = make_value("v");
+
+    // None of the blobstores have the key, the first put writes it everywhere
+    let mut put_fut = multiplex
+        .put_explicit(&ctx, "k".to_owned(), v.clone(), PutBehaviour::IfAbsent)
+        .boxed();
+    assert_pending(&mut put_fut).await;
+    tickable_queue.tick(None);
+    assert_pending(&mut put_fut).await;
+    for (_id, store) in &tickable_blobstores {
+        store.tick(None);
+    }
+    assert_eq!(put_fut.await?, OverwriteStatus::NotChecked);
+    tokio::task::yield_now().await;
+    tickable_queue.tick(None);
+    tokio::task::yield_now().await;
+    assert!(queue_keys(&ctx, &multiplex).await?.is_empty());
+
+    // The second put is skipped, and reports that the key was already there rather than
+    // the status of the first put
+    let put_fut = multiplex.put_explicit(&ctx, "k".to_owned(), v.clone(), PutBehaviour::IfAbsent);
+    assert!(matches!(
+        put_fut.now_or_never(),
+        Some(Ok(OverwriteStatus::Prevented))
+    ));
+
+    // A repeated logged overwrite reports that it overwrote the key
+    let put_fut = multiplex.put_explicit(
+        &ctx,
+        "k".to_owned(),
+        v.clone(),
+        PutBehaviour::OverwriteAndLog,
+    );
+    let mut put_fut = put_fut.boxed();
+    assert_pending(&mut put_fut).await;
+    tickable_queue.tick(None);
+    for (_id, store) in &tickable_blobstores {
+        store.tick(None);
+    }
+    put_fut.await?;
+    tokio::task::yield_now().await;
+    tickable_queue.tick(None);
+    tokio::task::yield_now().await;
+    let put_fut = multiplex.put_explicit(
+        &ctx,
+        "k".to_owned(),
+        v.clone(),
+        PutBehaviour::OverwriteAndLog,
+    );
+    assert!(matches!(
+        put_fut.now_or_never(),
+        Some(Ok(OverwriteStatus::Overwrote))
+    ));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_put_coalescing(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (stores, multiplex) = setup_faulty_multiplex(3, 2, None)?;
+    let multiplex = multiplex.with_put_coalescing();
+    let v1 = make_value("v1");
+    let v2 = make_value("v2");
+
+    // The puts overlap, and the third blobstore fails them so that they stay in the WAL
+    for (_id, store) in &stores[..2] {
+        store.set_fault("k".to_owned(), Fault::Delay(Duration::from_millis(10)));
+    }
+    stores[2]
+        .1
+        .set_fault("k".to_owned(), Fault::Fail("bs2 failed".to_owned()));
+
+    // The identical puts share a single write, the put of another value for the same key
+    // doesn't wait for it
+    let num_puts = 10;
+    let puts = (0..num_puts)
+        .map(|_| v1.clone())
+        .chain(std::iter::once(v2.clone()))
+        .map(|value| multiplex.put(&ctx, "k".to_owned(), value));
+    futures::future::try_join_all(puts).await?;
+
+    assert_eq!(queue_keys(&ctx, &multiplex).await?, vec!["k", "k"]);
+    for (_id, store) in &stores[..2] {
+        assert!(store.max_concurrent_puts() <= 2);
+    }
+
+    // Once the put completed, the same put is written again
+    multiplex.put(&ctx, "k".to_owned(), v1.clone()).await?;
+    assert_eq!(queue_keys(&ctx, &multiplex).await?, vec!["k", "k", "k"]);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_put_priority(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let bg_ctx = CoreContext::test_mock_session(
+        SessionContainer::builder(fb)
+            .session_class(SessionClass::Background)
+            .build(),
+    );
+    let wal_queue = Arc::new(SqlBlobstoreWal::with_sqlite_in_memory()?);
+    let stores: Vec<_> = (0..2).map(|_| Arc::new(FaultyBlobstore::new())).collect();
+    let write_only_store = Arc::new(FaultyBlobstore::new());
+    let scuba = Scuba::new(
+        MononokeScubaSampleBuilder::with_discard(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )?;
+    let multiplex = WalMultiplexedBlobstore::new(
+        MultiplexId::new(1),
+        wal_queue,
+        stores
+            .iter()
+            .enumerate()
+            .map(|(id, store)| {
+                (
+                    BlobstoreId::new(id as u64),
+                    store.clone() as Arc<dyn BlobstorePutOps>,
+                )
+            })
+            .collect(),
+        vec![(
+            BlobstoreId::new(2),
+            write_only_store.clone() as Arc<dyn BlobstorePutOps>,
+        )],
+        1,
+        None,
+        scuba,
+    )?;
+
+    for (ctx, k, priority) in [
+        (&ctx, "k_interactive", BlobstoreWalPriority::Interactive),
+        (&bg_ctx, "k_background", BlobstoreWalPriority::Background),
+    ] {
+        // The first blobstore is slow, the second one fails so that the entry stays in the WAL
+        stores[0].set_fault(k, Fault::Delay(Duration::from_millis(200)));
+        stores[1].set_fault(k, Fault::Fail("bs1 failed".to_owned()));
+
+        let mut put_fut = multiplex.put(ctx, k.to_owned(), make_value("v")).boxed();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), &mut put_fut)
+                .await
+                .is_err()
+        );
+        // Interactive puts write to the write-mostly blobstore straight away, background ones
+        // once the quorum is reached
+        let written_early = write_only_store.get_bytes(ctx, k).await?.is_some();
+        assert_eq!(written_early, priority == BlobstoreWalPriority::Interactive);
+        put_fut.await?;
+        multiplex.drain(Duration::from_secs(60)).await?;
+
+        // The blobstores see the priority of the put, except for the writes no one waits for
+        assert_eq!(stores[0].put_priority(k), Some(priority));
+        assert_eq!(
+            write_only_store.put_priority(k),
+            Some(BlobstoreWalPriority::Background)
+        );
+        assert_eq!(
+            write_only_store.get_bytes(ctx, k).await?,
+            Some(make_value("v"))
+        );
+    }
+
+    // The WAL entries keep the priority of their put
+    let mut entries: Vec<_> = multiplex
+        .wal_queue
+        .read(&ctx, &multiplex.multiplex_id, &Timestamp::now(), 100)
+        .await?
+        .into_iter()
+        .map(|e| (e.blobstore_key, e.priority))
+        .collect();
+    entries.sort_unstable_by(|(k1, _), (k2, _)| k1.cmp(k2));
+    assert_eq!(
+        entries,
+        vec![
+            ("k_background".to_owned(), BlobstoreWalPriority::Background),
+            (
+                "k_interactive".to_owned(),
+                BlobstoreWalPriority::Interactive
+            ),
+        ]
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_drain(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(3, 2, None)?;
+
+    // Nothing to wait for
+    multiplex.drain(Duration::from_secs(1)).await?;
+
+    // The put returns once the quorum is reached, the third write completes in the background
+    let mut put_fut = multiplex.put(&ctx, "k".to_owned(), make_value("v")).boxed();
+    assert_pending(&mut put_fut).await;
+    tickable_queue.tick(None);
+    assert_pending(&mut put_fut).await;
+    tickable_blobstores[0].1.tick(None);
+    tickable_blobstores[1].1.tick(None);
+    assert!(put_fut.await.is_ok());
+
+    // The third blobstore is slow: the drain times out
+    assert!(multiplex.drain(Duration::from_millis(10)).await.is_err());
+
+    // The drain waits for the third write, then for the removal of the entry from the WAL
+    let mut drain_fut = multiplex.drain(Duration::from_secs(60)).boxed();
+    assert_pending(&mut drain_fut).await;
+    tickable_blobstores[2].1.tick(None);
+    tokio::task::yield_now().await;
+    assert_pending(&mut drain_fut).await;
+    tickable_queue.tick(None);
+    drain_fut.await?;
+
+    assert!(queue_keys(&ctx, &multiplex).await?.is_empty());
+    assert_eq!(
+        tickable_blobstores[2].1.get_bytes("k"),
+        Some(make_value("v"))
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_max_background_writes(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let wal_queue = Arc::new(SqlBlobstoreWal::with_sqlite_in_memory()?);
+    let main_store = Arc::new(FaultyBlobstore::new());
+    let write_only_store = Arc::new(FaultyBlobstore::new());
+    let scuba = Scuba::new(
+        MononokeScubaSampleBuilder::with_discard(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )?;
+    let max_background_writes = 3;
+    let multiplex = WalMultiplexedBlobstore::new(
+        MultiplexId::new(1),
+        wal_queue,
+        vec![(BlobstoreId::new(0), main_store as Arc<dyn BlobstorePutOps>)],
+        // The same store behind both write-mostly blobstores counts all their writes in flight.
+        vec![
+            (
+                BlobstoreId::new(1),
+                write_only_store.clone() as Arc<dyn BlobstorePutOps>,
+            ),
+            (
+                BlobstoreId::new(2),
+                write_only_store.clone() as Arc<dyn BlobstorePutOps>,
+            ),
+        ],
+        1,
+        None,
+        scuba,
+    )?
+    .with_max_background_writes(max_background_writes)?;
+
+    // Nothing could run
+    let (_, other_multiplex) = setup_faulty_multiplex(1, 1, None)?;
+    assert!(other_multiplex.with_max_background_writes(0).is_err());
+
+    // The puts return once the main blobstore has the blob, the slow write-mostly blobstores
+    // are written to in the background, each write taking a slot.
+    let keys: Vec<_> = (0..20).map(|i| format!("k{}", i)).collect();
+    for key in &keys {
+        write_only_store.set_fault(key.clone(), Fault::Delay(Duration::from_millis(10)));
+    }
+    futures::future::try_join_all(
+        keys.iter()
+            .map(|key| multiplex.put(&ctx, key.clone(), make_value(key))),
+    )
+    .await?;
+
+    multiplex.drain(Duration::from_secs(60)).await?;
+    assert_eq!(
+        write_only_store.max_concurrent_puts(),
+        max_background_writes
+    );
+    for key in &keys {
+        assert_eq!(
+            write_only_store.get_bytes(&ctx, key).await?,
+            Some(make_value(key))
+        );
+    }
+
+    // The writes that are still in progress once a put reached its quorum take a slot too
+    let (tickable_queue, tickable_blobstores, multiplex) = setup_multiplex(2, 1, None)?;
+    let multiplex = multiplex.with_max_background_writes(1)?;
+    for key in ["k0", "k1"] {
+        let mut put_fut = multiplex.put(&ctx, key.to_owned(), make_value(key)).boxed();
+        assert_pending(&mut put_fut).await;
+        tickable_queue.tick(None);
+        assert_pending(&mut put_fut).await;
+        tickable_blobstores[0].1.tick(None);
+        assert!(put_fut.await.is_ok());
+    }
+    // The second blobstore is slow: its write of "k0" holds the only slot
+    assert_eq!(multiplex.background_writes.queued(), 1);
+
+    let mut drain_fut = multiplex.drain(Duration::from_secs(60)).boxed();
+    assert_pending(&mut drain_fut).await;
+    tickable_blobstores[1].1.tick(None);
+    // Tick the removals of the entries from the WAL as they come
+    loop {
+        match futures::poll!(&mut drain_fut) {
+            Poll::Ready(result) => break result?,
+            Poll::Pending => {
+                tokio::task::yield_now().await;
+                tickable_queue.tick(None);
+            }
+        }
+    }
+    assert_eq!(multiplex.background_writes.queued(), 0);
+    assert!(queue_keys(&ctx, &multiplex).await?.is_empty());
+    for key in ["k0", "k1"] {
+        assert_eq!(
+            tickable_blobstores[1].1.get_bytes(key),
+            Some(make_value(key))
+        );
+    }
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_faulty_blobstores(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let timeout = MultiplexTimeout::new(
+        Some(Duration::from_millis(100)), /* read */
+        Some(Duration::from_millis(100)), /* write */
+    );
+    let (stores, multiplex) = setup_faulty_multiplex(3, 2, Some(timeout))?;
+    let v = make_value("v");
+
+    // A failing blobstore doesn't prevent the quorum, but the key is left in the WAL to be healed
+    stores[0]
+        .1
+        .set_fault("fail", Fault::Fail("bs0 failed".to_owned()));
+    multiplex.put(&ctx, "fail".to_owned(), v.clone()).await?;
+    multiplex.drain(Duration::from_secs(1)).await?;
+    assert_eq!(&queue_keys(&ctx, &multiplex).await?, &["fail"]);
+    assert_eq!(stores[0].1.get_bytes(&ctx, "fail").await?, None);
+    assert_eq!(
+        multiplex.get(&ctx, "fail").await?.map(|d| d.into_bytes()),
+        Some(v.clone())
+    );
+
+    // A slow blobstore is waited for
+    stores[2]
+        .1
+        .set_fault("delay", Fault::Delay(Duration::from_millis(10)));
+    multiplex.put(&ctx, "delay".to_owned(), v.clone()).await?;
+    multiplex.drain(Duration::from_secs(1)).await?;
+    for (_id, store) in &stores {
+        assert_eq!(store.get_bytes(&ctx, "delay").await?, Some(v.clone()));
+    }
+
+    // Hanging blobstores time out, and without a quorum the put fails
+    stores[0].1.set_fault("hang", Fault::Hang);
+    stores[1].1.set_fault("hang", Fault::Hang);
+    assert!(
+        multiplex
+            .put(&ctx, "hang".to_owned(), v.clone())
+            .await
+            .is_err()
+    );
+
+    // A corrupt blobstore returns something else than what was written
+    stores[1].1.set_fault("delay", Fault::Corrupt);
+    let corrupted = stores[1]
+        .1
+        .get(&ctx, "delay")
+        .await?
+        .map(|d| d.into_bytes());
+    assert!(corrupted.is_some());
+    assert_ne!(corrupted, Some(v.clone()));
+    stores[1].1.clear_fault("delay");
+    let fixed = stores[1]
+        .1
+        .get(&ctx, "delay")
+        .await?
+        .map(|d| d.into_bytes());
+    assert_eq!(fixed, Some(v));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_check_consistency(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (stores, multiplex) = setup_faulty_multiplex(3, 2, None)?;
+    let v = make_value("v");
+
+    // All the blobstores have the key
+    multiplex.put(&ctx, "k".to_owned(), v.clone()).await?;
+    multiplex.drain(Duration::from_secs(1)).await?;
+    let report = multiplex.check_consistency(&ctx, "k").await?;
+    assert!(report.is_consistent());
+    assert_eq!(report.stores.len(), 3);
+    assert!(matches!(report.majority(), Some(KeyState::Present { .. })));
+
+    // None of the blobstores have the key
+    let report = multiplex.check_consistency(&ctx, "missing").await?;
+    assert!(report.is_consistent());
+    assert_eq!(report.majority(), Some(&KeyState::Absent));
+
+    // One of the blobstores lacks the key
+    for (_id, store) in &stores[1..] {
+        store.put(&ctx, "partial".to_owned(), v.clone()).await?;
+    }
+    let report = multiplex.check_consistency(&ctx, "partial").await?;
+    assert!(!report.is_consistent());
+    assert_eq!(report.stores[0], (stores[0].0, KeyState::Absent));
+    assert_eq!(report.disagreeing(), vec![stores[0].0]);
+    assert!(report.failed().is_empty());
+
+    // One of the blobstores has different content, and another one fails
+    stores[1].1.set_fault("k", Fault::Corrupt);
+    stores[2]
+        .1
+        .set_fault("k", Fault::Fail("bs2 failed".to_owned()));
+    let report = multiplex.check_consistency(&ctx, "k").await?;
+    assert!(!report.is_consistent());
+    assert_eq!(report.failed(), vec![stores[2].0]);
+    assert_eq!(report.disagreeing(), vec![stores[1].0]);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_put_all_with_deadline(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (stores, multiplex) = setup_faulty_multiplex(3, 2, None)?;
+    let v = make_value("v");
+
+    // A slow blobstore is waited for, beyond the write quorum
+    stores[2]
+        .1
+        .set_fault("slow", Fault::Delay(Duration::from_millis(50)));
+    let unconfirmed = multiplex
+        .put_all_with_deadline(
+            &ctx,
+            "slow".to_owned(),
+            v.clone(),
+            Duration::from_secs(5),
+            true,
+        )
+        .await?;
+    assert!(unconfirmed.is_empty());
+    for (_id, store) in &stores {
+        assert_eq!(store.get_bytes(&ctx, "slow").await?, Some(v.clone()));
+    }
+    // Every blobstore has the blob, there is nothing left to heal
+    multiplex.drain(Duration::from_secs(1)).await?;
+    assert!(queue_keys(&ctx, &multiplex).await?.is_empty());
+
+    // A blobstore missing the deadline is reported, and healed from the WAL later on
+    stores[2].1.set_fault("hang", Fault::Hang);
+    let unconfirmed = multiplex
+        .put_all_with_deadline(
+            &ctx,
+            "hang".to_owned(),
+            v.clone(),
+            Duration::from_millis(50),
+            true,
+        )
+        .await?;
+    assert_eq!(unconfirmed, HashSet::from([stores[2].0]));
+    for (_id, store) in &stores[..2] {
+        assert_eq!(store.get_bytes(&ctx, "hang").await?, Some(v.clone()));
+    }
+    assert_eq!(&queue_keys(&ctx, &multiplex).await?, &["hang"]);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_get_if_present(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    // The read quorum is all of the blobstores
+    let (stores, multiplex) = setup_faulty_multiplex(3, 1, None)?;
+    let v = make_value("v");
+
+    // The key is present, so it's read
+    multiplex.put(&ctx, "present".to_owned(), v.clone()).await?;
+    multiplex.drain(Duration::from_secs(1)).await?;
+    assert_eq!(
+        multiplex
+            .get_if_present(&ctx, "present")
+            .await?
+            .map(|d| d.into_bytes()),
+        Some(v)
+    );
+    assert_eq!(
+        ctx.perf_counters().get_counter(PerfCounterType::BlobGets),
+        1
+    );
+
+    // The key is confirmed absent, so it isn't read
+    assert_eq!(multiplex.get_if_present(&ctx, "absent").await?, None);
+    assert_eq!(
+        ctx.perf_counters().get_counter(PerfCounterType::BlobGets),
+        1
+    );
+
+    // Without a quorum on the key being absent, it's read, and the read fails the same way
+    // instead of reporting the key as missing
+    stores[0]
+        .1
+        .set_fault("inconclusive", Fault::Fail("bs0 failed".to_owned()));
+    assert_is_present_ok(
+        multiplex.is_present(&ctx, "inconclusive").await,
+        BlobstoreIsPresent::ProbablyNotPresent(anyhow!("some failed!")),
+    );
+    assert!(
+        multiplex
+            .get_if_present(&ctx, "inconclusive")
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        ctx.perf_counters().get_counter(PerfCounterType::BlobGets),
+        2
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_trace_key(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let (stores, multiplex) = setup_faulty_multiplex(3, 3, None)?;
+    let v = make_value("v");
+
+    // Every blobstore is written to, and only the traced key is recorded
+    let (result, trace) = multiplex
+        .trace_key(&ctx, "k", |ctx| {
+            borrowed!(multiplex, v);
+            async move {
+                multiplex.put(&ctx, "k".to_owned(), v.clone()).await?;
+                multiplex.put(&ctx, "other".to_owned(), v.clone()).await
+            }
+        })
+        .await;
+    result?;
+    assert_eq!(trace.len(), stores.len());
+    let mut traced_ids: Vec<_> = trace.iter().map(|entry| entry.blobstore_id).collect();
+    traced_ids.sort();
+    assert_eq!(
+        traced_ids,
+        stores.iter().map(|(id, _store)| *id).collect::<Vec<_>>()
+    );
+    for entry in &trace {
+        assert_eq!(entry.operation, OperationType::Put);
+        assert_eq!(entry.outcome, TraceOutcome::Written);
+    }
+
+    // The failures are recorded with their error
+    stores[1]
+        .1
+        .set_fault("k", Fault::Fail("bs1 failed".to_owned()));
+    let (result, trace) = multiplex
+        .trace_key(&ctx, "k", |ctx| {
+            borrowed!(multiplex);
+            async move { multiplex.check_consistency(&ctx, "k").await }
+        })
+        .await;
+    result?;
+    let outcomes: HashMap<_, _> = trace
+        .into_iter()
+        .map(|entry| (entry.blobstore_id, entry.outcome))
+        .collect();
+    assert_eq!(outcomes.len(), 3);
+    assert_eq!(
+        outcomes[&stores[0].0],
+        TraceOutcome::Found { size: v.len() }
+    );
+    assert!(
+        matches!(&outcomes[&stores[1].0], TraceOutcome::Failed(err) if err.contains("bs1 failed"))
+    );
+
+    // Outside of a trace, nothing is recorded
+    multiplex.put(&ctx, "k".to_owned(), v.clone()).await?;
+    let ((), trace) = multiplex.trace_key(&ctx, "k", |_ctx| async {}).await;
+    assert!(trace.is_empty());
+
+    Ok(())
+}
+
+async fn assert_pending<T: Debug>(fut: &mut (impl Future<Output = T> + Unpin)) {
+    match futures::poll!(fut) {
+        Poll::Pending => {}
+        state => {
+            panic!("future must be pending, received: {:?}", state);
+        }
+    }
+}
+
+fn setup_multiplex(
+    num: u64,
+    quorum: usize,
+    timeout: Option<MultiplexTimeout>,
+) -> Result<(
+    Arc<Tickable<BlobstoreWalEntry>>,
+    Vec<(BlobstoreId, Arc<Tickable<(BlobstoreBytes, u64)>>)>,
+    WalMultiplexedBlobstore,
+)> {
+    let (tickable_queue, wal_queue) = setup_queue();
+    let (tickable_blobstores, blobstores) = setup_blobstores(num);
+    let scuba = Scuba::new(
+        MononokeScubaSampleBuilder::with_discard(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )?;
+    let multiplex = WalMultiplexedBlobstore::new(
+        MultiplexId::new(1),
+        wal_queue,
+        blobstores,
+        vec![],
+        quorum,
+        timeout,
+        scuba,
+    )?;
+
+    Ok((tickable_queue, tickable_blobstores, multiplex))
+}
+
+/// Sets up a multiplex over `num` `FaultyBlobstore`s, with an in-memory WAL.
+fn setup_faulty_multiplex(
+    num: u64,
+    quorum: usize,
+    timeout: Option<MultiplexTimeout>,
+) -> Result<(
+    Vec<(BlobstoreId, Arc<FaultyBlobstore>)>,
+    WalMultiplexedBlobstore,
+)> {
+    let wal_queue = Arc::new(SqlBlobstoreWal::with_sqlite_in_memory()?);
+    let faulty_blobstores: Vec<_> = (0..num)
+        .map(|id| (BlobstoreId::new(id), Arc::new(FaultyBlobstore::new())))
+        .collect();
+    let blobstores = faulty_blobstores
+        .iter()
+        .map(|(id, store)| (*id, store.clone() as Arc<dyn BlobstorePutOps>))
+        .collect();
+    let scuba = Scuba::new(
+        MononokeScubaSampleBuilder::with_discard(),
+        MononokeScubaSampleBuilder::with_discard(),
+        nonzero!(1u64),
+    )?;
+    let multiplex = WalMultiplexedBlobstore::new(
+        MultiplexId::new(1),
+        wal_queue,
+        blobstores,
+        vec![],
+        quorum,
+        timeout,
+        scuba,
+    )?;
+
+    Ok((faulty_blobstores, multiplex))
 }
 
 type TickableBytes = Tickable<(BlobstoreBytes, u64)>;
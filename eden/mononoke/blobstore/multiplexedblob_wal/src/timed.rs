@@ -8,6 +8,7 @@
 use std::fmt;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Error;
 use anyhow::Result;
@@ -29,6 +30,10 @@ use mononoke_types::BlobstoreBytes;
 use scuba_ext::MononokeScubaSampleBuilder;
 use tokio::time::timeout;
 
+use crate::retry::MultiplexRetry;
+use crate::trace::KeyTracer;
+use crate::trace::TraceOutcome;
+
 // inferred from the current timeout, see https://fburl.com/code/rgj8497o
 const GET_REQUEST_TIMEOUT: Duration = Duration::from_secs(100);
 const PUT_REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
@@ -61,6 +66,10 @@ pub(crate) struct TimedStore {
     inner: Arc<dyn BlobstorePutOps>,
     /// Timeout enforced on the read/write futures, including those running in the background
     timeout: MultiplexTimeout,
+    /// Retries of the transient failures, each attempt is subject to the timeout
+    retry: Option<MultiplexRetry>,
+    /// Records the calls on the traced keys
+    tracer: Arc<KeyTracer>,
 }
 
 impl fmt::Debug for TimedStore {
@@ -80,8 +89,19 @@ impl TimedStore {
         id: BlobstoreId,
         inner: Arc<dyn BlobstorePutOps>,
         timeout: MultiplexTimeout,
+        tracer: Arc<KeyTracer>,
     ) -> Self {
-        Self { id, inner, timeout }
+        Self {
+            id,
+            inner,
+            timeout,
+            retry: None,
+            tracer,
+        }
+    }
+
+    pub(crate) fn with_retry(self, retry: Option<MultiplexRetry>) -> Self {
+        Self { retry, ..self }
     }
 
     pub(crate) fn id(&self) -> &BlobstoreId {
@@ -97,15 +117,30 @@ impl TimedStore {
         mut scuba: MononokeScubaSampleBuilder,
     ) -> Result<OverwriteStatus, (BlobstoreId, Error)> {
         let size = value.len();
-        let put_fut = if let Some(put_behaviour) = put_behaviour {
-            self.inner
-                .put_explicit(ctx, key.clone(), value, put_behaviour)
-        } else {
-            self.inner.put_with_status(ctx, key.clone(), value)
-        };
+        let put_fut = self.retried(|| {
+            let put_fut = if let Some(put_behaviour) = put_behaviour {
+                self.inner
+                    .put_explicit(ctx, key.clone(), value.clone(), put_behaviour)
+            } else {
+                self.inner.put_with_status(ctx, key.clone(), value.clone())
+            };
+            with_timeout(put_fut, self.timeout.write)
+        });
 
         let pc = ctx.clone().fork_perf_counters();
-        let (stats, result) = with_timeout(put_fut, self.timeout.write).timed().await;
+        let start = Instant::now();
+        let (stats, result) = put_fut.timed().await;
+        self.tracer.record(
+            ctx,
+            &key,
+            self.id,
+            OperationType::Put,
+            start,
+            || match &result {
+                Ok(_) => TraceOutcome::Written,
+                Err(err) => TraceOutcome::Failed(format!("{:#}", err)),
+            },
+        );
 
         record_put_stats(
             &mut scuba,
@@ -123,6 +158,24 @@ impl TimedStore {
         result.map_err(|er| (self.id.clone(), er))
     }
 
+    /// Copy `old_key` to `new_key` within this store, using its native copy when it has one.
+    pub(crate) async fn copy(
+        &self,
+        ctx: &CoreContext,
+        old_key: &str,
+        new_key: String,
+    ) -> Result<OverwriteStatus, (BlobstoreId, Error)> {
+        self.retried(|| {
+            with_timeout(
+                self.inner.copy(ctx, old_key, new_key.clone()),
+                self.timeout.write,
+            )
+        })
+        .await
+        .map(|()| OverwriteStatus::NotChecked)
+        .map_err(|er| (self.id.clone(), er))
+    }
+
     pub(crate) async fn get(
         &self,
         ctx: &CoreContext,
@@ -131,9 +184,17 @@ impl TimedStore {
         mut scuba: MononokeScubaSampleBuilder,
     ) -> Result<Option<BlobstoreGetData>, Error> {
         let pc = ctx.clone().fork_perf_counters();
-        let (stats, result) = with_timeout(self.inner.get(ctx, key), self.timeout.read)
+        let start = Instant::now();
+        let (stats, result) = self
+            .retried(|| with_timeout(self.inner.get(ctx, key), self.timeout.read))
             .timed()
             .await;
+        self.tracer
+            .record(ctx, key, self.id, operation, start, || match &result {
+                Ok(Some(data)) => TraceOutcome::Found { size: data.len() },
+                Ok(None) => TraceOutcome::Absent,
+                Err(err) => TraceOutcome::Failed(format!("{:#}", err)),
+            });
 
         record_get_stats(
             &mut scuba,
@@ -157,9 +218,25 @@ impl TimedStore {
         mut scuba: MononokeScubaSampleBuilder,
     ) -> (BlobstoreId, Result<BlobstoreIsPresent>) {
         let pc = ctx.clone().fork_perf_counters();
-        let (stats, result) = with_timeout(self.inner.is_present(ctx, key), self.timeout.read)
+        let start = Instant::now();
+        let (stats, result) = self
+            .retried(|| with_timeout(self.inner.is_present(ctx, key), self.timeout.read))
             .timed()
             .await;
+        self.tracer.record(
+            ctx,
+            key,
+            self.id,
+            OperationType::IsPresent,
+            start,
+            || match &result {
+                Ok(BlobstoreIsPresent::Present) => TraceOutcome::Present,
+                Ok(BlobstoreIsPresent::Absent) => TraceOutcome::Absent,
+                Ok(BlobstoreIsPresent::ProbablyNotPresent(err)) | Err(err) => {
+                    TraceOutcome::Failed(format!("{:#}", err))
+                }
+            },
+        );
 
         record_is_present_stats(
             &mut scuba,
@@ -174,15 +251,48 @@ impl TimedStore {
 
         (self.id.clone(), result)
     }
+
+    /// Lightweight `is_present` call used to check the health of the store, without retries
+    /// nor logging.
+    pub(crate) async fn probe(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+        to: Duration,
+    ) -> Result<BlobstoreIsPresent> {
+        with_timeout(self.inner.is_present(ctx, key), to).await
+    }
+
+    async fn retried<T, Fut>(&self, mut op: impl FnMut() -> Fut + Send) -> Result<T>
+    where
+        T: Send + 'static,
+        Fut: Future<Output = Result<T>>,
+    {
+        match &self.retry {
+            Some(retry) => retry.run(op).await,
+            None => op().await,
+        }
+    }
 }
 
 pub(crate) fn with_timed_stores(
     blobstores: Vec<(BlobstoreId, Arc<dyn BlobstorePutOps>)>,
     to: MultiplexTimeout,
+    tracer: &Arc<KeyTracer>,
 ) -> Vec<TimedStore> {
     blobstores
         .into_iter()
-        .map(|(id, bs)| TimedStore::new(id, bs, to.clone()))
+        .map(|(id, bs)| TimedStore::new(id, bs, to.clone(), tracer.clone()))
+        .collect()
+}
+
+pub(crate) fn with_retried_stores(
+    blobstores: &[TimedStore],
+    retry: Option<MultiplexRetry>,
+) -> Vec<TimedStore> {
+    blobstores
+        .iter()
+        .map(|bs| bs.clone().with_retry(retry.clone()))
         .collect()
 }
 
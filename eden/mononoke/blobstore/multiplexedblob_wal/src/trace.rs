@@ -0,0 +1,160 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use blobstore_stats::OperationType;
+use context::CoreContext;
+use context::SamplingKey;
+use futures::Future;
+use metaconfig_types::BlobstoreId;
+
+use crate::WalMultiplexedBlobstore;
+
+/// What an underlying blobstore answered to a traced call.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TraceOutcome {
+    /// The blob was read, with this size.
+    Found { size: usize },
+    /// The blob is present, as answered to `is_present`.
+    Present,
+    /// The blob wasn't found.
+    Absent,
+    /// The blob was written.
+    Written,
+    /// The call failed.
+    Failed(String),
+}
+
+/// A call to one of the underlying blobstores, as recorded by
+/// `WalMultiplexedBlobstore::trace_key`.
+#[derive(Clone, Debug)]
+pub struct TraceEntry {
+    pub blobstore_id: BlobstoreId,
+    pub operation: OperationType,
+    pub start: Instant,
+    pub duration: Duration,
+    pub outcome: TraceOutcome,
+}
+
+struct Trace {
+    key: String,
+    entries: Vec<TraceEntry>,
+}
+
+/// The traces in progress, by the sampling key of the context they were started with.
+#[derive(Default)]
+pub(crate) struct KeyTracer {
+    /// Number of traces in progress, so that nothing is locked when there are none.
+    active: AtomicUsize,
+    traces: Mutex<HashMap<SamplingKey, Trace>>,
+}
+
+impl KeyTracer {
+    /// Record the call to `blobstore_id` on `key` that started at `start`, if it is traced for
+    /// the sampling key of `ctx`.
+    pub(crate) fn record(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+        blobstore_id: BlobstoreId,
+        operation: OperationType,
+        start: Instant,
+        outcome: impl FnOnce() -> TraceOutcome,
+    ) {
+        if self.active.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let sampling_key = match ctx.sampling_key() {
+            Some(sampling_key) => sampling_key,
+            None => return,
+        };
+        let mut traces = self.traces.lock().expect("lock poisoned");
+        if let Some(trace) = traces.get_mut(sampling_key) {
+            if trace.key == key {
+                trace.entries.push(TraceEntry {
+                    blobstore_id,
+                    operation,
+                    start,
+                    duration: start.elapsed(),
+                    outcome: outcome(),
+                });
+            }
+        }
+    }
+
+    fn start(&self, sampling_key: SamplingKey, key: &str) -> ActiveTrace<'_> {
+        self.traces.lock().expect("lock poisoned").insert(
+            sampling_key,
+            Trace {
+                key: key.to_owned(),
+                entries: Vec::new(),
+            },
+        );
+        self.active.fetch_add(1, Ordering::Relaxed);
+        ActiveTrace {
+            tracer: self,
+            sampling_key,
+        }
+    }
+}
+
+/// Stops the trace when dropped, e.g. when the traced operation is cancelled.
+struct ActiveTrace<'a> {
+    tracer: &'a KeyTracer,
+    sampling_key: SamplingKey,
+}
+
+impl ActiveTrace<'_> {
+    fn finish(self) -> Vec<TraceEntry> {
+        self.tracer
+            .traces
+            .lock()
+            .expect("lock poisoned")
+            .get_mut(&self.sampling_key)
+            .map_or_else(Vec::new, |trace| std::mem::take(&mut trace.entries))
+    }
+}
+
+impl Drop for ActiveTrace<'_> {
+    fn drop(&mut self) {
+        self.tracer
+            .traces
+            .lock()
+            .expect("lock poisoned")
+            .remove(&self.sampling_key);
+        self.tracer.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl WalMultiplexedBlobstore {
+    /// Run `op` with a copy of `ctx` tagged with a new sampling key, and return alongside its
+    /// result the calls made with that context to the underlying blobstores on `key`, in the
+    /// order they completed. This is meant for debugging a specific key: calls are only recorded
+    /// while a trace is in progress.
+    ///
+    /// The writes still running in the background when `op` completes aren't in the trace.
+    pub async fn trace_key<T, Fut>(
+        &self,
+        ctx: &CoreContext,
+        key: &str,
+        op: impl FnOnce(CoreContext) -> Fut,
+    ) -> (T, Vec<TraceEntry>)
+    where
+        Fut: Future<Output = T>,
+    {
+        let sampling_key = SamplingKey::new();
+        let trace = self.key_tracer.start(sampling_key, key);
+        let result = op(ctx.clone_and_sample(sampling_key)).await;
+        (result, trace.finish())
+    }
+}
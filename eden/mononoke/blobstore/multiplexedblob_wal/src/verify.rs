@@ -0,0 +1,55 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use anyhow::anyhow;
+use anyhow::Result;
+use blobstore::BlobstoreGetData;
+use mononoke_types::BlobstoreKey;
+use mononoke_types::BlobstoreValue;
+use mononoke_types::ContentChunk;
+use mononoke_types::FileContents;
+
+/// Check that the blob hashes to the content hash encoded in its key. Only two key families are
+/// verified:
+/// - `content.blake2.<hash>`, for the files stored in a single blob;
+/// - `chunk.blake2.<hash>`, for the chunks of the files stored in several blobs.
+///
+/// All the other blobs pass the check unverified, including the `content` blobs of chunked files,
+/// whose hash is the one of all their chunks, and the other `blake2` keys, such as changesets.
+pub(crate) fn verify_content_hash(key: &str, data: &BlobstoreGetData) -> Result<()> {
+    let key_type = match key.rsplit_once(".blake2.") {
+        Some((prefix, _hash)) => prefix.rsplit('.').next().unwrap_or(prefix),
+        None => return Ok(()),
+    };
+    let bytes = data.as_raw_bytes().clone();
+    let expected_key = match key_type {
+        "content" => match FileContents::from_encoded_bytes(bytes)? {
+            // The hash of a chunked file is recorded in its blob, checking it would mean
+            // reading all its chunks.
+            FileContents::Chunked(_) => return Ok(()),
+            contents => contents.into_blob().id().blobstore_key(),
+        },
+        "chunk" => ContentChunk::from_encoded_bytes(bytes)?
+            .into_blob()
+            .id()
+            .blobstore_key(),
+        _ => return Ok(()),
+    };
+
+    let matches = key
+        .strip_suffix(&expected_key)
+        .map_or(false, |prefix| prefix.is_empty() || prefix.ends_with('.'));
+    if matches {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Blob content doesn't match its key {}, it hashes to {}",
+            key,
+            expected_key
+        ))
+    }
+}
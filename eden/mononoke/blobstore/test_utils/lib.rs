@@ -9,8 +9,11 @@ use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::fmt;
 use std::future::Future;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 use std::time::SystemTime;
 
 use anyhow::bail;
@@ -24,15 +27,19 @@ use blobstore::OverwriteStatus;
 use blobstore::PutBehaviour;
 use blobstore_sync_queue::BlobstoreWal;
 use blobstore_sync_queue::BlobstoreWalEntry;
+use blobstore_sync_queue::BlobstoreWalPriority;
 use context::CoreContext;
 use futures::channel::oneshot;
+use futures::future;
 use futures::stream;
 use futures::StreamExt;
 use futures::TryStreamExt;
 use lock_ext::LockExt;
+use memblob::Memblob;
 use metaconfig_types::MultiplexId;
 use mononoke_types::BlobstoreBytes;
 use mononoke_types::Timestamp;
+
 pub struct Tickable<T> {
     pub storage: Arc<Mutex<HashMap<String, T>>>,
     // queue of pending operations
@@ -149,13 +156,19 @@ impl BlobstorePutOps for Tickable<(BlobstoreBytes, u64)> {
         put_behaviour: PutBehaviour,
     ) -> Result<OverwriteStatus> {
         self.on_tick().await?;
-        if put_behaviour == PutBehaviour::IfAbsent {
-            if self.storage.with(|s| s.contains_key(&key)) {
+        let status = match put_behaviour {
+            PutBehaviour::Overwrite => OverwriteStatus::NotChecked,
+            PutBehaviour::OverwriteAndLog if self.storage.with(|s| s.contains_key(&key)) => {
+                OverwriteStatus::Overwrote
+            }
+            PutBehaviour::OverwriteAndLog => OverwriteStatus::New,
+            PutBehaviour::IfAbsent if self.storage.with(|s| s.contains_key(&key)) => {
                 return Ok(OverwriteStatus::Prevented);
             }
-        }
+            PutBehaviour::IfAbsent => OverwriteStatus::NotChecked,
+        };
         self.add_bytes(key, value);
-        Ok(OverwriteStatus::NotChecked)
+        Ok(status)
     }
 
     async fn put_with_status<'a>(
@@ -204,6 +217,15 @@ impl BlobstoreWal for Tickable<BlobstoreWalEntry> {
         Ok(self.storage.with(|s| s.values().cloned().collect()))
     }
 
+    async fn count<'a>(
+        &'a self,
+        _c: &'a CoreContext,
+        _u: &MultiplexId,
+        _o: &Timestamp,
+    ) -> Result<u64> {
+        Ok(self.storage.with(|s| s.len() as u64))
+    }
+
     async fn delete<'a>(
         &'a self,
         _ctx: &'a CoreContext,
@@ -222,3 +244,177 @@ impl BlobstoreWal for Tickable<BlobstoreWalEntry> {
         self.delete(ctx, entries).await
     }
 }
+
+/// What a `FaultyBlobstore` does instead of, or on top of, the operations on a key.
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Fail with this error.
+    Fail(String),
+    /// Never complete.
+    Hang,
+    /// Return the stored value with garbage appended on `get`. Other operations are unaffected.
+    Corrupt,
+    /// Complete after this delay.
+    Delay(Duration),
+}
+
+/// In-memory blobstore applying the faults configured with `set_fault` to the operations on the
+/// matching keys, for testing how multiplexed blobstores deal with misbehaving blobstores.
+#[derive(Default)]
+pub struct FaultyBlobstore {
+    inner: Memblob,
+    faults: Mutex<HashMap<String, Fault>>,
+    puts_in_flight: AtomicUsize,
+    max_puts_in_flight: AtomicUsize,
+    put_priorities: Mutex<HashMap<String, BlobstoreWalPriority>>,
+}
+
+/// Counts a put as in flight until dropped.
+struct PutInFlight<'a>(&'a FaultyBlobstore);
+
+impl<'a> PutInFlight<'a> {
+    fn new(store: &'a FaultyBlobstore, ctx: &CoreContext, key: &str) -> Self {
+        store.put_priorities.with(|priorities| {
+            priorities.insert(key.to_owned(), BlobstoreWalPriority::from_ctx(ctx));
+        });
+        let in_flight = store.puts_in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        store
+            .max_puts_in_flight
+            .fetch_max(in_flight, Ordering::SeqCst);
+        Self(store)
+    }
+}
+
+impl Drop for PutInFlight<'_> {
+    fn drop(&mut self) {
+        self.0.puts_in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl fmt::Debug for FaultyBlobstore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FaultyBlobstore")
+            .field("faults", &self.faults)
+            .finish()
+    }
+}
+
+impl fmt::Display for FaultyBlobstore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FaultyBlobstore")
+    }
+}
+
+impl FaultyBlobstore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply `fault` to the operations on `key` from now on, replacing its previous fault.
+    pub fn set_fault(&self, key: impl Into<String>, fault: Fault) {
+        self.faults.with(|faults| {
+            faults.insert(key.into(), fault);
+        })
+    }
+
+    /// Make the operations on `key` behave normally again.
+    pub fn clear_fault(&self, key: &str) {
+        self.faults.with(|faults| {
+            faults.remove(key);
+        })
+    }
+
+    /// The value stored for `key`, regardless of its fault.
+    pub async fn get_bytes(&self, ctx: &CoreContext, key: &str) -> Result<Option<BlobstoreBytes>> {
+        Ok(self
+            .inner
+            .get(ctx, key)
+            .await?
+            .map(|data| data.into_bytes()))
+    }
+
+    /// The largest number of puts that were in flight at once.
+    pub fn max_concurrent_puts(&self) -> usize {
+        self.max_puts_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// The priority of the last put of `key`, as seen by the blobstores that schedule the puts
+    /// according to the session class of their context.
+    pub fn put_priority(&self, key: &str) -> Option<BlobstoreWalPriority> {
+        self.put_priorities
+            .with(|priorities| priorities.get(key).copied())
+    }
+
+    /// Apply the fault of `key` before the operation, returning whether its result must be
+    /// corrupted.
+    async fn inject_fault(&self, key: &str) -> Result<bool> {
+        match self.faults.with(|faults| faults.get(key).cloned()) {
+            None => Ok(false),
+            Some(Fault::Fail(error)) => bail!(error),
+            Some(Fault::Hang) => future::pending().await,
+            Some(Fault::Corrupt) => Ok(true),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                Ok(false)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Blobstore for FaultyBlobstore {
+    async fn get<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: &'a str,
+    ) -> Result<Option<BlobstoreGetData>> {
+        let corrupt = self.inject_fault(key).await?;
+        let data = self.inner.get(ctx, key).await?;
+        if !corrupt {
+            return Ok(data);
+        }
+        Ok(data.map(|data| {
+            let mut bytes = data.into_raw_bytes().to_vec();
+            bytes.extend_from_slice(b"corrupted");
+            BlobstoreGetData::from_bytes(bytes)
+        }))
+    }
+
+    async fn put<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<()> {
+        BlobstorePutOps::put_with_status(self, ctx, key, value).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl BlobstorePutOps for FaultyBlobstore {
+    async fn put_explicit<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+        put_behaviour: PutBehaviour,
+    ) -> Result<OverwriteStatus> {
+        let _in_flight = PutInFlight::new(self, ctx, &key);
+        self.inject_fault(&key).await?;
+        self.inner
+            .put_explicit(ctx, key, value, put_behaviour)
+            .await
+    }
+
+    async fn put_with_status<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        key: String,
+        value: BlobstoreBytes,
+    ) -> Result<OverwriteStatus> {
+        let _in_flight = PutInFlight::new(self, ctx, &key);
+        self.inject_fault(&key).await?;
+        self.inner.put_with_status(ctx, key, value).await
+    }
+}
@@ -0,0 +1,12 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+-- Adds the priority of the writes logged to the WAL, see `BlobstoreWalPriority`. It must be
+-- applied to every shard of the WAL before the writers and the healers read or write the column.
+-- The existing entries become interactive.
+ALTER TABLE `blobstore_write_ahead_log`
+  ADD COLUMN `priority` TINYINT NOT NULL DEFAULT 0 /* 0: interactive, 1: background */;
@@ -11,5 +11,6 @@ CREATE TABLE IF NOT EXISTS `blobstore_write_ahead_log` (
   `timestamp` BIGINT NOT NULL, /* time the blob was added to the queue */
   `multiplex_id` INTEGER NOT NULL,
   `blob_size` BIGINT NOT NULL,
-  `retry_count` INTEGER NOT NULL
+  `retry_count` INTEGER NOT NULL,
+  `priority` TINYINT NOT NULL DEFAULT 0 /* 0: interactive, 1: background */
 );
@@ -9,6 +9,9 @@
 
 mod write_ahead_log;
 
+use std::fmt;
+use std::str::FromStr;
+
 use sql::mysql;
 use sql::mysql_async::prelude::ConvIr;
 use sql::mysql_async::prelude::FromValue;
@@ -17,6 +20,7 @@ use sql::mysql_async::Value;
 use uuid::Uuid;
 pub use write_ahead_log::BlobstoreWal;
 pub use write_ahead_log::BlobstoreWalEntry;
+pub use write_ahead_log::BlobstoreWalPriority;
 pub use write_ahead_log::SqlBlobstoreWal;
 
 // Identifier for given blobstore operation to faciliate correlating same operation
@@ -33,6 +37,21 @@ impl OperationKey {
     }
 }
 
+/// Operation keys are logged as hyphenated UUIDs, which can be parsed back with `FromStr`.
+impl fmt::Display for OperationKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.hyphenated())
+    }
+}
+
+impl FromStr for OperationKey {
+    type Err = uuid::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(OperationKey(Uuid::parse_str(s)?))
+    }
+}
+
 impl From<OperationKey> for Value {
     fn from(id: OperationKey) -> Self {
         let OperationKey(uuid) = id;
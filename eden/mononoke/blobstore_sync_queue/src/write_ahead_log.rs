@@ -20,6 +20,7 @@ use anyhow::Result;
 use async_trait::async_trait;
 use auto_impl::auto_impl;
 use context::CoreContext;
+use context::SessionClass;
 use futures::channel::mpsc;
 use futures::channel::oneshot;
 use futures::future;
@@ -39,6 +40,11 @@ use rendezvous::RendezVousOptions;
 use rendezvous::RendezVousStats;
 use shared_error::anyhow::IntoSharedError;
 use shared_error::anyhow::SharedError;
+use sql::mysql;
+use sql::mysql_async::prelude::ConvIr;
+use sql::mysql_async::prelude::FromValue;
+use sql::mysql_async::FromValueError;
+use sql::mysql_async::Value;
 use sql::Connection;
 use sql::WriteResult;
 use sql_common::mysql::IsolationLevel;
@@ -65,6 +71,76 @@ impl ReadInfo {
     }
 }
 
+/// The kind of traffic that logged the entry, derived from the session class of the writer.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    PartialEq,
+    Hash,
+    mysql::OptTryFromRowField
+)]
+pub enum BlobstoreWalPriority {
+    /// Someone is waiting for the write to complete.
+    #[default]
+    Interactive,
+    /// The write comes from background work, e.g. a backfill.
+    Background,
+}
+
+impl BlobstoreWalPriority {
+    pub fn from_ctx(ctx: &CoreContext) -> Self {
+        match ctx.session().session_class() {
+            SessionClass::UserWaiting | SessionClass::WarmBookmarksCache => Self::Interactive,
+            SessionClass::Background | SessionClass::BackgroundUnlessTooSlow => Self::Background,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Interactive => "interactive",
+            Self::Background => "background",
+        }
+    }
+}
+
+impl From<BlobstoreWalPriority> for Value {
+    fn from(priority: BlobstoreWalPriority) -> Self {
+        let val = match priority {
+            BlobstoreWalPriority::Interactive => 0,
+            BlobstoreWalPriority::Background => 1,
+        };
+        Value::Int(val)
+    }
+}
+
+impl ConvIr<BlobstoreWalPriority> for BlobstoreWalPriority {
+    fn new(v: Value) -> Result<Self, FromValueError> {
+        // MySQL can return the column as any of integer, unsigned or string.
+        match v {
+            Value::Int(0) | Value::UInt(0) => Ok(BlobstoreWalPriority::Interactive),
+            Value::Bytes(ref b) if b == b"0" => Ok(BlobstoreWalPriority::Interactive),
+            Value::Int(1) | Value::UInt(1) => Ok(BlobstoreWalPriority::Background),
+            Value::Bytes(ref b) if b == b"1" => Ok(BlobstoreWalPriority::Background),
+            v => Err(FromValueError(v)),
+        }
+    }
+
+    fn commit(self) -> Self {
+        self
+    }
+
+    fn rollback(self) -> Value {
+        self.into()
+    }
+}
+
+impl FromValue for BlobstoreWalPriority {
+    type Intermediate = BlobstoreWalPriority;
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct BlobstoreWalEntry {
     pub blobstore_key: String,
@@ -73,6 +149,8 @@ pub struct BlobstoreWalEntry {
     pub read_info: ReadInfo,
     pub blob_size: u64,
     pub retry_count: u32,
+    /// Kept when the healer requeues the entry.
+    pub priority: BlobstoreWalPriority,
 }
 
 impl BlobstoreWalEntry {
@@ -92,20 +170,36 @@ impl BlobstoreWalEntry {
                 shard_id: None,
             },
             retry_count: 0,
+            priority: BlobstoreWalPriority::default(),
         }
     }
 
+    pub fn with_priority(mut self, priority: BlobstoreWalPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
     pub fn increment_retry(&mut self) {
         self.retry_count += 1;
     }
 
-    fn into_sql_tuple(self) -> (String, MultiplexId, Timestamp, u64, u32) {
+    fn into_sql_tuple(
+        self,
+    ) -> (
+        String,
+        MultiplexId,
+        Timestamp,
+        u64,
+        u32,
+        BlobstoreWalPriority,
+    ) {
         let Self {
             blobstore_key,
             multiplex_id,
             timestamp,
             blob_size,
             retry_count,
+            priority,
             ..
         } = self;
         (
@@ -114,11 +208,23 @@ impl BlobstoreWalEntry {
             timestamp,
             blob_size,
             retry_count,
+            priority,
         )
     }
 
-    fn from_row(shard_id: usize, row: (String, MultiplexId, Timestamp, u64, u64, u32)) -> Self {
-        let (blobstore_key, multiplex_id, timestamp, id, blob_size, retry_count) = row;
+    fn from_row(
+        shard_id: usize,
+        row: (
+            String,
+            MultiplexId,
+            Timestamp,
+            u64,
+            u64,
+            u32,
+            BlobstoreWalPriority,
+        ),
+    ) -> Self {
+        let (blobstore_key, multiplex_id, timestamp, id, blob_size, retry_count, priority) = row;
         Self {
             blobstore_key,
             multiplex_id,
@@ -129,6 +235,7 @@ impl BlobstoreWalEntry {
             },
             blob_size,
             retry_count,
+            priority,
         }
     }
 }
@@ -284,6 +391,14 @@ pub trait BlobstoreWal: Send + Sync {
         limit: usize,
     ) -> Result<Vec<BlobstoreWalEntry>>;
 
+    /// Number of the entries that `read` would return without a limit.
+    async fn count<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        multiplex_id: &MultiplexId,
+        older_than: &Timestamp,
+    ) -> Result<u64>;
+
     /// Entries must have `id` and `shard_id` set (automatic when they are obtained from `read`)
     async fn delete<'a>(
         &'a self,
@@ -365,6 +480,21 @@ impl BlobstoreWal for SqlBlobstoreWal {
         Ok(entries)
     }
 
+    async fn count<'a>(
+        &'a self,
+        _ctx: &'a CoreContext,
+        multiplex_id: &MultiplexId,
+        older_than: &Timestamp,
+    ) -> Result<u64> {
+        let counts = future::try_join_all(
+            self.read_master_connections
+                .iter()
+                .map(|conn| WalCountEntries::query(conn, multiplex_id, older_than)),
+        )
+        .await?;
+        Ok(counts.into_iter().flatten().map(|(count,)| count).sum())
+    }
+
     async fn delete<'a>(
         &'a self,
         _ctx: &'a CoreContext,
@@ -486,7 +616,7 @@ async fn insert_entries(
         .collect();
     let entries_ref: Vec<_> = entries
         .iter()
-        .map(|(a, b, c, d, e)| (a, b, c, d, e)) // &(a, b, ...) into (&a, &b, ...)
+        .map(|(a, b, c, d, e, f)| (a, b, c, d, e, f)) // &(a, b, ...) into (&a, &b, ...)
         .collect();
 
     WalInsertEntry::query(write_connection, &entries_ref).await
@@ -514,9 +644,10 @@ mononoke_queries! {
         timestamp: Timestamp,
         blob_size: u64,
         retry_count: u32,
+        priority: BlobstoreWalPriority,
     )) {
         none,
-        "INSERT INTO blobstore_write_ahead_log (blobstore_key, multiplex_id, timestamp, blob_size, retry_count)
+        "INSERT INTO blobstore_write_ahead_log (blobstore_key, multiplex_id, timestamp, blob_size, retry_count, priority)
          VALUES {values}"
     }
 
@@ -534,10 +665,17 @@ mononoke_queries! {
         u64,
         u64,
         u32,
+        BlobstoreWalPriority,
     ) {
-        "SELECT blobstore_key, multiplex_id, timestamp, id, blob_size, retry_count
+        "SELECT blobstore_key, multiplex_id, timestamp, id, blob_size, retry_count, priority
          FROM blobstore_write_ahead_log
          WHERE multiplex_id = {multiplex_id} AND timestamp <= {older_than}
          LIMIT {limit}"
     }
+
+    read WalCountEntries(multiplex_id: MultiplexId, older_than: Timestamp) -> (u64) {
+        "SELECT COUNT(*)
+         FROM blobstore_write_ahead_log
+         WHERE multiplex_id = {multiplex_id} AND timestamp <= {older_than}"
+    }
 }
@@ -9,6 +9,7 @@ use anyhow::format_err;
 use anyhow::Error;
 use blobstore_sync_queue::BlobstoreWal;
 use blobstore_sync_queue::BlobstoreWalEntry;
+use blobstore_sync_queue::OperationKey;
 use blobstore_sync_queue::SqlBlobstoreWal;
 use context::CoreContext;
 use fbinit::FacebookInit;
@@ -40,6 +41,11 @@ async fn test_write_ahead_log(fb: FacebookInit) -> Result<(), Error> {
         .unwrap();
     wal.log(&ctx, entry3.clone()).await.unwrap();
 
+    // count the entries older than a timestamp
+    assert_eq!(wal.count(&ctx, &mp, &t0).await?, 1);
+    assert_eq!(wal.count(&ctx, &mp, &t2).await?, 4);
+    assert_eq!(wal.count(&ctx, &MultiplexId::new(2), &t2).await?, 0);
+
     // read different ranges of entries
     let validate = |entry: &BlobstoreWalEntry, expected: &BlobstoreWalEntry| {
         assert_eq!(entry.blobstore_key, expected.blobstore_key);
@@ -103,3 +109,16 @@ async fn test_write_ahead_log(fb: FacebookInit) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[test]
+fn test_operation_key_string_encoding() -> Result<(), Error> {
+    let key = OperationKey::gen();
+    let encoded = key.to_string();
+    assert_eq!(encoded.len(), 36);
+    assert_eq!(encoded.parse::<OperationKey>()?, key);
+
+    let null: OperationKey = "00000000-0000-0000-0000-000000000000".parse()?;
+    assert!(null.is_null());
+    assert!("not-a-key".parse::<OperationKey>().is_err());
+    Ok(())
+}
@@ -110,6 +110,15 @@ impl<Q: BlobstoreWal> BlobstoreWal for DummyBlobstoreWal<Q> {
         self.inner.read(ctx, multiplex_id, older_than, limit).await
     }
 
+    async fn count<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        multiplex_id: &MultiplexId,
+        older_than: &Timestamp,
+    ) -> Result<u64> {
+        self.inner.count(ctx, multiplex_id, older_than).await
+    }
+
     async fn delete<'a>(
         &'a self,
         ctx: &'a CoreContext,
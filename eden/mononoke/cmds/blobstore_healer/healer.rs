@@ -22,11 +22,16 @@ pub(crate) const DEFAULT_BLOB_SIZE_BYTES: u64 = 1024;
 pub struct HealResult {
     pub processed_full_batch: bool,
     pub processed_rows: u64,
+    /// The processed rows whose blob couldn't be healed.
+    pub failed_rows: u64,
 }
 
 #[async_trait]
 pub trait Healer {
     async fn heal(&self, ctx: &CoreContext, minimum_age: ChronoDuration) -> Result<HealResult>;
+
+    /// Number of the queued entries still waiting to be healed, whatever their age.
+    async fn queue_depth(&self, ctx: &CoreContext) -> Result<u64>;
 }
 
 #[derive(Default, Debug, PartialEq)]
@@ -11,13 +11,13 @@
 mod dummy;
 mod healer;
 mod wal_healer;
+mod worker;
 
 use std::collections::HashMap;
 use std::num::NonZeroU64;
 use std::ops::Bound;
 use std::sync::Arc;
 use std::time::Duration;
-use std::time::Instant;
 
 use anyhow::bail;
 use anyhow::format_err;
@@ -31,6 +31,9 @@ use blobstore_factory::ReadOnlyStorage;
 use blobstore_sync_queue::BlobstoreWal;
 use blobstore_sync_queue::SqlBlobstoreWal;
 use borrowed::borrowed;
+use cacheblob::InProcessLease;
+use cacheblob::LeaseOps;
+use cacheblob::MemcacheOps;
 use cached_config::ConfigStore;
 use chrono::Duration as ChronoDuration;
 use clap::Parser;
@@ -38,10 +41,10 @@ use context::CoreContext;
 use context::SessionContainer;
 use dummy::DummyBlobstore;
 use dummy::DummyBlobstoreWal;
+use environment::Caching;
 use fbinit::FacebookInit;
 use futures::future;
 use futures_03_ext::BufferedParams;
-use healer::HealResult;
 use healer::Healer;
 use metaconfig_types::BlobConfig;
 use metaconfig_types::BlobstoreId;
@@ -58,6 +61,8 @@ use sql_construct::SqlConstructFromShardedDatabaseConfig;
 use sql_ext::facebook::MysqlOptions;
 use wait_for_replication::WaitForReplication;
 use wal_healer::WalHealer;
+use worker::HealWorker;
+use worker::HealWorkerConfig;
 
 #[derive(Parser)]
 #[clap(about = "Monitors blobstore_sync_queue to heal blobstores with missing data")]
@@ -92,8 +97,17 @@ struct MononokeBlobstoreHealerArgs {
     /// which shards to read from, useful for spawning multiple independent healers
     #[clap(long, default_value = "..")]
     shard_range: ShardRange,
+    /// Milliseconds to wait after healing less than a full batch
+    #[clap(long, default_value_t = 1000)]
+    idle_interval_ms: u64,
+    /// take turns with the other healers of the same storage and shard range, rather than
+    /// healing the same entries at the same time. Only coordinates across processes if caching
+    /// is enabled.
+    #[clap(long)]
+    heal_lease: bool,
 }
 
+#[derive(Debug)]
 struct ShardRange {
     left: Bound<usize>,
     right: Bound<usize>,
@@ -147,8 +161,8 @@ async fn maybe_schedule_healer_for_storage(
     mysql_options: &MysqlOptions,
     readonly_storage: ReadOnlyStorage,
     blobstore_options: &BlobstoreOptions,
-    iter_limit: Option<u64>,
-    heal_min_age: ChronoDuration,
+    worker_config: HealWorkerConfig,
+    lease: Option<(Arc<dyn LeaseOps>, String)>,
     config_store: &ConfigStore,
     shard_range: ShardRange,
 ) -> Result<(), Error> {
@@ -198,14 +212,20 @@ async fn maybe_schedule_healer_for_storage(
 
     let wait_for_replication = WaitForReplication::new(fb, config_store, storage_config, "healer")?;
 
-    schedule_healing(
-        ctx,
-        multiplex_healer,
-        wait_for_replication,
-        iter_limit,
-        heal_min_age,
-    )
-    .await
+    let mut worker = HealWorker::new(multiplex_healer, worker_config)
+        .with_wait_for_replication(wait_for_replication);
+    if let Some((lease, lease_key)) = lease {
+        worker = worker.with_lease(lease, lease_key);
+    }
+    let shutdown = async {
+        // Without the signal handler, run until the iteration limit as before.
+        if tokio::signal::ctrl_c().await.is_err() {
+            future::pending::<()>().await;
+        }
+    };
+    let result = worker.run(ctx, shutdown).await;
+    info!(ctx.logger(), "Healer stats: {:?}", worker.stats());
+    result
 }
 
 fn setup_wal(
@@ -285,61 +305,6 @@ async fn setup_blobstores(
     Ok(blobstores)
 }
 
-// Pass None as iter_limit for never ending run
-async fn schedule_healing(
-    ctx: &CoreContext,
-    multiplex_healer: Arc<dyn Healer>,
-    wait_for_replication: WaitForReplication,
-    iter_limit: Option<u64>,
-    heal_min_age: ChronoDuration,
-) -> Result<(), Error> {
-    let mut count = 0;
-    let healing_start_time = Instant::now();
-    let mut total_deleted_rows = 0;
-
-    loop {
-        let iteration_start_time = Instant::now();
-        count += 1;
-        if let Some(iter_limit) = iter_limit {
-            if count > iter_limit {
-                return Ok(());
-            }
-        }
-
-        wait_for_replication
-            .wait_for_replication(ctx.logger())
-            .await
-            .context("While waiting for replication")?;
-
-        let HealResult {
-            processed_full_batch,
-            processed_rows,
-        } = multiplex_healer
-            .heal(ctx, heal_min_age)
-            .await
-            .context("While healing")?;
-
-        total_deleted_rows += processed_rows;
-        let total_elapsed = healing_start_time.elapsed().as_secs_f32();
-        let iteration_elapsed = iteration_start_time.elapsed().as_secs_f32();
-        info!(
-            ctx.logger(),
-            "Iteration rows processed: {} rows, {}s; total: {} rows, {}s",
-            processed_rows,
-            iteration_elapsed,
-            total_deleted_rows,
-            total_elapsed,
-        );
-
-        // if last batch read was not full,  wait at least 1 second, to avoid busy looping as don't
-        // want to hammer the database with thousands of reads a second.
-        if !processed_full_batch {
-            info!(ctx.logger(), "The last batch was not full size, waiting...",);
-            tokio::time::sleep(Duration::from_secs(1)).await;
-        }
-    }
-}
-
 #[fbinit::main]
 fn main(fb: FacebookInit) -> Result<()> {
     let app = MononokeAppBuilder::new(fb)
@@ -370,8 +335,11 @@ async fn async_main(app: MononokeApp) -> Result<(), Error> {
     let dry_run = args.dry_run;
     let drain_only = args.drain_only;
 
-    let iter_limit = args.iteration_limit;
-    let healing_min_age = ChronoDuration::seconds(args.heal_min_age_secs);
+    let worker_config = HealWorkerConfig {
+        min_age: ChronoDuration::seconds(args.heal_min_age_secs),
+        idle_interval: Duration::from_millis(args.idle_interval_ms),
+        iteration_limit: args.iteration_limit,
+    };
     let quiet = args.quiet;
     if !quiet {
         info!(logger, "Using storage_config {:?}", storage_config);
@@ -385,6 +353,17 @@ async fn async_main(app: MononokeApp) -> Result<(), Error> {
         buffer_size: heal_concurrency,
     };
     let shard_range = args.shard_range;
+    let lease = if args.heal_lease {
+        let lease: Arc<dyn LeaseOps> = if let Caching::Enabled(_) = env.caching {
+            Arc::new(MemcacheOps::new(app.fb, "blobstore-healer-lease", "")?)
+        } else {
+            Arc::new(InProcessLease::new())
+        };
+        let lease_key = format!("{}.{:?}", storage_id, shard_range);
+        Some((lease, lease_key))
+    } else {
+        None
+    };
 
     maybe_schedule_healer_for_storage(
         app.fb,
@@ -397,8 +376,8 @@ async fn async_main(app: MononokeApp) -> Result<(), Error> {
         mysql_options,
         readonly_storage,
         blobstore_options,
-        iter_limit,
-        healing_min_age,
+        worker_config,
+        lease,
         config_store,
         shard_range,
     )
@@ -162,6 +162,7 @@ impl WalHealer {
             return Ok(HealResult {
                 processed_full_batch: false,
                 processed_rows: 0,
+                failed_rows: 0,
             });
         }
 
@@ -176,6 +177,7 @@ impl WalHealer {
             return Ok(HealResult {
                 processed_full_batch: true,
                 processed_rows: deleted_entries,
+                failed_rows: 0,
             });
         }
 
@@ -218,6 +220,7 @@ impl WalHealer {
             .await;
 
         let mut healthy_blobs = 0;
+        let mut failed_entries = 0;
         let mut to_enqueue = vec![];
         let mut missing_blobs = vec![];
         let processed_entries: Vec<_> = heal_res
@@ -248,6 +251,7 @@ impl WalHealer {
                                 .collect(),
                         );
                         missing_blobs.push(key);
+                        failed_entries += entries.len();
                     }
                     HealBlobOutcome::MissingBlobstores(key, blobstores) => {
                         info!(
@@ -255,6 +259,7 @@ impl WalHealer {
                             "Couldn't heal blob {} in these blobstores: {:?}", key, blobstores
                         );
                         to_enqueue.push(entries.clone());
+                        failed_entries += entries.len();
                     }
                 }
                 entries
@@ -282,6 +287,7 @@ impl WalHealer {
         Ok(HealResult {
             processed_full_batch: unique_puts == batch_size,
             processed_rows: deleted_entries,
+            failed_rows: failed_entries as u64,
         })
     }
 }
@@ -291,6 +297,12 @@ impl Healer for WalHealer {
     async fn heal(&self, ctx: &CoreContext, minimum_age: ChronoDuration) -> Result<HealResult> {
         self.heal_impl(ctx, minimum_age).await
     }
+
+    async fn queue_depth(&self, ctx: &CoreContext) -> Result<u64> {
+        self.wal
+            .count(ctx, &self.multiplex_id, &Timestamp::now())
+            .await
+    }
 }
 
 struct HealingBlob {
@@ -439,10 +451,12 @@ async fn enqueue_entries(
                 blobstore_key,
                 multiplex_id,
                 blob_size,
+                priority,
                 ..
             } = entry;
 
             BlobstoreWalEntry::new(blobstore_key, multiplex_id, Timestamp::now(), blob_size)
+                .with_priority(priority)
         })
         .collect();
 
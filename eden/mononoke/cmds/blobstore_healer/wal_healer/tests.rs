@@ -8,6 +8,7 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -16,8 +17,11 @@ use blobstore::BlobstoreBytes;
 use blobstore::BlobstoreGetData;
 use blobstore_sync_queue::SqlBlobstoreWal;
 use bytes::Bytes;
+use cacheblob::InProcessLease;
+use cacheblob::LeaseOps;
 use context::CoreContext;
 use fbinit::FacebookInit;
+use futures::future;
 use futures_03_ext::BufferedParams;
 use metaconfig_types::BlobstoreId;
 use metaconfig_types::MultiplexId;
@@ -25,6 +29,9 @@ use sql_construct::SqlConstruct;
 
 use super::*;
 use crate::wal_healer::WalHealer;
+use crate::worker::HealWorker;
+use crate::worker::HealWorkerConfig;
+use crate::worker::HealWorkerStats;
 
 #[derive(Clone, Debug, Default)]
 struct GoodBlob {
@@ -522,6 +529,118 @@ async fn test_entry_timestamp_updated(fb: FacebookInit) -> Result<()> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_heal_worker(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+
+    let bs1: Arc<dyn Blobstore> = Arc::new(GoodBlob::default());
+    let bs2: Arc<dyn Blobstore> = Arc::new(GoodBlob::default());
+    let bs3: Arc<dyn Blobstore> = Arc::new(GoodBlob::default());
+    let blobstores: Arc<HashMap<_, _>> = Arc::new(
+        vec![
+            (BlobstoreId::new(1), bs1.clone()),
+            (BlobstoreId::new(2), bs2.clone()),
+            (BlobstoreId::new(3), bs3.clone()),
+        ]
+        .into_iter()
+        .collect(),
+    );
+
+    // set up some variables
+    let multiplex_id = MultiplexId::new(1);
+    let ts = Timestamp::now();
+    let keys: Vec<_> = (0..3).map(|i| format!("key{}", i)).collect();
+    let value = make_value("value");
+
+    // the blobs are only in the first blobstore, and the queue has their entries
+    let wal = Arc::new(SqlBlobstoreWal::with_sqlite_in_memory()?);
+    for key in &keys {
+        bs1.put(&ctx, key.clone(), value.clone()).await?;
+        let entry = BlobstoreWalEntry::new(key.clone(), multiplex_id, ts, 12);
+        wal.log_many(&ctx, vec![entry]).await?;
+    }
+    validate_queue(
+        &ctx,
+        wal.clone(),
+        multiplex_id,
+        Timestamp::now(),
+        keys.clone(),
+    )
+    .await?;
+
+    let buf_params = BufferedParams {
+        weight_limit: 1000,
+        buffer_size: 100,
+    };
+    let healer: Arc<dyn Healer> = Arc::new(WalHealer::new(
+        10,
+        buf_params,
+        wal.clone(),
+        blobstores,
+        multiplex_id,
+        false,
+    ));
+    let config = HealWorkerConfig {
+        min_age: ChronoDuration::seconds(0),
+        idle_interval: Duration::from_millis(10),
+        iteration_limit: None,
+    };
+    let lease: Arc<dyn LeaseOps> = Arc::new(InProcessLease::new());
+    let lease_key = "healer".to_string();
+
+    // while another healer holds the lease, nothing is healed until shutdown
+    assert!(lease.try_add_put_lease(&lease_key).await?);
+    let worker = HealWorker::new(healer.clone(), config.clone())
+        .with_lease(lease.clone(), lease_key.clone());
+    worker
+        .run(&ctx, tokio::time::sleep(Duration::from_millis(50)))
+        .await?;
+    assert_eq!(worker.stats(), HealWorkerStats::default());
+    validate_queue(
+        &ctx,
+        wal.clone(),
+        multiplex_id,
+        Timestamp::now(),
+        keys.clone(),
+    )
+    .await?;
+    lease.release_lease(&lease_key).await;
+    assert_eq!(healer.queue_depth(&ctx).await?, 3);
+
+    // once the lease is released, the entries are healed by the first iteration, and the second
+    // one finds the queue empty
+    let worker = HealWorker::new(
+        healer,
+        HealWorkerConfig {
+            iteration_limit: Some(2),
+            ..config
+        },
+    )
+    .with_lease(lease.clone(), lease_key.clone());
+    worker.run(&ctx, future::pending()).await?;
+    assert_eq!(
+        worker.stats(),
+        HealWorkerStats {
+            iterations: 2,
+            entries_healed: 3,
+            entries_failed: 0,
+            last_processed_rows: 0,
+            queue_depth: 0,
+        }
+    );
+    // the worker released the lease after its last iteration
+    assert!(lease.try_add_put_lease(&lease_key).await?);
+    validate_queue(&ctx, wal.clone(), multiplex_id, Timestamp::now(), vec![]).await?;
+    for key in &keys {
+        assert_eq!(
+            bs3.get(&ctx, key).await?.map(|data| data.into_bytes()),
+            Some(value.clone())
+        );
+    }
+
+    Ok(())
+}
+
 async fn validate_queue<'a>(
     ctx: &'a CoreContext,
     wal: Arc<dyn BlobstoreWal>,
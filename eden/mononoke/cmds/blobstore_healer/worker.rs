@@ -0,0 +1,197 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Context;
+use anyhow::Result;
+use cacheblob::LeaseOps;
+use chrono::Duration as ChronoDuration;
+use context::CoreContext;
+use futures::channel::oneshot;
+use futures::future::Either;
+use futures::Future;
+use futures::FutureExt;
+use slog::info;
+use wait_for_replication::WaitForReplication;
+
+use crate::healer::HealResult;
+use crate::healer::Healer;
+
+/// Configuration of a `HealWorker`. The batch size is the one of its healer.
+#[derive(Clone, Debug)]
+pub struct HealWorkerConfig {
+    /// Only the WAL entries older than this are healed, leaving the writes in flight a chance to
+    /// complete.
+    pub min_age: ChronoDuration,
+    /// How long to wait after an iteration that didn't process a full batch, and between two
+    /// attempts at taking the lease.
+    pub idle_interval: Duration,
+    /// Stop after this many iterations, or never if `None`.
+    pub iteration_limit: Option<u64>,
+}
+
+/// What a `HealWorker` did so far.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HealWorkerStats {
+    pub iterations: u64,
+    /// WAL entries removed from the queue, as their blob was healthy or was healed.
+    pub entries_healed: u64,
+    /// WAL entries whose blob couldn't be healed, most of them being put back in the queue.
+    pub entries_failed: u64,
+    /// WAL entries processed by the last iteration, up to the batch size.
+    pub last_processed_rows: u64,
+    /// WAL entries of the multiplex, in the shards the worker reads from, still waiting to be
+    /// healed after the last iteration.
+    pub queue_depth: u64,
+}
+
+/// Heals the blobstores of a multiplex, one batch of WAL entries at a time, until stopped.
+pub struct HealWorker {
+    healer: Arc<dyn Healer>,
+    config: HealWorkerConfig,
+    wait_for_replication: Option<WaitForReplication>,
+    /// Lease taken for each iteration, so that the workers healing the same WAL entries take
+    /// turns instead of healing them at the same time.
+    lease: Option<(Arc<dyn LeaseOps>, String)>,
+    stats: Mutex<HealWorkerStats>,
+}
+
+impl HealWorker {
+    pub fn new(healer: Arc<dyn Healer>, config: HealWorkerConfig) -> Self {
+        Self {
+            healer,
+            config,
+            wait_for_replication: None,
+            lease: None,
+            stats: Mutex::new(HealWorkerStats::default()),
+        }
+    }
+
+    /// Wait for the replication of the WAL database before each iteration.
+    pub fn with_wait_for_replication(mut self, wait_for_replication: WaitForReplication) -> Self {
+        self.wait_for_replication = Some(wait_for_replication);
+        self
+    }
+
+    /// Only heal while holding `lease_key` in `lease`, which should be shared by all the workers
+    /// reading the same WAL entries.
+    pub fn with_lease(mut self, lease: Arc<dyn LeaseOps>, lease_key: String) -> Self {
+        self.lease = Some((lease, lease_key));
+        self
+    }
+
+    pub fn stats(&self) -> HealWorkerStats {
+        self.stats.lock().expect("lock poisoned").clone()
+    }
+
+    /// Heal until the iteration limit is reached or `shutdown` resolves. The iteration in
+    /// progress when `shutdown` resolves is completed first.
+    pub async fn run(&self, ctx: &CoreContext, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let healing_start_time = Instant::now();
+        let mut shutdown = Box::pin(shutdown.fuse());
+
+        loop {
+            if let Some(iteration_limit) = self.config.iteration_limit {
+                if self.stats().iterations >= iteration_limit {
+                    return Ok(());
+                }
+            }
+            if (&mut shutdown).now_or_never().is_some() {
+                info!(ctx.logger(), "Shutting down the healer");
+                return Ok(());
+            }
+
+            let processed_full_batch = match &self.lease {
+                Some((lease, lease_key)) => {
+                    if !lease.try_add_put_lease(lease_key).await? {
+                        info!(
+                            ctx.logger(),
+                            "Another healer holds {}, waiting...", lease_key
+                        );
+                        false
+                    } else {
+                        let (sender, receiver) = oneshot::channel();
+                        lease.renew_lease_until(
+                            ctx.clone(),
+                            lease_key,
+                            receiver.map(|_| ()).boxed(),
+                        );
+                        let result = self.heal_once(ctx, healing_start_time).await;
+                        let _ = sender.send(());
+                        lease.release_lease(lease_key).await;
+                        result?
+                    }
+                }
+                None => self.heal_once(ctx, healing_start_time).await?,
+            };
+
+            // if last batch read was not full,  wait at least `idle_interval`, to avoid busy
+            // looping as don't want to hammer the database with thousands of reads a second.
+            if !processed_full_batch {
+                let sleep = Box::pin(tokio::time::sleep(self.config.idle_interval));
+                if let Either::Left(_) = futures::future::select(&mut shutdown, sleep).await {
+                    info!(ctx.logger(), "Shutting down the healer");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Heal one batch of WAL entries. Returns whether the batch was full.
+    async fn heal_once(&self, ctx: &CoreContext, healing_start_time: Instant) -> Result<bool> {
+        let iteration_start_time = Instant::now();
+
+        if let Some(wait_for_replication) = &self.wait_for_replication {
+            wait_for_replication
+                .wait_for_replication(ctx.logger())
+                .await
+                .context("While waiting for replication")?;
+        }
+
+        let HealResult {
+            processed_full_batch,
+            processed_rows,
+            failed_rows,
+        } = self
+            .healer
+            .heal(ctx, self.config.min_age)
+            .await
+            .context("While healing")?;
+        let queue_depth = self
+            .healer
+            .queue_depth(ctx)
+            .await
+            .context("While counting the WAL entries")?;
+
+        let stats = {
+            let mut stats = self.stats.lock().expect("lock poisoned");
+            stats.iterations += 1;
+            stats.entries_healed += processed_rows.saturating_sub(failed_rows);
+            stats.entries_failed += failed_rows;
+            stats.last_processed_rows = processed_rows;
+            stats.queue_depth = queue_depth;
+            stats.clone()
+        };
+
+        info!(
+            ctx.logger(),
+            "Iteration rows processed: {} rows, {}s; total: {} rows healed, {} rows failed, {} rows queued, {}s",
+            processed_rows,
+            iteration_start_time.elapsed().as_secs_f32(),
+            stats.entries_healed,
+            stats.entries_failed,
+            stats.queue_depth,
+            healing_start_time.elapsed().as_secs_f32(),
+        );
+
+        Ok(processed_full_batch)
+    }
+}
@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashMap;
 use std::fmt;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -22,6 +23,7 @@ use mononoke_types::RepositoryId;
 use reachabilityindex::LeastCommonAncestorsHint;
 use slog::debug;
 use synced_commit_mapping::SyncedCommitMapping;
+use synced_commit_mapping::SyncedCommitMappingTarget;
 use synced_commit_mapping::WorkingCopyEquivalence;
 
 use crate::commit_sync_data_provider::CommitSyncDataProvider;
@@ -212,28 +214,66 @@ pub async fn get_plural_commit_sync_outcome<'a, M: SyncedCommitMapping>(
         .get(ctx, source_repo_id.0, source_cs_id.0, target_repo_id.0)
         .await?;
     if !remapped.is_empty() {
-        let remapped: Result<Vec<_>, Error> = remapped.into_iter()
-            .map(|(cs_id, maybe_version, _maybe_source_repo)| {
-                let version = maybe_version.ok_or_else(||
-                    anyhow!(
-                        "no sync commit version specified for remapping of {} -> {} (source repo {}, target repo {})",
-                        source_cs_id.0, cs_id,
-                        source_repo_id,
-                        target_repo_id,
-                    )
-                )?;
-
-                Ok((cs_id, version))
-            })
-            .collect();
-        let remapped = remapped?;
-        return Ok(Some(PluralCommitSyncOutcome::RewrittenAs(remapped)));
+        return Ok(Some(plural_commit_sync_outcome_from_remapped(
+            source_repo_id,
+            target_repo_id,
+            source_cs_id,
+            remapped,
+        )?));
     }
 
     let maybe_wc_equivalence = mapping
         .get_equivalent_working_copy(ctx, source_repo_id.0, source_cs_id.0, target_repo_id.0)
         .await?;
 
+    plural_commit_sync_outcome_from_wc_equivalence(
+        ctx,
+        source_repo_id,
+        target_repo_id,
+        source_cs_id,
+        maybe_wc_equivalence,
+        mapping,
+        direction,
+        commit_sync_data_provider,
+    )
+    .await
+}
+
+/// The outcome of a commit that was rewritten as `remapped`, which isn't empty
+fn plural_commit_sync_outcome_from_remapped(
+    source_repo_id: Source<RepositoryId>,
+    target_repo_id: Target<RepositoryId>,
+    source_cs_id: Source<ChangesetId>,
+    remapped: Vec<SyncedCommitMappingTarget>,
+) -> Result<PluralCommitSyncOutcome, Error> {
+    let remapped: Result<Vec<_>, Error> = remapped.into_iter()
+        .map(|(cs_id, maybe_version, _maybe_source_repo)| {
+            let version = maybe_version.ok_or_else(||
+                anyhow!(
+                    "no sync commit version specified for remapping of {} -> {} (source repo {}, target repo {})",
+                    source_cs_id.0, cs_id,
+                    source_repo_id,
+                    target_repo_id,
+                )
+            )?;
+
+            Ok((cs_id, version))
+        })
+        .collect();
+    Ok(PluralCommitSyncOutcome::RewrittenAs(remapped?))
+}
+
+/// The outcome of a commit that wasn't rewritten, given its working copy equivalence
+async fn plural_commit_sync_outcome_from_wc_equivalence<'a, M: SyncedCommitMapping>(
+    ctx: &'a CoreContext,
+    source_repo_id: Source<RepositoryId>,
+    target_repo_id: Target<RepositoryId>,
+    source_cs_id: Source<ChangesetId>,
+    maybe_wc_equivalence: Option<WorkingCopyEquivalence>,
+    mapping: &'a M,
+    direction: CommitSyncDirection,
+    commit_sync_data_provider: &CommitSyncDataProvider,
+) -> Result<Option<PluralCommitSyncOutcome>, Error> {
     match maybe_wc_equivalence {
         None => {
             if direction == CommitSyncDirection::LargeToSmall {
@@ -315,6 +355,70 @@ pub async fn get_commit_sync_outcome<'a, M: SyncedCommitMapping>(
     .await
 }
 
+/// Get `CommitSyncOutcome` for each of `source_cs_ids`, in the same way as
+/// `get_commit_sync_outcome`, but batching the mapping lookups of all the commits.
+/// Every commit is in the result, with `None` for those that weren't synced.
+pub async fn get_commit_sync_outcomes<'a, M: SyncedCommitMapping>(
+    ctx: &'a CoreContext,
+    source_repo_id: Source<RepositoryId>,
+    target_repo_id: Target<RepositoryId>,
+    source_cs_ids: &[ChangesetId],
+    mapping: &'a M,
+    direction: CommitSyncDirection,
+    commit_sync_data_provider: &CommitSyncDataProvider,
+) -> Result<HashMap<ChangesetId, Option<CommitSyncOutcome>>, Error> {
+    let mut remapped = mapping
+        .get_many(ctx, source_repo_id.0, source_cs_ids, target_repo_id.0)
+        .await?;
+    let not_remapped: Vec<_> = source_cs_ids
+        .iter()
+        .filter(|cs_id| !remapped.contains_key(cs_id))
+        .copied()
+        .collect();
+    let mut wc_equivalences = mapping
+        .get_many_equivalent_working_copies(ctx, source_repo_id.0, &not_remapped, target_repo_id.0)
+        .await?;
+
+    let mut outcomes = HashMap::with_capacity(source_cs_ids.len());
+    for source_cs_id in source_cs_ids {
+        if outcomes.contains_key(source_cs_id) {
+            continue;
+        }
+        let maybe_plural_commit_sync_outcome = match remapped.remove(source_cs_id) {
+            Some(remapped) => Some(plural_commit_sync_outcome_from_remapped(
+                source_repo_id,
+                target_repo_id,
+                Source(*source_cs_id),
+                remapped,
+            )?),
+            None => {
+                plural_commit_sync_outcome_from_wc_equivalence(
+                    ctx,
+                    source_repo_id,
+                    target_repo_id,
+                    Source(*source_cs_id),
+                    wc_equivalences.remove(source_cs_id),
+                    mapping,
+                    direction,
+                    commit_sync_data_provider,
+                )
+                .await?
+            }
+        };
+        let maybe_commit_sync_outcome = match maybe_plural_commit_sync_outcome {
+            Some(plural_commit_sync_outcome) => Some(
+                plural_commit_sync_outcome
+                    .try_into_commit_sync_outcome(Source(*source_cs_id))
+                    .await?,
+            ),
+            None => None,
+        };
+        outcomes.insert(*source_cs_id, maybe_commit_sync_outcome);
+    }
+
+    Ok(outcomes)
+}
+
 /// Get `CommitSyncOutcome` for `source_cs_id`
 /// If `source_cs_id` is remapped into just one commit in the target
 /// repo, this function works the same way as `get_commit_sync_outcome`
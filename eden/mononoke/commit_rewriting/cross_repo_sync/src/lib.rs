@@ -123,6 +123,7 @@ pub use commit_sync_data_provider::CommitSyncDataProvider;
 pub use crate::commit_sync_outcome::commit_sync_outcome_exists;
 pub use crate::commit_sync_outcome::get_commit_sync_outcome;
 pub use crate::commit_sync_outcome::get_commit_sync_outcome_with_hint;
+pub use crate::commit_sync_outcome::get_commit_sync_outcomes;
 pub use crate::commit_sync_outcome::get_plural_commit_sync_outcome;
 pub use crate::commit_sync_outcome::CandidateSelectionHint;
 pub use crate::commit_sync_outcome::CommitSyncOutcome;
@@ -749,6 +750,25 @@ where
         .await
     }
 
+    /// Get `CommitSyncOutcome` for each of `source_cs_ids`, with `None` for those that weren't
+    /// synced, in fewer mapping queries than calling `get_commit_sync_outcome` for each of them.
+    pub async fn get_commit_sync_outcomes<'a>(
+        &'a self,
+        ctx: &'a CoreContext,
+        source_cs_ids: &[ChangesetId],
+    ) -> Result<HashMap<ChangesetId, Option<CommitSyncOutcome>>, Error> {
+        get_commit_sync_outcomes::<M>(
+            ctx,
+            Source(self.repos.get_source_repo().repo_identity().id()),
+            Target(self.repos.get_target_repo().repo_identity().id()),
+            source_cs_ids,
+            &self.mapping,
+            self.repos.get_direction(),
+            &self.commit_sync_data_provider,
+        )
+        .await
+    }
+
     pub async fn commit_sync_outcome_exists<'a>(
         &'a self,
         ctx: &'a CoreContext,
@@ -1924,6 +1944,41 @@ pub async fn update_mapping_with_version<'a, M: SyncedCommitMapping + Clone + 's
     Ok(())
 }
 
+/// Returns the config version `source_cs_id` was synced with by `syncer`, as recorded in the
+/// synced commit mapping, or `None` if it wasn't synced. Fails if the commit was rewritten
+/// several times with different versions.
+pub async fn get_sync_version<'a, M: SyncedCommitMapping + Clone + 'static, R: Repo>(
+    ctx: &'a CoreContext,
+    syncer: &'a CommitSyncer<M, R>,
+    source_cs_id: ChangesetId,
+) -> Result<Option<CommitSyncConfigVersion>, Error> {
+    let version = match syncer
+        .get_plural_commit_sync_outcome(ctx, source_cs_id)
+        .await?
+    {
+        None => return Ok(None),
+        Some(PluralCommitSyncOutcome::NotSyncCandidate(version))
+        | Some(PluralCommitSyncOutcome::EquivalentWorkingCopyAncestor(_, version)) => version,
+        Some(PluralCommitSyncOutcome::RewrittenAs(rewrites)) => {
+            let mut versions = rewrites.into_iter().map(|(_cs_id, version)| version);
+            let version = match versions.next() {
+                Some(version) => version,
+                None => return Ok(None),
+            };
+            if let Some(other) = versions.find(|other| other != &version) {
+                bail!(
+                    "{} was synced with several versions: {} and {}",
+                    source_cs_id,
+                    version,
+                    other
+                );
+            }
+            version
+        }
+    };
+    Ok(Some(version))
+}
+
 pub fn create_synced_commit_mapping_entry<R: Repo>(
     from: ChangesetId,
     to: ChangesetId,
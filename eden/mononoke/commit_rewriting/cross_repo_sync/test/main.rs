@@ -8,6 +8,7 @@
 //! Tests for the synced commits mapping.
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
 use std::str::FromStr;
 use std::sync::Arc;
 
@@ -24,6 +25,7 @@ use bookmarks::BookmarksRef;
 use cacheblob::InProcessLease;
 use changeset_fetcher::ChangesetFetcherRef;
 use context::CoreContext;
+use cross_repo_sync::get_sync_version;
 use cross_repo_sync::types::Target;
 use cross_repo_sync::update_mapping_with_version;
 use cross_repo_sync::validation::verify_working_copy;
@@ -35,7 +37,35 @@ use cross_repo_sync::CommitSyncRepos;
 use cross_repo_sync::CommitSyncer;
 use cross_repo_sync::ErrorKind;
 use cross_repo_sync::PluralCommitSyncOutcome;
+use cross_repo_sync_test_utils::assert_mover_respects_map;
+use cross_repo_sync_test_utils::assert_movers_roundtrip;
+use cross_repo_sync_test_utils::assert_sync_outcome;
+use cross_repo_sync_test_utils::assert_working_copy_equivalent;
+use cross_repo_sync_test_utils::assert_working_copy_equivalent_with_strategy;
+use cross_repo_sync_test_utils::backsync_and_verify;
+use cross_repo_sync_test_utils::build_syncers;
+use cross_repo_sync_test_utils::create_and_sync_deletion;
+use cross_repo_sync_test_utils::create_and_sync_diamond;
+use cross_repo_sync_test_utils::create_and_sync_merge;
+use cross_repo_sync_test_utils::get_small_repo_sync_config_1;
+use cross_repo_sync_test_utils::get_small_repo_sync_config_2;
+use cross_repo_sync_test_utils::init_large_with_small_repos;
+use cross_repo_sync_test_utils::init_small_large_repo;
+use cross_repo_sync_test_utils::init_small_large_repo_with_bookmark_prefix;
+use cross_repo_sync_test_utils::map_based_mover;
+use cross_repo_sync_test_utils::prefix_mover;
 use cross_repo_sync_test_utils::rebase_root_on_master;
+use cross_repo_sync_test_utils::reverse_prefix_mover;
+use cross_repo_sync_test_utils::sync_across_noop_version_change;
+use cross_repo_sync_test_utils::try_sync_with_diagnostics;
+use cross_repo_sync_test_utils::xrepo_mapping_noop_version;
+use cross_repo_sync_test_utils::xrepo_mapping_version_with_small_repo;
+use cross_repo_sync_test_utils::EqualityStrategy;
+use cross_repo_sync_test_utils::ExpectedOutcome;
+use cross_repo_sync_test_utils::LargeWithSmallRepos;
+use cross_repo_sync_test_utils::MoveAction;
+use cross_repo_sync_test_utils::RewriteOutcome;
+use cross_repo_sync_test_utils::SmallRepoSpec;
 use cross_repo_sync_test_utils::TestRepo;
 use fbinit::FacebookInit;
 use fixtures::Linear;
@@ -52,6 +82,7 @@ use mercurial_derived_data::DeriveHgChangeset;
 use mercurial_types::HgChangesetId;
 use metaconfig_types::CommitSyncConfig;
 use metaconfig_types::CommitSyncConfigVersion;
+use metaconfig_types::CommitSyncDirection;
 use metaconfig_types::CommonCommitSyncConfig;
 use metaconfig_types::DefaultSmallToLargeCommitSyncPathAction;
 use metaconfig_types::SmallRepoCommitSyncConfig;
@@ -62,6 +93,9 @@ use mononoke_types::DateTime;
 use mononoke_types::FileChange;
 use mononoke_types::MPath;
 use mononoke_types::RepositoryId;
+use movers::get_large_to_small_mover;
+use movers::get_small_to_large_mover;
+use movers::Mover;
 use pushrebase::PushrebaseError;
 use reachabilityindex::LeastCommonAncestorsHint;
 use repo_blobstore::RepoBlobstoreRef;
@@ -75,6 +109,7 @@ use synced_commit_mapping::SyncedCommitMapping;
 use synced_commit_mapping::SyncedCommitMappingEntry;
 use test_repo_factory::TestRepoFactory;
 use tests_utils::bookmark;
+use tests_utils::list_working_copy_utf8;
 use tests_utils::resolve_cs_id;
 use tests_utils::CreateCommitContext;
 use tunables::with_tunables_async;
@@ -403,6 +438,51 @@ async fn test_sync_parentage(fb: FacebookInit) -> Result<(), Error> {
     Ok(())
 }
 
+#[fbinit::test]
+async fn test_get_commit_sync_outcomes(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (small_repo, megarepo, mapping) = prepare_repos_and_mapping(fb)?;
+    Linear::initrepo(fb, &small_repo).await;
+    let config =
+        create_small_to_large_commit_syncer(&ctx, small_repo, megarepo.clone(), "linear", mapping)?;
+    create_initial_commit(ctx.clone(), &megarepo).await;
+
+    // Only the first commit from linear is synced
+    let linear_base_bcs_id = get_bcs_id(
+        &ctx,
+        &config,
+        HgChangesetId::from_str("2d7d4ba9ce0a6ffd222de7785b249ead9c51c536")?,
+    )
+    .await;
+    let megarepo_base_bcs_id =
+        rebase_root_on_master(ctx.clone(), &config, linear_base_bcs_id).await?;
+    let linear_second_bcs_id = get_bcs_id(
+        &ctx,
+        &config,
+        HgChangesetId::from_str("3e0e761030db6e479a7fb58b12881883f9f8c63f")?,
+    )
+    .await;
+
+    let outcomes = config
+        .get_commit_sync_outcomes(&ctx, &[linear_base_bcs_id, linear_second_bcs_id])
+        .await?;
+    assert_eq!(outcomes.len(), 2);
+    assert!(matches!(
+        outcomes[&linear_base_bcs_id],
+        Some(CommitSyncOutcome::RewrittenAs(cs_id, _)) if cs_id == megarepo_base_bcs_id
+    ));
+    assert_eq!(outcomes[&linear_second_bcs_id], None);
+
+    // The outcomes are the same as when looked up one by one
+    for (cs_id, outcome) in outcomes {
+        assert_eq!(outcome, config.get_commit_sync_outcome(&ctx, cs_id).await?);
+    }
+
+    assert!(config.get_commit_sync_outcomes(&ctx, &[]).await?.is_empty());
+
+    Ok(())
+}
+
 async fn create_commit_from_parent_and_changes<'a>(
     ctx: &'a CoreContext,
     repo: &'a TestRepo,
@@ -2193,3 +2273,875 @@ async fn test_not_sync_candidate_if_mapping_does_not_have_small_repo(
     );
     Ok(())
 }
+
+#[fbinit::test]
+async fn test_sync_with_many_small_repos(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let LargeWithSmallRepos {
+        large_repo,
+        small_repos,
+        ..
+    } = init_large_with_small_repos(
+        &ctx,
+        &[
+            SmallRepoSpec::new("first", "first/")?,
+            SmallRepoSpec::new("second", "second/")?,
+        ],
+    )
+    .await?;
+
+    for (small_repo, prefix) in small_repos.iter().zip(["first", "second"]) {
+        let small_cs_id = CreateCommitContext::new_root(&ctx, &small_repo.repo)
+            .add_file("file", prefix)
+            .commit()
+            .await?;
+        let large_cs_id = small_repo
+            .commit_syncer()
+            .unsafe_sync_commit_with_expected_version(
+                &ctx,
+                small_cs_id,
+                CandidateSelectionHint::Only,
+                xrepo_mapping_version_with_small_repo(),
+                CommitSyncContext::Tests,
+            )
+            .await?
+            .ok_or_else(|| anyhow!("commit wasn't synced"))?;
+
+        assert_eq!(
+            list_working_copy_utf8(&ctx, &large_repo, large_cs_id).await?,
+            hashmap! { mpath(prefix).join(&mpath("file")) => prefix.to_string() }
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_map_based_mover() -> Result<(), Error> {
+    let mover = map_based_mover(hashmap! {
+        mpath("lib") => MoveAction::PrependPrefix(mpath("third-party")),
+        mpath("docs") => MoveAction::Rename(mpath("site/content")),
+        mpath("docs/internal") => MoveAction::Drop,
+    })?;
+
+    assert_eq!(
+        mover(&mpath("lib/foo.rs"))?,
+        Some(mpath("third-party/lib/foo.rs"))
+    );
+    assert_eq!(
+        mover(&mpath("docs/index.md"))?,
+        Some(mpath("site/content/index.md"))
+    );
+    // The longest prefix wins.
+    assert_eq!(mover(&mpath("docs/internal/secret.md"))?, None);
+    assert_eq!(mover(&mpath("README"))?, Some(mpath("README")));
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_sync_merge_large_to_small(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) = init_small_large_repo(&ctx).await?;
+    let large_to_small = &syncers.large_to_small;
+    let large_repo = large_to_small.get_source_repo();
+    let small_repo = large_to_small.get_target_repo();
+
+    let master = resolve_cs_id(&ctx, large_repo, "master").await?;
+    let first = CreateCommitContext::new(&ctx, large_repo, vec![master])
+        .add_file("prefix/first", "first")
+        .commit()
+        .await?;
+    let second = CreateCommitContext::new(&ctx, large_repo, vec![master])
+        .add_file("prefix/second", "second")
+        .commit()
+        .await?;
+    for cs_id in [first, second] {
+        large_to_small
+            .unsafe_sync_commit(
+                &ctx,
+                cs_id,
+                CandidateSelectionHint::Only,
+                CommitSyncContext::Tests,
+            )
+            .await?;
+    }
+
+    // Both parents are synced.
+    let (_, small_merge) = create_and_sync_merge(
+        &ctx,
+        large_to_small,
+        vec![first, second],
+        [("prefix/merge", "merge")],
+    )
+    .await?;
+    let small_merge = small_merge.ok_or_else(|| anyhow!("merge wasn't synced"))?;
+    let wc = list_working_copy_utf8(&ctx, small_repo, small_merge).await?;
+    assert!(wc.contains_key(&mpath("first")));
+    assert!(wc.contains_key(&mpath("second")));
+    assert!(wc.contains_key(&mpath("merge")));
+
+    // The other parent only has files outside of the small repo, so it is dropped.
+    let outside = CreateCommitContext::new_root(&ctx, large_repo)
+        .add_file("outside/file", "outside")
+        .commit()
+        .await?;
+    large_to_small
+        .unsafe_sync_commit_with_expected_version(
+            &ctx,
+            outside,
+            CandidateSelectionHint::Only,
+            xrepo_mapping_version_with_small_repo(),
+            CommitSyncContext::Tests,
+        )
+        .await?;
+
+    let (_, small_merge) = create_and_sync_merge(
+        &ctx,
+        large_to_small,
+        vec![first, outside],
+        [("prefix/merge", "merge"), ("outside/merge", "merge")],
+    )
+    .await?;
+    let small_merge = small_merge.ok_or_else(|| anyhow!("merge wasn't synced"))?;
+    let wc = list_working_copy_utf8(&ctx, small_repo, small_merge).await?;
+    assert!(wc.contains_key(&mpath("first")));
+    assert!(wc.contains_key(&mpath("merge")));
+    assert!(!wc.keys().any(|path| path.to_string().contains("outside")));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_assert_working_copy_equivalent(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) = init_small_large_repo(&ctx).await?;
+    let small_to_large = &syncers.small_to_large;
+    let small_repo = small_to_large.get_small_repo();
+    let large_repo = small_to_large.get_large_repo();
+
+    let small_master = resolve_cs_id(&ctx, small_repo, "master").await?;
+    let large_master = resolve_cs_id(&ctx, large_repo, "master").await?;
+    assert_working_copy_equivalent(&ctx, small_to_large, small_master, large_master).await?;
+
+    // Files outside of the small repo prefix are ignored.
+    let large_cs_id = CreateCommitContext::new(&ctx, large_repo, vec![large_master])
+        .add_file("outside", "content")
+        .commit()
+        .await?;
+    assert_working_copy_equivalent(&ctx, small_to_large, small_master, large_cs_id).await?;
+
+    // But a mismatch within the prefix is caught.
+    let large_cs_id = CreateCommitContext::new(&ctx, large_repo, vec![large_master])
+        .add_file("prefix/extra", "content")
+        .commit()
+        .await?;
+    let res = AssertUnwindSafe(assert_working_copy_equivalent(
+        &ctx,
+        small_to_large,
+        small_master,
+        large_cs_id,
+    ))
+    .catch_unwind()
+    .await;
+    assert!(res.is_err());
+
+    Ok(())
+}
+
+async fn working_copy_check_fails(
+    ctx: &CoreContext,
+    small_to_large: &CommitSyncer<SqlSyncedCommitMapping, TestRepo>,
+    small_bcs: ChangesetId,
+    large_bcs: ChangesetId,
+    strategy: EqualityStrategy,
+) -> bool {
+    AssertUnwindSafe(assert_working_copy_equivalent_with_strategy(
+        ctx,
+        small_to_large,
+        small_bcs,
+        large_bcs,
+        strategy,
+    ))
+    .catch_unwind()
+    .await
+    .map_or(true, |res| res.is_err())
+}
+
+#[fbinit::test]
+async fn test_working_copy_equality_strategies(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) = init_small_large_repo(&ctx).await?;
+    let small_to_large = &syncers.small_to_large;
+    let small_repo = small_to_large.get_small_repo();
+    let large_repo = small_to_large.get_large_repo();
+    let small_master = resolve_cs_id(&ctx, small_repo, "master").await?;
+    let large_master = resolve_cs_id(&ctx, large_repo, "master").await?;
+
+    // A faithfully synced commit passes all the checks.
+    let small_cs_id = CreateCommitContext::new(&ctx, small_repo, vec![small_master])
+        .add_file("file", "modified")
+        .commit()
+        .await?;
+    small_to_large
+        .unsafe_sync_commit(
+            &ctx,
+            small_cs_id,
+            CandidateSelectionHint::Only,
+            CommitSyncContext::Tests,
+        )
+        .await?;
+    let large_cs_id = match small_to_large
+        .get_commit_sync_outcome(&ctx, small_cs_id)
+        .await?
+    {
+        Some(CommitSyncOutcome::RewrittenAs(cs_id, _)) => cs_id,
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    };
+    for strategy in [
+        EqualityStrategy::FullManifest,
+        EqualityStrategy::ChangedPathsOnly,
+        EqualityStrategy::FileCountAndHashes,
+    ] {
+        assert_working_copy_equivalent_with_strategy(
+            &ctx,
+            small_to_large,
+            small_cs_id,
+            large_cs_id,
+            strategy,
+        )
+        .await?;
+    }
+
+    // A file at the wrong path is only caught by comparing the full manifests.
+    let renamed = CreateCommitContext::new(&ctx, large_repo, vec![large_master])
+        .delete_file("prefix/file")
+        .add_file("prefix/renamed", "content")
+        .commit()
+        .await?;
+    assert!(
+        working_copy_check_fails(
+            &ctx,
+            small_to_large,
+            small_master,
+            renamed,
+            EqualityStrategy::FullManifest
+        )
+        .await
+    );
+    assert!(
+        !working_copy_check_fails(
+            &ctx,
+            small_to_large,
+            small_master,
+            renamed,
+            EqualityStrategy::FileCountAndHashes
+        )
+        .await
+    );
+
+    // An extra file is caught by comparing the file counts.
+    let extra = CreateCommitContext::new(&ctx, large_repo, vec![large_master])
+        .add_file("prefix/extra", "content")
+        .commit()
+        .await?;
+    assert!(
+        working_copy_check_fails(
+            &ctx,
+            small_to_large,
+            small_master,
+            extra,
+            EqualityStrategy::FileCountAndHashes
+        )
+        .await
+    );
+
+    // A change synced with the wrong content is caught by comparing the changed paths.
+    let wrong_change = CreateCommitContext::new(&ctx, large_repo, vec![large_master])
+        .add_file("prefix/file", "not modified")
+        .commit()
+        .await?;
+    assert!(
+        working_copy_check_fails(
+            &ctx,
+            small_to_large,
+            small_cs_id,
+            wrong_change,
+            EqualityStrategy::ChangedPathsOnly
+        )
+        .await
+    );
+
+    // But the changed paths don't cover a divergence introduced earlier.
+    let same_change_on_extra = CreateCommitContext::new(&ctx, large_repo, vec![extra])
+        .add_file("prefix/file", "modified")
+        .commit()
+        .await?;
+    assert!(
+        !working_copy_check_fails(
+            &ctx,
+            small_to_large,
+            small_cs_id,
+            same_change_on_extra,
+            EqualityStrategy::ChangedPathsOnly
+        )
+        .await
+    );
+    assert!(
+        working_copy_check_fails(
+            &ctx,
+            small_to_large,
+            small_cs_id,
+            same_change_on_extra,
+            EqualityStrategy::FullManifest
+        )
+        .await
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_sync_deletions(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) = init_small_large_repo(&ctx).await?;
+    let small_repo = syncers.small_to_large.get_small_repo();
+    let large_repo = syncers.small_to_large.get_large_repo();
+    let small_master = resolve_cs_id(&ctx, small_repo, "master").await?;
+    let large_master = resolve_cs_id(&ctx, large_repo, "master").await?;
+
+    // A deletion in the small repo is applied under the prefix in the large repo.
+    let (small_cs_id, large_cs_id) =
+        create_and_sync_deletion(&ctx, &syncers.small_to_large, small_master, &["file3"]).await?;
+    let large_cs_id = large_cs_id.ok_or_else(|| anyhow!("deletion wasn't synced"))?;
+    assert_ne!(large_cs_id, large_master);
+    let wc = list_working_copy_utf8(&ctx, large_repo, large_cs_id).await?;
+    assert!(!wc.contains_key(&mpath("prefix/file3")));
+    assert!(wc.contains_key(&mpath("prefix/file")));
+    assert_working_copy_equivalent(&ctx, &syncers.small_to_large, small_cs_id, large_cs_id).await?;
+
+    // Deleting a file outside of the small repo prefix in the large repo doesn't change the small
+    // repo.
+    let outside = CreateCommitContext::new(&ctx, large_repo, vec![large_master])
+        .add_file("outside", "content")
+        .commit()
+        .await?;
+    let small_outside = syncers
+        .large_to_small
+        .unsafe_sync_commit(
+            &ctx,
+            outside,
+            CandidateSelectionHint::Only,
+            CommitSyncContext::Tests,
+        )
+        .await?;
+    assert_eq!(small_outside, None);
+
+    let (_, small_cs_id) =
+        create_and_sync_deletion(&ctx, &syncers.large_to_small, outside, &["outside"]).await?;
+    assert_eq!(small_cs_id, Some(small_master));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_sync_diamond_large_to_small(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) = init_small_large_repo(&ctx).await?;
+    let large_master =
+        resolve_cs_id(&ctx, syncers.large_to_small.get_large_repo(), "master").await?;
+
+    let (_, small) =
+        create_and_sync_diamond(&ctx, &syncers.large_to_small, large_master, "prefix").await?;
+
+    let small_repo = syncers.large_to_small.get_small_repo();
+    assert_eq!(small.base, resolve_cs_id(&ctx, small_repo, "master").await?);
+    let wc = list_working_copy_utf8(&ctx, small_repo, small.merge).await?;
+    assert_eq!(wc.get(&mpath("left")).map(String::as_str), Some("left"));
+    assert_eq!(wc.get(&mpath("right")).map(String::as_str), Some("right"));
+    assert_eq!(wc.get(&mpath("merge")).map(String::as_str), Some("merge"));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_sync_bookmark_with_prefix(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) =
+        init_small_large_repo_with_bookmark_prefix(&ctx, AsciiString::from_str("small/")?).await?;
+    let small_repo = syncers.small_to_large.get_small_repo();
+    let large_repo = syncers.small_to_large.get_large_repo();
+
+    let small_master = resolve_cs_id(&ctx, small_repo, "master").await?;
+    let small_cs_id = CreateCommitContext::new(&ctx, small_repo, vec![small_master])
+        .add_file("feature", "content")
+        .commit()
+        .await?;
+    move_bookmark(&ctx, small_repo, "feature", small_cs_id).await;
+
+    let large_cs_id = syncers
+        .small_to_large
+        .sync_commit(
+            &ctx,
+            small_cs_id,
+            CandidateSelectionHint::Only,
+            CommitSyncContext::Tests,
+            false,
+        )
+        .await?
+        .ok_or_else(|| anyhow!("commit wasn't synced"))?;
+
+    let large_bookmark = syncers
+        .small_to_large
+        .rename_bookmark(&BookmarkKey::new("feature")?)
+        .await?
+        .ok_or_else(|| anyhow!("bookmark wasn't renamed"))?;
+    assert_eq!(large_bookmark, BookmarkKey::new("small/feature")?);
+    move_bookmark(&ctx, large_repo, large_bookmark.as_str(), large_cs_id).await;
+    assert_eq!(
+        get_bookmark(&ctx, large_repo, "small/feature").await,
+        large_cs_id
+    );
+
+    // The prefix is stripped when syncing back, and the bookmarks without it aren't synced.
+    assert_eq!(
+        syncers
+            .large_to_small
+            .rename_bookmark(&large_bookmark)
+            .await?,
+        Some(BookmarkKey::new("feature")?)
+    );
+    assert_eq!(
+        syncers
+            .large_to_small
+            .rename_bookmark(&BookmarkKey::new("feature")?)
+            .await?,
+        None
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_sync_noop_version_change(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) = init_small_large_repo(&ctx).await?;
+
+    let (small_cs_id, large_cs_id) =
+        sync_across_noop_version_change(&ctx, &syncers.small_to_large).await?;
+    let version = assert_sync_outcome(
+        &ctx,
+        &syncers.large_to_small,
+        large_cs_id,
+        ExpectedOutcome::RewrittenAs(small_cs_id),
+    )
+    .await?;
+    assert_eq!(version, Some(xrepo_mapping_version_with_small_repo()));
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_assert_sync_outcome(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) = init_small_large_repo(&ctx).await?;
+    let small_repo = syncers.small_to_large.get_small_repo();
+    let large_repo = syncers.small_to_large.get_large_repo();
+    let small_master = resolve_cs_id(&ctx, small_repo, "master").await?;
+    let large_master = resolve_cs_id(&ctx, large_repo, "master").await?;
+
+    let version = assert_sync_outcome(
+        &ctx,
+        &syncers.small_to_large,
+        small_master,
+        ExpectedOutcome::RewrittenAs(large_master),
+    )
+    .await?;
+    assert_eq!(version, Some(xrepo_mapping_version_with_small_repo()));
+
+    let unsynced = CreateCommitContext::new(&ctx, small_repo, vec![small_master])
+        .add_file("unsynced", "content")
+        .commit()
+        .await?;
+    let version = assert_sync_outcome(
+        &ctx,
+        &syncers.small_to_large,
+        unsynced,
+        ExpectedOutcome::NotSynced,
+    )
+    .await?;
+    assert_eq!(version, None);
+
+    // A large repo commit outside of the small repo prefix is rewritten to nothing
+    let large_only = CreateCommitContext::new(&ctx, large_repo, vec![large_master])
+        .add_file("large_only", "content")
+        .commit()
+        .await?;
+    syncers
+        .large_to_small
+        .sync_commit(
+            &ctx,
+            large_only,
+            CandidateSelectionHint::Only,
+            CommitSyncContext::Tests,
+            false,
+        )
+        .await?;
+    assert_sync_outcome(
+        &ctx,
+        &syncers.large_to_small,
+        large_only,
+        ExpectedOutcome::EquivalentWorkingCopyAncestor(small_master),
+    )
+    .await?;
+
+    // The wrong kind of outcome fails the assertion
+    let res = AssertUnwindSafe(assert_sync_outcome(
+        &ctx,
+        &syncers.large_to_small,
+        large_only,
+        ExpectedOutcome::RewrittenAs(small_master),
+    ))
+    .catch_unwind()
+    .await;
+    assert!(res.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_mover_respects_map() -> Result<(), Error> {
+    let paths = [
+        "file",
+        "dir/file",
+        "special",
+        "special/file",
+        "special/dir/file",
+        "specialfile",
+        "dir/special/file",
+    ];
+    assert_mover_respects_map(&get_small_repo_sync_config_1(), &paths)?;
+    assert_mover_respects_map(&get_small_repo_sync_config_2(), &paths)?;
+
+    // The longest prefix of the map takes precedence
+    let config = SmallRepoCommitSyncConfig {
+        default_action: DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(MPath::new(
+            "prefix",
+        )?),
+        map: hashmap! {
+            MPath::new("special")? => MPath::new("special")?,
+            MPath::new("special/nested")? => MPath::new("nested")?,
+        },
+    };
+    assert_mover_respects_map(&config, &paths)?;
+    assert_mover_respects_map(&config, &["special/nested", "special/nested/file"])?;
+
+    Ok(())
+}
+
+#[test]
+fn test_movers_roundtrip() -> Result<(), Error> {
+    let paths = [
+        MPath::new("file")?,
+        MPath::new("dir/file")?,
+        // Not moved back by the reverse prefix mover
+        MPath::new("prefixfile")?,
+        MPath::new("other/prefix/file")?,
+        // Moved back to "file" and "dir/file"
+        MPath::new("prefix/file")?,
+        MPath::new("prefix/dir/file")?,
+    ];
+    let mover: Mover = Arc::new(prefix_mover);
+    let reverse_mover: Mover = Arc::new(reverse_prefix_mover);
+    assert_movers_roundtrip(&mover, &reverse_mover, &paths)?;
+
+    let commit_sync_config = CommitSyncConfig {
+        large_repo_id: RepositoryId::new(0),
+        common_pushrebase_bookmarks: vec![],
+        small_repos: hashmap! {
+            RepositoryId::new(1) => get_small_repo_sync_config_2(),
+        },
+        version_name: xrepo_mapping_version_with_small_repo(),
+    };
+    let mover = get_small_to_large_mover(&commit_sync_config, RepositoryId::new(1))?;
+    let reverse_mover = get_large_to_small_mover(&commit_sync_config, RepositoryId::new(1))?;
+    let special_paths = [MPath::new("special/file")?, MPath::new("special/dir/file")?];
+    assert_movers_roundtrip(&mover, &reverse_mover, &paths)?;
+    assert_movers_roundtrip(&mover, &reverse_mover, &special_paths)?;
+
+    // A reverse mover that doesn't strip the prefix fails the assertion
+    let mover: Mover = Arc::new(prefix_mover);
+    let res = std::panic::catch_unwind(AssertUnwindSafe(|| {
+        assert_movers_roundtrip(&mover, &mover, &paths)
+    }));
+    assert!(res.is_err());
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_build_syncers(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let large_repo_id = RepositoryId::new(1);
+    let (
+        LargeWithSmallRepos {
+            large_repo,
+            small_repos,
+            ..
+        },
+        commit_sync_config,
+    ) = build_syncers(
+        &ctx,
+        large_repo_id,
+        &[
+            SmallRepoSpec::new("first", "first/")?,
+            SmallRepoSpec::new("second", "second/")?
+                .with_direction(CommitSyncDirection::LargeToSmall)
+                .with_path_map(hashmap! { mpath("tools") => mpath("shared/tools") }),
+        ],
+    )
+    .await?;
+
+    assert_eq!(commit_sync_config.large_repo_id, large_repo_id);
+    let small_repo_ids: Vec<_> = small_repos
+        .iter()
+        .map(|small_repo| small_repo.repo.repo_identity().id())
+        .collect();
+    assert_eq!(
+        small_repo_ids,
+        vec![RepositoryId::new(0), RepositoryId::new(2)]
+    );
+    assert_eq!(
+        commit_sync_config.small_repos[&RepositoryId::new(2)].map,
+        hashmap! { mpath("tools") => mpath("shared/tools") }
+    );
+
+    // The second small repo is synced from the large repo.
+    let second = &small_repos[1];
+    assert_eq!(
+        second
+            .commit_syncer()
+            .get_source_repo()
+            .repo_identity()
+            .id(),
+        large_repo_id
+    );
+    let large_cs_id = CreateCommitContext::new_root(&ctx, &large_repo)
+        .add_file("second/file", "content")
+        .add_file("shared/tools/tool", "tool")
+        .commit()
+        .await?;
+    let small_cs_id = second
+        .commit_syncer()
+        .unsafe_sync_commit_with_expected_version(
+            &ctx,
+            large_cs_id,
+            CandidateSelectionHint::Only,
+            xrepo_mapping_version_with_small_repo(),
+            CommitSyncContext::Tests,
+        )
+        .await?
+        .ok_or_else(|| anyhow!("commit wasn't synced"))?;
+    assert_eq!(
+        list_working_copy_utf8(&ctx, &second.repo, small_cs_id).await?,
+        hashmap! {
+            mpath("file") => "content".to_string(),
+            mpath("tools/tool") => "tool".to_string(),
+        }
+    );
+
+    // Mapping paths of the second repo under the prefix of the first one is rejected.
+    let colliding = build_syncers(
+        &ctx,
+        large_repo_id,
+        &[
+            SmallRepoSpec::new("first", "first/")?,
+            SmallRepoSpec::new("second", "second/")?
+                .with_path_map(hashmap! { mpath("tools") => mpath("first/tools") }),
+        ],
+    )
+    .await;
+    assert!(colliding.is_err());
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_backsync_and_verify(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let LargeWithSmallRepos {
+        large_repo,
+        small_repos,
+        ..
+    } = init_large_with_small_repos(
+        &ctx,
+        &[SmallRepoSpec::new("prefix", "small/")?
+            .with_direction(CommitSyncDirection::LargeToSmall)],
+    )
+    .await?;
+    let small = &small_repos[0];
+
+    // Only the files under the prefix are backsynced, with the prefix stripped.
+    let large_root = CreateCommitContext::new_root(&ctx, &large_repo)
+        .add_file("prefix/file", "content")
+        .add_file("outside/file", "outside")
+        .commit()
+        .await?;
+    let small_root = backsync_and_verify(&ctx, &small.syncers, large_root)
+        .await?
+        .ok_or_else(|| anyhow!("root wasn't backsynced"))?;
+    assert_eq!(
+        list_working_copy_utf8(&ctx, &small.repo, small_root).await?,
+        hashmap! { mpath("file") => "content".to_string() }
+    );
+
+    // A commit only touching paths outside of the prefix is dropped.
+    let large_outside = CreateCommitContext::new(&ctx, &large_repo, vec![large_root])
+        .add_file("outside/other", "other")
+        .delete_file("outside/file")
+        .commit()
+        .await?;
+    assert_eq!(
+        backsync_and_verify(&ctx, &small.syncers, large_outside).await?,
+        None
+    );
+    assert_sync_outcome(
+        &ctx,
+        &small.syncers.large_to_small,
+        large_outside,
+        ExpectedOutcome::EquivalentWorkingCopyAncestor(small_root),
+    )
+    .await?;
+
+    // Its child is backsynced on top of the small repo commit it is equivalent to.
+    let large_child = CreateCommitContext::new(&ctx, &large_repo, vec![large_outside])
+        .add_file("prefix/dir/file", "new")
+        .delete_file("prefix/file")
+        .add_file("outside/file", "again")
+        .commit()
+        .await?;
+    let small_child = backsync_and_verify(&ctx, &small.syncers, large_child)
+        .await?
+        .ok_or_else(|| anyhow!("child wasn't backsynced"))?;
+    assert_eq!(
+        list_working_copy_utf8(&ctx, &small.repo, small_child).await?,
+        hashmap! { mpath("dir/file") => "new".to_string() }
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_get_sync_version(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let (syncers, _, _, _) = init_small_large_repo(&ctx).await?;
+    let small_repo = syncers.small_to_large.get_small_repo();
+
+    // The root is synced with the noop version, its child with the prefix one
+    let (small_child, large_child) =
+        sync_across_noop_version_change(&ctx, &syncers.small_to_large).await?;
+    let small_root = small_child
+        .load(&ctx, small_repo.repo_blobstore())
+        .await?
+        .parents()
+        .next()
+        .ok_or_else(|| anyhow!("child has no parent"))?;
+
+    assert_eq!(
+        get_sync_version(&ctx, &syncers.small_to_large, small_root).await?,
+        Some(xrepo_mapping_noop_version())
+    );
+    assert_eq!(
+        get_sync_version(&ctx, &syncers.small_to_large, small_child).await?,
+        Some(xrepo_mapping_version_with_small_repo())
+    );
+    // The mapping is recorded in both directions
+    assert_eq!(
+        get_sync_version(&ctx, &syncers.large_to_small, large_child).await?,
+        Some(xrepo_mapping_version_with_small_repo())
+    );
+
+    // A commit that wasn't synced has no version
+    let unsynced = CreateCommitContext::new(&ctx, small_repo, vec![small_child])
+        .add_file("unsynced", "unsynced")
+        .commit()
+        .await?;
+    assert_eq!(
+        get_sync_version(&ctx, &syncers.small_to_large, unsynced).await?,
+        None
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_try_sync_with_diagnostics(fb: FacebookInit) -> Result<(), Error> {
+    let ctx = CoreContext::test_mock(fb);
+    let LargeWithSmallRepos {
+        large_repo,
+        small_repos,
+        ..
+    } = init_large_with_small_repos(
+        &ctx,
+        &[SmallRepoSpec::new("prefix", "small/")?
+            .with_direction(CommitSyncDirection::LargeToSmall)],
+    )
+    .await?;
+    let small = &small_repos[0];
+    let large_to_small = &small.syncers.large_to_small;
+    let version = xrepo_mapping_version_with_small_repo();
+
+    // The paths under the prefix are moved, the others are dropped.
+    let large_root = CreateCommitContext::new_root(&ctx, &large_repo)
+        .add_file("prefix/file", "content")
+        .add_file("outside/file", "outside")
+        .commit()
+        .await?;
+    let diagnostics = try_sync_with_diagnostics(&ctx, large_to_small, large_root, &version).await?;
+    assert_eq!(
+        diagnostics.paths,
+        btreemap! {
+            mpath("outside/file") => None,
+            mpath("prefix/file") => Some(mpath("file")),
+        }
+    );
+    match diagnostics.outcome {
+        RewriteOutcome::Rewritten(rewritten) => assert_eq!(
+            rewritten
+                .file_changes()
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>(),
+            vec![mpath("file")],
+        ),
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    }
+    // Nothing was synced.
+    assert_sync_outcome(&ctx, large_to_small, large_root, ExpectedOutcome::NotSynced).await?;
+
+    // A commit only touching paths outside of the prefix is dropped.
+    backsync_and_verify(&ctx, &small.syncers, large_root).await?;
+    let large_outside = CreateCommitContext::new(&ctx, &large_repo, vec![large_root])
+        .add_file("outside/other", "other")
+        .commit()
+        .await?;
+    let diagnostics =
+        try_sync_with_diagnostics(&ctx, large_to_small, large_outside, &version).await?;
+    assert_eq!(
+        diagnostics.paths,
+        btreemap! { mpath("outside/other") => None }
+    );
+    assert_matches!(diagnostics.outcome, RewriteOutcome::Dropped);
+
+    // A commit whose parent isn't synced can't be rewritten.
+    let large_child = CreateCommitContext::new(&ctx, &large_repo, vec![large_outside])
+        .add_file("prefix/other", "other")
+        .commit()
+        .await?;
+    let diagnostics =
+        try_sync_with_diagnostics(&ctx, large_to_small, large_child, &version).await?;
+    assert_eq!(
+        diagnostics.paths,
+        btreemap! { mpath("prefix/other") => Some(mpath("other")) }
+    );
+    assert_matches!(diagnostics.outcome, RewriteOutcome::Failed(_));
+
+    Ok(())
+}
@@ -5,10 +5,13 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use anyhow::bail;
 use anyhow::format_err;
 use anyhow::Error;
 use ascii::AsciiString;
@@ -27,9 +30,11 @@ use commit_transformation::upload_commits;
 use context::CoreContext;
 use cross_repo_sync::rewrite_commit;
 use cross_repo_sync::update_mapping_with_version;
+use cross_repo_sync::CandidateSelectionHint;
 use cross_repo_sync::CommitRewrittenToEmpty;
 use cross_repo_sync::CommitSyncContext;
 use cross_repo_sync::CommitSyncDataProvider;
+use cross_repo_sync::CommitSyncOutcome;
 use cross_repo_sync::CommitSyncRepos;
 use cross_repo_sync::CommitSyncer;
 use cross_repo_sync::Repo;
@@ -44,15 +49,23 @@ use megarepolib::common::ChangesetArgs;
 use megarepolib::perform_move;
 use metaconfig_types::CommitSyncConfig;
 use metaconfig_types::CommitSyncConfigVersion;
+use metaconfig_types::CommitSyncDirection;
 use metaconfig_types::CommonCommitSyncConfig;
 use metaconfig_types::DefaultSmallToLargeCommitSyncPathAction;
 use metaconfig_types::RepoConfig;
 use metaconfig_types::SmallRepoCommitSyncConfig;
 use metaconfig_types::SmallRepoPermanentConfig;
+use mononoke_types::BonsaiChangeset;
 use mononoke_types::ChangesetId;
 use mononoke_types::DateTime;
 use mononoke_types::MPath;
 use mononoke_types::RepositoryId;
+use movers::get_large_to_small_mover;
+use movers::get_small_to_large_mover;
+use movers::mover_factory;
+use movers::DefaultAction;
+use movers::Mover;
+use movers::PrefixAction;
 use mutable_counters::MutableCounters;
 use phases::Phases;
 use repo_blobstore::RepoBlobstore;
@@ -65,6 +78,7 @@ use synced_commit_mapping::SyncedCommitMapping;
 use synced_commit_mapping::SyncedCommitMappingEntry;
 use test_repo_factory::TestRepoFactory;
 use tests_utils::bookmark;
+use tests_utils::list_working_copy;
 use tests_utils::CreateCommitContext;
 
 #[facet::container]
@@ -103,6 +117,11 @@ pub fn xrepo_mapping_version_with_small_repo() -> CommitSyncConfigVersion {
     CommitSyncConfigVersion("TEST_VERSION_NAME".to_string())
 }
 
+/// The version of `init_small_large_repo` that syncs the small repo paths unchanged.
+pub fn xrepo_mapping_noop_version() -> CommitSyncConfigVersion {
+    CommitSyncConfigVersion("noop".to_string())
+}
+
 // Helper function that takes a root commit from source repo and rebases it on master bookmark
 // in target repo
 pub async fn rebase_root_on_master<M, R>(
@@ -194,6 +213,23 @@ pub async fn init_small_large_repo(
         TestLiveCommitSyncConfigSource,
     ),
     Error,
+> {
+    init_small_large_repo_with_bookmark_prefix(ctx, AsciiString::new()).await
+}
+
+/// Same as `init_small_large_repo`, with the bookmarks of the small repo renamed to start with
+/// `bookmark_prefix` in the large repo. The prefix is stripped when going the other way.
+pub async fn init_small_large_repo_with_bookmark_prefix(
+    ctx: &CoreContext,
+    bookmark_prefix: AsciiString,
+) -> Result<
+    (
+        Syncers<SqlSyncedCommitMapping, TestRepo>,
+        CommitSyncConfig,
+        TestLiveCommitSyncConfig,
+        TestLiveCommitSyncConfigSource,
+    ),
+    Error,
 > {
     let mut factory = TestRepoFactory::new(ctx.fb)?;
     let megarepo: TestRepo = factory.with_id(RepositoryId::new(1)).build()?;
@@ -206,7 +242,7 @@ pub async fn init_small_large_repo(
         large_repo: megarepo.clone(),
     };
 
-    let noop_version = CommitSyncConfigVersion("noop".to_string());
+    let noop_version = xrepo_mapping_noop_version();
     let version_with_small_repo = xrepo_mapping_version_with_small_repo();
     let (sync_config, source) = TestLiveCommitSyncConfig::new_with_source();
 
@@ -235,7 +271,7 @@ pub async fn init_small_large_repo(
         common_pushrebase_bookmarks: vec![],
         small_repos: hashmap! {
             RepositoryId::new(0) => SmallRepoPermanentConfig {
-                bookmark_prefix: AsciiString::new(),
+                bookmark_prefix,
             }
         },
         large_repo_id: RepositoryId::new(1),
@@ -303,14 +339,16 @@ pub async fn init_small_large_repo(
         bookmark: None,
         mark_public: false,
     };
-    let move_hg_cs = perform_move(
-        ctx,
-        &megarepo.blob_repo,
-        second_bcs_id,
-        Arc::new(prefix_mover),
-        move_cs_args,
-    )
-    .await?;
+    // Syncing the moved files back to the small repo strips the prefix again
+    let mover: Mover = Arc::new(prefix_mover);
+    let reverse_mover: Mover = Arc::new(reverse_prefix_mover);
+    assert_movers_roundtrip(
+        &mover,
+        &reverse_mover,
+        &[MPath::new("file")?, MPath::new("file2")?],
+    )?;
+    let move_hg_cs =
+        perform_move(ctx, &megarepo.blob_repo, second_bcs_id, mover, move_cs_args).await?;
 
     let maybe_move_bcs_id = megarepo
         .bonsai_hg_mapping()
@@ -353,16 +391,13 @@ pub async fn init_small_large_repo(
     )
     .await?;
 
-    println!(
-        "small master: {}, large master: {}",
-        small_master_bcs_id, large_master_bcs_id
-    );
-    println!(
-        "{:?}",
-        small_to_large_commit_syncer
-            .get_commit_sync_outcome(ctx, small_master_bcs_id)
-            .await?
-    );
+    assert_sync_outcome(
+        ctx,
+        &small_to_large_commit_syncer,
+        small_master_bcs_id,
+        ExpectedOutcome::RewrittenAs(large_master_bcs_id),
+    )
+    .await?;
 
     Ok((
         Syncers {
@@ -375,6 +410,884 @@ pub async fn init_small_large_repo(
     ))
 }
 
+/// The sync outcome `assert_sync_outcome` expects for a commit, regardless of the version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpectedOutcome {
+    /// The commit wasn't synced at all.
+    NotSynced,
+    /// The commit isn't suitable for syncing to the target repo.
+    NotSyncCandidate,
+    /// The commit was rewritten as this commit of the target repo.
+    RewrittenAs(ChangesetId),
+    /// The commit was rewritten to nothing, and this commit of the target repo has the same
+    /// working copy.
+    EquivalentWorkingCopyAncestor(ChangesetId),
+}
+
+/// Asserts that the sync outcome of `cs_id` with `commit_syncer` is `expected`, failing with both
+/// outcomes otherwise. Returns the version the commit was synced with, if any.
+pub async fn assert_sync_outcome<M>(
+    ctx: &CoreContext,
+    commit_syncer: &CommitSyncer<M, TestRepo>,
+    cs_id: ChangesetId,
+    expected: ExpectedOutcome,
+) -> Result<Option<CommitSyncConfigVersion>, Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    let outcome = commit_syncer.get_commit_sync_outcome(ctx, cs_id).await?;
+    let (actual, version) = match outcome.clone() {
+        None => (ExpectedOutcome::NotSynced, None),
+        Some(CommitSyncOutcome::NotSyncCandidate(version)) => {
+            (ExpectedOutcome::NotSyncCandidate, Some(version))
+        }
+        Some(CommitSyncOutcome::RewrittenAs(target_cs_id, version)) => {
+            (ExpectedOutcome::RewrittenAs(target_cs_id), Some(version))
+        }
+        Some(CommitSyncOutcome::EquivalentWorkingCopyAncestor(target_cs_id, version)) => (
+            ExpectedOutcome::EquivalentWorkingCopyAncestor(target_cs_id),
+            Some(version),
+        ),
+    };
+    if actual != expected {
+        panic!(
+            "unexpected sync outcome of {} from repo {} to repo {}\n\
+             expected: {:?}\n\
+             actual: {:?}",
+            cs_id,
+            commit_syncer.get_source_repo().repo_identity().id(),
+            commit_syncer.get_target_repo().repo_identity().id(),
+            expected,
+            outcome,
+        );
+    }
+
+    Ok(version)
+}
+
+/// Exercises the switch of a small repo from the `noop` version of `init_small_large_repo` to
+/// the prefix one, `xrepo_mapping_version_with_small_repo`, with the small to large syncer.
+///
+/// Syncs a new root commit of the small repo with the `noop` version, then a child of it with the
+/// prefix version. Asserts that:
+/// - the root is `RewrittenAs` with the `noop` version, with its file at the same path in the
+///   large repo.
+/// - the child is `RewrittenAs` with the prefix version, on top of the synced root, with its own
+///   file moved under `prefix`. The file of the root keeps its path, as only the files changed by
+///   the child are moved.
+///
+/// Returns the ids of the child in the small and large repos.
+pub async fn sync_across_noop_version_change<M>(
+    ctx: &CoreContext,
+    small_to_large: &CommitSyncer<M, TestRepo>,
+) -> Result<(ChangesetId, ChangesetId), Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    let noop_version = xrepo_mapping_noop_version();
+    let prefix_version = xrepo_mapping_version_with_small_repo();
+    let small_repo = small_to_large.get_small_repo();
+    let large_repo = small_to_large.get_large_repo();
+    let noop_file = MPath::new("noop_file")?;
+    let prefix_file = MPath::new("prefix_file")?;
+
+    // The movers of both versions, as returned by `get_mover_by_version`
+    let noop_mover = small_to_large.get_mover_by_version(&noop_version).await?;
+    assert_eq!(noop_mover(&noop_file)?, Some(noop_file.clone()));
+    let prefix_mover = small_to_large.get_mover_by_version(&prefix_version).await?;
+    let moved_prefix_file = MPath::new("prefix")?.join(&prefix_file);
+    assert_eq!(prefix_mover(&prefix_file)?, Some(moved_prefix_file.clone()));
+
+    let small_root = CreateCommitContext::new_root(ctx, small_repo)
+        .add_file(noop_file.clone(), "noop")
+        .commit()
+        .await?;
+    let large_root = small_to_large
+        .unsafe_always_rewrite_sync_commit(
+            ctx,
+            small_root,
+            None, // parents override
+            &noop_version,
+            CommitSyncContext::Tests,
+        )
+        .await?
+        .ok_or_else(|| format_err!("{} wasn't synced with the noop version", small_root))?;
+    let version = assert_sync_outcome(
+        ctx,
+        small_to_large,
+        small_root,
+        ExpectedOutcome::RewrittenAs(large_root),
+    )
+    .await?;
+    assert_eq!(version, Some(noop_version));
+    assert_eq!(
+        list_working_copy(ctx, large_repo, large_root)
+            .await?
+            .into_keys()
+            .collect::<BTreeSet<_>>(),
+        BTreeSet::from([noop_file.clone()])
+    );
+
+    let small_child = CreateCommitContext::new(ctx, small_repo, vec![small_root])
+        .add_file(prefix_file.clone(), "prefix")
+        .commit()
+        .await?;
+    let large_child = small_to_large
+        .unsafe_always_rewrite_sync_commit(
+            ctx,
+            small_child,
+            None, // parents override
+            &prefix_version,
+            CommitSyncContext::Tests,
+        )
+        .await?
+        .ok_or_else(|| format_err!("{} wasn't synced with the prefix version", small_child))?;
+    let version = assert_sync_outcome(
+        ctx,
+        small_to_large,
+        small_child,
+        ExpectedOutcome::RewrittenAs(large_child),
+    )
+    .await?;
+    assert_eq!(version, Some(prefix_version));
+
+    // The parent is remapped to the commit synced with the previous version
+    let large_child_bcs = large_child.load(ctx, large_repo.repo_blobstore()).await?;
+    assert_eq!(
+        large_child_bcs.parents().collect::<Vec<_>>(),
+        vec![large_root]
+    );
+    assert_eq!(
+        list_working_copy(ctx, large_repo, large_child)
+            .await?
+            .into_keys()
+            .collect::<BTreeSet<_>>(),
+        BTreeSet::from([noop_file, moved_prefix_file])
+    );
+
+    Ok((small_child, large_child))
+}
+
+/// Creates a merge of `parents` in the source repo of `commit_syncer`, adding `files`, and syncs
+/// it. Merges can only be synced from the large repo to a small one.
+///
+/// Asserts that the synced merge has the synced versions of `parents` as parents, in the same
+/// order. The parents that aren't sync candidates for the target repo are expected to be dropped,
+/// as are the files they introduced. Returns the ids of the merge in the source and target repos,
+/// the latter being `None` if the merge isn't a sync candidate either.
+pub async fn create_and_sync_merge<M>(
+    ctx: &CoreContext,
+    commit_syncer: &CommitSyncer<M, TestRepo>,
+    parents: Vec<ChangesetId>,
+    files: impl IntoIterator<Item = (&str, &str)>,
+) -> Result<(ChangesetId, Option<ChangesetId>), Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    let mut expected_parents = Vec::new();
+    for parent in &parents {
+        match commit_syncer.get_commit_sync_outcome(ctx, *parent).await? {
+            Some(CommitSyncOutcome::RewrittenAs(cs_id, _))
+            | Some(CommitSyncOutcome::EquivalentWorkingCopyAncestor(cs_id, _)) => {
+                expected_parents.push(cs_id)
+            }
+            Some(CommitSyncOutcome::NotSyncCandidate(_)) => {}
+            None => bail!("parent {} of the merge isn't synced", parent),
+        }
+    }
+
+    let source_cs_id = CreateCommitContext::new(ctx, commit_syncer.get_source_repo(), parents)
+        .add_files(files)
+        .commit()
+        .await?;
+    commit_syncer
+        .unsafe_sync_commit(
+            ctx,
+            source_cs_id,
+            CandidateSelectionHint::Only,
+            CommitSyncContext::Tests,
+        )
+        .await?;
+
+    let target_cs_id = match commit_syncer
+        .get_commit_sync_outcome(ctx, source_cs_id)
+        .await?
+    {
+        Some(CommitSyncOutcome::RewrittenAs(cs_id, _)) => cs_id,
+        Some(CommitSyncOutcome::EquivalentWorkingCopyAncestor(cs_id, _)) => {
+            // Only a merge with a single remaining parent can be rewritten to nothing.
+            assert_eq!(vec![cs_id], expected_parents);
+            return Ok((source_cs_id, Some(cs_id)));
+        }
+        Some(CommitSyncOutcome::NotSyncCandidate(_)) => {
+            assert!(expected_parents.is_empty());
+            return Ok((source_cs_id, None));
+        }
+        None => bail!("merge {} wasn't synced", source_cs_id),
+    };
+
+    let target_bcs = target_cs_id
+        .load(ctx, commit_syncer.get_target_repo().repo_blobstore())
+        .await?;
+    assert_eq!(target_bcs.parents().collect::<Vec<_>>(), expected_parents);
+
+    Ok((source_cs_id, Some(target_cs_id)))
+}
+
+/// How thoroughly `assert_working_copy_equivalent_with_strategy` compares the working copies.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EqualityStrategy {
+    /// Compare every path of the small repo working copy, moved, and its content with the large
+    /// repo working copy.
+    #[default]
+    FullManifest,
+    /// Only compare the file changes of the small repo commit, moved, with those of the large repo
+    /// commit, without listing the working copies. Catches a commit whose changes weren't synced
+    /// faithfully, but not a divergence introduced by an earlier commit. The large repo commit must
+    /// be the one the small repo commit was rewritten as.
+    ChangedPathsOnly,
+    /// Compare the number of files and their contents, regardless of their paths. Catches missing,
+    /// extra or modified files, but not a file synced to the wrong path.
+    FileCountAndHashes,
+}
+
+/// Asserts that moving the paths of the working copy of `small_bcs` with the mover of the version
+/// it was synced with gives exactly the files of the working copy of `large_bcs` that belong to
+/// the small repo, with the same content. Fails with the list of mismatched paths otherwise.
+pub async fn assert_working_copy_equivalent<M>(
+    ctx: &CoreContext,
+    small_to_large: &CommitSyncer<M, TestRepo>,
+    small_bcs: ChangesetId,
+    large_bcs: ChangesetId,
+) -> Result<(), Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    assert_working_copy_equivalent_with_strategy(
+        ctx,
+        small_to_large,
+        small_bcs,
+        large_bcs,
+        EqualityStrategy::default(),
+    )
+    .await
+}
+
+/// Same as `assert_working_copy_equivalent`, comparing the working copies as thoroughly as
+/// `strategy` requires.
+pub async fn assert_working_copy_equivalent_with_strategy<M>(
+    ctx: &CoreContext,
+    small_to_large: &CommitSyncer<M, TestRepo>,
+    small_bcs: ChangesetId,
+    large_bcs: ChangesetId,
+    strategy: EqualityStrategy,
+) -> Result<(), Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    let version = match small_to_large
+        .get_commit_sync_outcome(ctx, small_bcs)
+        .await?
+    {
+        Some(CommitSyncOutcome::RewrittenAs(_, version))
+        | Some(CommitSyncOutcome::EquivalentWorkingCopyAncestor(_, version)) => version,
+        outcome => bail!(
+            "{} wasn't synced to the large repo: {:?}",
+            small_bcs,
+            outcome
+        ),
+    };
+    let mover = small_to_large.get_mover_by_version(&version).await?;
+    let reverse_mover = small_to_large
+        .get_reverse_mover_by_version(&version)
+        .await?;
+
+    if strategy == EqualityStrategy::ChangedPathsOnly {
+        let small_changeset = small_bcs
+            .load(ctx, small_to_large.get_small_repo().repo_blobstore())
+            .await?;
+        let mut moved_small_changes = BTreeMap::new();
+        for (path, change) in small_changeset.file_changes() {
+            if let Some(path) = mover(path)? {
+                moved_small_changes
+                    .insert(path, change.simplify().map(|change| change.content_id()));
+            }
+        }
+
+        let large_changeset = large_bcs
+            .load(ctx, small_to_large.get_large_repo().repo_blobstore())
+            .await?;
+        let mut large_changes = BTreeMap::new();
+        for (path, change) in large_changeset.file_changes() {
+            if reverse_mover(path)?.is_some() {
+                large_changes.insert(
+                    path.clone(),
+                    change.simplify().map(|change| change.content_id()),
+                );
+            }
+        }
+
+        assert_eq!(
+            moved_small_changes, large_changes,
+            "file changes of {} (small) and {} (large) differ with version {}",
+            small_bcs, large_bcs, version,
+        );
+        return Ok(());
+    }
+
+    let mut moved_small_files = BTreeMap::new();
+    for (path, content) in
+        list_working_copy(ctx, small_to_large.get_small_repo(), small_bcs).await?
+    {
+        if let Some(path) = mover(&path)? {
+            moved_small_files.insert(path, content);
+        }
+    }
+
+    let mut large_files = BTreeMap::new();
+    for (path, content) in
+        list_working_copy(ctx, small_to_large.get_large_repo(), large_bcs).await?
+    {
+        if reverse_mover(&path)?.is_some() {
+            large_files.insert(path, content);
+        }
+    }
+
+    if strategy == EqualityStrategy::FileCountAndHashes {
+        let mut small_contents: Vec<_> = moved_small_files.into_values().collect();
+        let mut large_contents: Vec<_> = large_files.into_values().collect();
+        assert_eq!(
+            small_contents.len(),
+            large_contents.len(),
+            "working copies of {} (small) and {} (large) have different file counts with version {}",
+            small_bcs,
+            large_bcs,
+            version,
+        );
+        small_contents.sort();
+        large_contents.sort();
+        assert!(
+            small_contents == large_contents,
+            "working copies of {} (small) and {} (large) have different file contents with version {}",
+            small_bcs,
+            large_bcs,
+            version,
+        );
+        return Ok(());
+    }
+
+    if moved_small_files != large_files {
+        let only_small: Vec<_> = moved_small_files
+            .keys()
+            .filter(|path| !large_files.contains_key(path))
+            .collect();
+        let only_large: Vec<_> = large_files
+            .keys()
+            .filter(|path| !moved_small_files.contains_key(path))
+            .collect();
+        let different: Vec<_> = moved_small_files
+            .iter()
+            .filter(|(path, content)| {
+                large_files
+                    .get(path)
+                    .map_or(false, |large_content| large_content != *content)
+            })
+            .map(|(path, _)| path)
+            .collect();
+        panic!(
+            "working copies of {} (small) and {} (large) differ with version {}\n\
+             moved small paths missing from large: {:?}\n\
+             large paths missing from small: {:?}\n\
+             paths with different content: {:?}",
+            small_bcs, large_bcs, version, only_small, only_large, different,
+        );
+    }
+
+    Ok(())
+}
+
+/// Creates a commit deleting `paths` on top of `parent` in the source repo of `commit_syncer`, and
+/// syncs it. Returns the ids of the deletion in the source repo and of the commit it is equivalent
+/// to in the target repo: when none of the deleted paths maps to the target repo, the deletion is
+/// rewritten to nothing and the latter is the synced version of `parent`.
+pub async fn create_and_sync_deletion<M>(
+    ctx: &CoreContext,
+    commit_syncer: &CommitSyncer<M, TestRepo>,
+    parent: ChangesetId,
+    paths: &[&str],
+) -> Result<(ChangesetId, Option<ChangesetId>), Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    let source_cs_id = paths
+        .iter()
+        .fold(
+            CreateCommitContext::new(ctx, commit_syncer.get_source_repo(), vec![parent]),
+            |commit, path| commit.delete_file(*path),
+        )
+        .commit()
+        .await?;
+    commit_syncer
+        .unsafe_sync_commit(
+            ctx,
+            source_cs_id,
+            CandidateSelectionHint::Only,
+            CommitSyncContext::Tests,
+        )
+        .await?;
+
+    let target_cs_id = match commit_syncer
+        .get_commit_sync_outcome(ctx, source_cs_id)
+        .await?
+    {
+        Some(CommitSyncOutcome::RewrittenAs(cs_id, _))
+        | Some(CommitSyncOutcome::EquivalentWorkingCopyAncestor(cs_id, _)) => Some(cs_id),
+        Some(CommitSyncOutcome::NotSyncCandidate(_)) => None,
+        None => bail!("deletion {} wasn't synced", source_cs_id),
+    };
+
+    Ok((source_cs_id, target_cs_id))
+}
+
+/// Backsyncs `large_bcs`, a commit of the large repo, to the small repo of `syncers` with the
+/// `large_to_small` syncer, which records the commit in the synced commit mapping.
+///
+/// Asserts that the file changes of the synced commit are exactly those of `large_bcs` that belong
+/// to the small repo, with their paths moved by the mover of the version used, and that the
+/// mapping resolves the synced commit back to `large_bcs`. Returns the id of the synced commit,
+/// or `None` if `large_bcs` doesn't touch the small repo and was rewritten to nothing.
+pub async fn backsync_and_verify<M>(
+    ctx: &CoreContext,
+    syncers: &Syncers<M, TestRepo>,
+    large_bcs: ChangesetId,
+) -> Result<Option<ChangesetId>, Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    let large_to_small = &syncers.large_to_small;
+    large_to_small
+        .unsafe_sync_commit(
+            ctx,
+            large_bcs,
+            CandidateSelectionHint::Only,
+            CommitSyncContext::Tests,
+        )
+        .await?;
+
+    let (small_bcs, version) = match large_to_small
+        .get_commit_sync_outcome(ctx, large_bcs)
+        .await?
+    {
+        Some(CommitSyncOutcome::RewrittenAs(cs_id, version)) => (Some(cs_id), version),
+        Some(CommitSyncOutcome::EquivalentWorkingCopyAncestor(_, version)) => (None, version),
+        outcome => bail!(
+            "{} wasn't backsynced to the small repo: {:?}",
+            large_bcs,
+            outcome
+        ),
+    };
+
+    let mover = large_to_small.get_mover_by_version(&version).await?;
+    let large_changeset = large_bcs
+        .load(ctx, large_to_small.get_large_repo().repo_blobstore())
+        .await?;
+    let mut expected_changes = BTreeMap::new();
+    for (path, change) in large_changeset.file_changes() {
+        if let Some(path) = mover(path)? {
+            expected_changes.insert(path, change.simplify().map(|change| change.content_id()));
+        }
+    }
+
+    let small_bcs = match small_bcs {
+        Some(small_bcs) => small_bcs,
+        None => {
+            assert!(
+                expected_changes.is_empty(),
+                "{} was rewritten to nothing but changes small repo paths: {:?}",
+                large_bcs,
+                expected_changes.keys().collect::<Vec<_>>(),
+            );
+            return Ok(None);
+        }
+    };
+
+    let small_changeset = small_bcs
+        .load(ctx, large_to_small.get_small_repo().repo_blobstore())
+        .await?;
+    let small_changes: BTreeMap<_, _> = small_changeset
+        .file_changes()
+        .map(|(path, change)| {
+            (
+                path.clone(),
+                change.simplify().map(|change| change.content_id()),
+            )
+        })
+        .collect();
+    assert_eq!(
+        small_changes, expected_changes,
+        "file changes of {} (small) don't match those of {} (large) with version {}",
+        small_bcs, large_bcs, version,
+    );
+
+    assert_sync_outcome(
+        ctx,
+        &syncers.small_to_large,
+        small_bcs,
+        ExpectedOutcome::RewrittenAs(large_bcs),
+    )
+    .await?;
+
+    Ok(Some(small_bcs))
+}
+
+/// What rewriting a commit with a mover would produce, see `try_sync_with_diagnostics`.
+#[derive(Debug)]
+pub enum RewriteOutcome {
+    /// The commit was rewritten as this commit of the target repo.
+    Rewritten(BonsaiChangeset),
+    /// The commit was rewritten to nothing, as none of its changed paths map to the target repo.
+    Dropped,
+    /// The commit couldn't be rewritten.
+    Failed(Error),
+}
+
+/// The outcome of `try_sync_with_diagnostics`, along with how each path changed by the source
+/// commit maps to the target repo.
+#[derive(Debug)]
+pub struct RewriteDiagnostics {
+    pub outcome: RewriteOutcome,
+    /// The paths changed by the source commit, and the target repo path each one is moved to, if
+    /// any. Stops at the first path the mover fails on.
+    pub paths: BTreeMap<MPath, Option<MPath>>,
+}
+
+/// Rewrites `source_bcs_id` with the mover of `version` of `commit_syncer`, without uploading
+/// the result or recording it in the mapping, and reports why it was rewritten the way it was.
+/// The parents of the commit must already be synced.
+///
+/// Unlike the syncing helpers, a commit rewritten to nothing or failing to be rewritten is
+/// reported rather than unwrapped, which helps with debugging mover configs.
+pub async fn try_sync_with_diagnostics<M>(
+    ctx: &CoreContext,
+    commit_syncer: &CommitSyncer<M, TestRepo>,
+    source_bcs_id: ChangesetId,
+    version: &CommitSyncConfigVersion,
+) -> Result<RewriteDiagnostics, Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    let source_repo = commit_syncer.get_source_repo();
+    let source_bcs = source_bcs_id
+        .load(ctx, source_repo.repo_blobstore())
+        .await?;
+    let mover = commit_syncer.get_mover_by_version(version).await?;
+
+    let mut paths = BTreeMap::new();
+    for (path, _change) in source_bcs.file_changes() {
+        match mover(path) {
+            Ok(target_path) => {
+                paths.insert(path.clone(), target_path);
+            }
+            Err(err) => {
+                return Ok(RewriteDiagnostics {
+                    outcome: RewriteOutcome::Failed(
+                        err.context(format!("failed to move {}", path)),
+                    ),
+                    paths,
+                });
+            }
+        }
+    }
+
+    let outcome = async {
+        let mut remapped_parents = HashMap::new();
+        for parent in source_bcs.parents() {
+            match commit_syncer.get_commit_sync_outcome(ctx, parent).await? {
+                Some(CommitSyncOutcome::RewrittenAs(target_cs_id, _))
+                | Some(CommitSyncOutcome::EquivalentWorkingCopyAncestor(target_cs_id, _)) => {
+                    remapped_parents.insert(parent, target_cs_id);
+                }
+                outcome => bail!("parent {} isn't synced: {:?}", parent, outcome),
+            }
+        }
+
+        let rewritten = rewrite_commit(
+            ctx,
+            source_bcs.clone().into_mut(),
+            &remapped_parents,
+            mover,
+            source_repo,
+            CommitRewrittenToEmpty::Discard,
+        )
+        .await?;
+        match rewritten {
+            Some(rewritten) => Ok(RewriteOutcome::Rewritten(rewritten.freeze()?)),
+            None => Ok(RewriteOutcome::Dropped),
+        }
+    }
+    .await
+    .unwrap_or_else(RewriteOutcome::Failed);
+
+    Ok(RewriteDiagnostics { outcome, paths })
+}
+
+/// The commits of a diamond: two branches forked off `base` and merged back together.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Diamond {
+    pub base: ChangesetId,
+    pub left: ChangesetId,
+    pub right: ChangesetId,
+    pub merge: ChangesetId,
+}
+
+/// Creates a diamond on top of the already synced `base` in the large repo, with each side adding
+/// a file under `prefix`, and syncs it to the small repo with `CommitSyncer::sync_commit`.
+///
+/// Asserts that the small repo ends up with the same diamond, and returns the diamonds of the
+/// large and small repos.
+pub async fn create_and_sync_diamond<M>(
+    ctx: &CoreContext,
+    large_to_small: &CommitSyncer<M, TestRepo>,
+    base: ChangesetId,
+    prefix: &str,
+) -> Result<(Diamond, Diamond), Error>
+where
+    M: SyncedCommitMapping + Clone + 'static,
+{
+    let large_repo = large_to_small.get_large_repo();
+    let left = CreateCommitContext::new(ctx, large_repo, vec![base])
+        .add_file(format!("{}/left", prefix).as_str(), "left")
+        .commit()
+        .await?;
+    let right = CreateCommitContext::new(ctx, large_repo, vec![base])
+        .add_file(format!("{}/right", prefix).as_str(), "right")
+        .commit()
+        .await?;
+    let merge = CreateCommitContext::new(ctx, large_repo, vec![left, right])
+        .add_file(format!("{}/merge", prefix).as_str(), "merge")
+        .commit()
+        .await?;
+    let large = Diamond {
+        base,
+        left,
+        right,
+        merge,
+    };
+
+    let mut synced = HashMap::new();
+    for cs_id in [base, left, right, merge] {
+        large_to_small
+            .sync_commit(
+                ctx,
+                cs_id,
+                CandidateSelectionHint::Only,
+                CommitSyncContext::Tests,
+                false, // disable lease
+            )
+            .await?;
+        match large_to_small.get_commit_sync_outcome(ctx, cs_id).await? {
+            Some(CommitSyncOutcome::RewrittenAs(synced_cs_id, _))
+            | Some(CommitSyncOutcome::EquivalentWorkingCopyAncestor(synced_cs_id, _)) => {
+                synced.insert(cs_id, synced_cs_id);
+            }
+            outcome => bail!("{} wasn't synced to the small repo: {:?}", cs_id, outcome),
+        }
+    }
+    let small = Diamond {
+        base: synced[&base],
+        left: synced[&left],
+        right: synced[&right],
+        merge: synced[&merge],
+    };
+
+    let small_repo = large_to_small.get_small_repo();
+    let small_parents = |cs_id: ChangesetId| async move {
+        let bcs = cs_id.load(ctx, small_repo.repo_blobstore()).await?;
+        Result::<_, Error>::Ok(bcs.parents().collect::<Vec<_>>())
+    };
+    assert_eq!(small_parents(small.left).await?, vec![small.base]);
+    assert_eq!(small_parents(small.right).await?, vec![small.base]);
+    assert_eq!(
+        small_parents(small.merge).await?,
+        vec![small.left, small.right]
+    );
+
+    Ok((large, small))
+}
+
+/// Description of one of the small repos built by `build_syncers`.
+pub struct SmallRepoSpec {
+    /// Directory of the large repo the small repo is mapped to.
+    pub prefix: MPath,
+    /// Prefix of the small repo bookmarks in the large repo.
+    pub bookmark_prefix: AsciiString,
+    /// The direction the commits of the small repo are mostly synced in, picked by
+    /// `SyncedSmallRepo::commit_syncer`.
+    pub direction: CommitSyncDirection,
+    /// Small repo path prefixes mapped to another large repo path than the one under `prefix`.
+    pub path_map: HashMap<MPath, MPath>,
+}
+
+impl SmallRepoSpec {
+    pub fn new(prefix: &str, bookmark_prefix: &str) -> Result<Self, Error> {
+        Ok(Self {
+            prefix: MPath::new(prefix)?,
+            bookmark_prefix: AsciiString::from_str(bookmark_prefix)?,
+            direction: CommitSyncDirection::SmallToLarge,
+            path_map: HashMap::new(),
+        })
+    }
+
+    pub fn with_direction(mut self, direction: CommitSyncDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_path_map(mut self, path_map: HashMap<MPath, MPath>) -> Self {
+        self.path_map = path_map;
+        self
+    }
+
+    fn commit_sync_config(&self) -> SmallRepoCommitSyncConfig {
+        SmallRepoCommitSyncConfig {
+            default_action: DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(
+                self.prefix.clone(),
+            ),
+            map: self.path_map.clone(),
+        }
+    }
+}
+
+pub struct SyncedSmallRepo {
+    pub repo: TestRepo,
+    /// The syncers between the small repo and the large repo.
+    pub syncers: Syncers<SqlSyncedCommitMapping, TestRepo>,
+    pub direction: CommitSyncDirection,
+}
+
+impl SyncedSmallRepo {
+    /// The syncer in the direction of the spec the small repo was built from.
+    pub fn commit_syncer(&self) -> &CommitSyncer<SqlSyncedCommitMapping, TestRepo> {
+        match self.direction {
+            CommitSyncDirection::SmallToLarge => &self.syncers.small_to_large,
+            CommitSyncDirection::LargeToSmall => &self.syncers.large_to_small,
+        }
+    }
+}
+
+pub struct LargeWithSmallRepos {
+    pub large_repo: TestRepo,
+    /// The small repos, in the order of the specs they were built from.
+    pub small_repos: Vec<SyncedSmallRepo>,
+    pub live_commit_sync_config: TestLiveCommitSyncConfig,
+    pub source: TestLiveCommitSyncConfigSource,
+}
+
+/// Builds an empty large repo (with id 0) and one empty small repo per spec (with ids starting at
+/// 1), all sharing the same synced commit mapping. See `build_syncers`.
+pub async fn init_large_with_small_repos(
+    ctx: &CoreContext,
+    specs: &[SmallRepoSpec],
+) -> Result<LargeWithSmallRepos, Error> {
+    let (repos, _commit_sync_config) = build_syncers(ctx, RepositoryId::new(0), specs).await?;
+    Ok(repos)
+}
+
+/// Builds an empty large repo with id `large_repo_id` and one empty small repo per spec, all
+/// sharing the same synced commit mapping. The small repos get the lowest ids other than
+/// `large_repo_id`, in the order of `specs`.
+///
+/// Every small repo is mapped to the large repo as its spec says in the
+/// `xrepo_mapping_version_with_small_repo` config version, which is returned along with the
+/// repos. Fails if that config doesn't pass `CommitSyncConfig::validate`.
+pub async fn build_syncers(
+    ctx: &CoreContext,
+    large_repo_id: RepositoryId,
+    specs: &[SmallRepoSpec],
+) -> Result<(LargeWithSmallRepos, CommitSyncConfig), Error> {
+    let mut factory = TestRepoFactory::new(ctx.fb)?;
+    let large_repo: TestRepo = factory.with_id(large_repo_id).build()?;
+    let mapping =
+        SqlSyncedCommitMapping::from_sql_connections(factory.metadata_db().clone().into());
+
+    let small_repo_ids = (0..)
+        .map(RepositoryId::new)
+        .filter(|id| *id != large_repo_id);
+    let mut small_repos = Vec::new();
+    let mut small_repo_configs = HashMap::new();
+    let mut small_repo_permanent_configs = HashMap::new();
+    for (spec, small_repo_id) in specs.iter().zip(small_repo_ids) {
+        let small_repo: TestRepo = factory.with_id(small_repo_id).build()?;
+        small_repos.push((small_repo, spec.direction));
+        small_repo_configs.insert(small_repo_id, spec.commit_sync_config());
+        small_repo_permanent_configs.insert(
+            small_repo_id,
+            SmallRepoPermanentConfig {
+                bookmark_prefix: spec.bookmark_prefix.clone(),
+            },
+        );
+    }
+
+    let commit_sync_config = CommitSyncConfig {
+        large_repo_id,
+        common_pushrebase_bookmarks: vec![BookmarkKey::new("master")?],
+        small_repos: small_repo_configs,
+        version_name: xrepo_mapping_version_with_small_repo(),
+    };
+    commit_sync_config.validate()?;
+    let (live_commit_sync_config, source) = TestLiveCommitSyncConfig::new_with_source();
+    source.add_config(commit_sync_config.clone());
+    source.add_common_config(CommonCommitSyncConfig {
+        common_pushrebase_bookmarks: vec![],
+        small_repos: small_repo_permanent_configs,
+        large_repo_id,
+    });
+
+    let commit_sync_data_provider =
+        CommitSyncDataProvider::Live(Arc::new(live_commit_sync_config.clone()));
+    let small_repos = small_repos
+        .into_iter()
+        .map(|(small_repo, direction)| {
+            let small_to_large = CommitSyncer::new_with_provider(
+                ctx,
+                mapping.clone(),
+                CommitSyncRepos::SmallToLarge {
+                    small_repo: small_repo.clone(),
+                    large_repo: large_repo.clone(),
+                },
+                commit_sync_data_provider.clone(),
+            );
+            let large_to_small = CommitSyncer::new_with_provider(
+                ctx,
+                mapping.clone(),
+                CommitSyncRepos::LargeToSmall {
+                    small_repo: small_repo.clone(),
+                    large_repo: large_repo.clone(),
+                },
+                commit_sync_data_provider.clone(),
+            );
+            SyncedSmallRepo {
+                repo: small_repo,
+                syncers: Syncers {
+                    small_to_large,
+                    large_to_small,
+                },
+                direction,
+            }
+        })
+        .collect();
+
+    Ok((
+        LargeWithSmallRepos {
+            large_repo,
+            small_repos,
+            live_commit_sync_config,
+            source,
+        },
+        commit_sync_config,
+    ))
+}
+
 pub fn base_commit_sync_config(large_repo: &TestRepo, small_repo: &TestRepo) -> CommitSyncConfig {
     let small_repo_sync_config = SmallRepoCommitSyncConfig {
         default_action: DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(
@@ -392,11 +1305,142 @@ pub fn base_commit_sync_config(large_repo: &TestRepo, small_repo: &TestRepo) ->
     }
 }
 
-fn prefix_mover(v: &MPath) -> Result<Option<MPath>, Error> {
+/// What `map_based_mover` does with the paths under a given prefix.
+#[derive(Clone, Debug)]
+pub enum MoveAction {
+    /// Keep the path, moved under this directory.
+    PrependPrefix(MPath),
+    /// Replace the matching prefix with this path.
+    Rename(MPath),
+    /// Don't sync the path.
+    Drop,
+}
+
+/// Builds a mover applying the action of the longest prefix of the path found in `map`. Paths
+/// that don't match any prefix are preserved.
+pub fn map_based_mover(map: HashMap<MPath, MoveAction>) -> Result<Mover, Error> {
+    let prefix_map = map
+        .into_iter()
+        .map(|(prefix, action)| {
+            let prefix_action = match action {
+                MoveAction::PrependPrefix(new_prefix) => {
+                    PrefixAction::Change(new_prefix.join(&prefix))
+                }
+                MoveAction::Rename(new_prefix) => PrefixAction::Change(new_prefix),
+                MoveAction::Drop => PrefixAction::DoNotSync,
+            };
+            (prefix, prefix_action)
+        })
+        .collect();
+
+    mover_factory(prefix_map, DefaultAction::Preserve)
+}
+
+/// Builds the small to large mover of `small_repo_config`, and asserts for each of `paths` that:
+/// - a path under a prefix of `map` is moved as the longest of them says, the other ones being
+///   moved according to `default_action`.
+/// - the large to small mover moves the path back.
+///
+/// Fails with the path and the `map` entry it matched otherwise.
+pub fn assert_mover_respects_map(
+    small_repo_config: &SmallRepoCommitSyncConfig,
+    paths: &[&str],
+) -> Result<(), Error> {
+    let small_repo_id = RepositoryId::new(1);
+    let commit_sync_config = CommitSyncConfig {
+        large_repo_id: RepositoryId::new(0),
+        common_pushrebase_bookmarks: vec![],
+        small_repos: hashmap! {
+            small_repo_id => small_repo_config.clone(),
+        },
+        version_name: xrepo_mapping_version_with_small_repo(),
+    };
+    let mover = get_small_to_large_mover(&commit_sync_config, small_repo_id)?;
+    let reverse_mover = get_large_to_small_mover(&commit_sync_config, small_repo_id)?;
+
+    for path in paths {
+        let path = MPath::new(path)?;
+        let map_entry = small_repo_config
+            .map
+            .iter()
+            .filter(|(prefix, _)| prefix.is_prefix_of(&path))
+            .max_by_key(|(prefix, _)| prefix.num_components());
+        let expected = match (map_entry, &small_repo_config.default_action) {
+            (Some((prefix, target)), _) => {
+                target.join((&path).into_iter().skip(prefix.num_components()))
+            }
+            (None, DefaultSmallToLargeCommitSyncPathAction::Preserve) => path.clone(),
+            (None, DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(prefix)) => {
+                prefix.join(&path)
+            }
+        };
+
+        assert_eq!(
+            mover(&path)?,
+            Some(expected.clone()),
+            "small repo path {} matching map entry {:?} was moved incorrectly",
+            path,
+            map_entry,
+        );
+        assert_eq!(
+            reverse_mover(&expected)?,
+            Some(path.clone()),
+            "large repo path {} wasn't moved back to {}",
+            expected,
+            path,
+        );
+    }
+
+    Ok(())
+}
+
+/// Asserts for each of `paths` that:
+/// - if `mover` moves it, `reverse_mover` moves it back.
+/// - if `reverse_mover` moves it, `mover` moves it back. A path that `reverse_mover` drops, e.g.
+///   because it's outside of the prefix `mover` moves the paths to, is fine.
+pub fn assert_movers_roundtrip(
+    mover: &Mover,
+    reverse_mover: &Mover,
+    paths: &[MPath],
+) -> Result<(), Error> {
+    for path in paths {
+        if let Some(moved) = mover(path)? {
+            assert_eq!(
+                reverse_mover(&moved)?,
+                Some(path.clone()),
+                "path {} was moved to {}, which wasn't moved back",
+                path,
+                moved,
+            );
+        }
+        if let Some(moved) = reverse_mover(path)? {
+            assert_eq!(
+                mover(&moved)?,
+                Some(path.clone()),
+                "path {} was reverse moved to {}, which wasn't moved back",
+                path,
+                moved,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn prefix_mover(v: &MPath) -> Result<Option<MPath>, Error> {
     let prefix = MPath::new("prefix").unwrap();
     Ok(Some(MPath::join(&prefix, v)))
 }
 
+pub fn reverse_prefix_mover(v: &MPath) -> Result<Option<MPath>, Error> {
+    let prefix = MPath::new("prefix").unwrap();
+    if prefix.is_prefix_of(v) {
+        Ok(v.remove_prefix_component(&prefix))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn get_live_commit_sync_config() -> Arc<dyn LiveCommitSyncConfig> {
     let (sync_config, source) = TestLiveCommitSyncConfig::new_with_source();
 
@@ -442,7 +1486,7 @@ fn get_small_repo_sync_config_noop() -> SmallRepoCommitSyncConfig {
     }
 }
 
-fn get_small_repo_sync_config_1() -> SmallRepoCommitSyncConfig {
+pub fn get_small_repo_sync_config_1() -> SmallRepoCommitSyncConfig {
     SmallRepoCommitSyncConfig {
         default_action: DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(
             MPath::new("prefix").unwrap(),
@@ -451,7 +1495,7 @@ fn get_small_repo_sync_config_1() -> SmallRepoCommitSyncConfig {
     }
 }
 
-fn get_small_repo_sync_config_2() -> SmallRepoCommitSyncConfig {
+pub fn get_small_repo_sync_config_2() -> SmallRepoCommitSyncConfig {
     SmallRepoCommitSyncConfig {
         default_action: DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(
             MPath::new("prefix").unwrap(),
@@ -87,6 +87,21 @@ pub trait LiveCommitSyncConfig: Send + Sync {
         repo_id: RepositoryId,
     ) -> Result<HashMap<CommitSyncConfigVersion, CommitSyncConfig>>;
 
+    /// Return the names of all historical versions of
+    /// `CommitSyncConfig` for a given repository, sorted
+    ///
+    /// NOTE: two subsequent calls may return different results
+    ///       as this queries config source
+    async fn list_versions(&self, repo_id: RepositoryId) -> Result<Vec<CommitSyncConfigVersion>> {
+        let mut versions: Vec<_> = self
+            .get_all_commit_sync_config_versions(repo_id)
+            .await?
+            .into_keys()
+            .collect();
+        versions.sort();
+        Ok(versions)
+    }
+
     /// Return `CommitSyncConfig` for repo `repo_id` of version `version_name`
     async fn get_commit_sync_config_by_version(
         &self,
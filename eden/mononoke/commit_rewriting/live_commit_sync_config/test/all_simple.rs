@@ -7,6 +7,9 @@
 
 use fbinit::FacebookInit;
 use live_commit_sync_config::LiveCommitSyncConfig;
+use live_commit_sync_config::TestLiveCommitSyncConfig;
+use metaconfig_types::CommitSyncConfig;
+use metaconfig_types::CommitSyncConfigVersion;
 use mononoke_types::RepositoryId;
 use pretty_assertions::assert_eq;
 
@@ -168,3 +171,63 @@ async fn test_version_counts(fb: FacebookInit) {
     assert_eq!(av0.len(), 2);
     assert_eq!(av4.len(), 1);
 }
+
+#[fbinit::test]
+async fn test_list_versions(fb: FacebookInit) {
+    let (_ctx, _test_source, _store, live_commit_sync_config) =
+        get_ctx_source_store_and_live_config(fb, EMPTY_PUSHREDIRECTOR, ALL_COMMIT_SYNC_CONFIG_V1);
+
+    let versions = live_commit_sync_config
+        .list_versions(RepositoryId::new(1))
+        .await
+        .unwrap();
+    assert_eq!(
+        versions,
+        vec![
+            CommitSyncConfigVersion("TEST_VERSION_NAME_LIVE_1".to_string()),
+            CommitSyncConfigVersion("TEST_VERSION_NAME_LIVE_2".to_string()),
+        ]
+    );
+
+    let versions = live_commit_sync_config
+        .list_versions(RepositoryId::new(4))
+        .await
+        .unwrap();
+    assert_eq!(
+        versions,
+        vec![CommitSyncConfigVersion(
+            "TEST_VERSION_NAME_R3_1".to_string()
+        )]
+    );
+
+    let versions = live_commit_sync_config
+        .list_versions(RepositoryId::new(5))
+        .await
+        .unwrap();
+    assert!(versions.is_empty());
+}
+
+#[fbinit::test]
+async fn test_list_versions_test_config(_fb: FacebookInit) {
+    let (live_commit_sync_config, source) = TestLiveCommitSyncConfig::new_with_source();
+    let repo_0 = RepositoryId::new(0);
+    let version_config = |version_name: &str| CommitSyncConfig {
+        large_repo_id: repo_0,
+        common_pushrebase_bookmarks: vec![],
+        small_repos: Default::default(),
+        version_name: CommitSyncConfigVersion(version_name.to_string()),
+    };
+
+    // Added out of order, listed sorted.
+    source.add_config(version_config("second_version"));
+    source.add_config(version_config("first_version"));
+
+    let versions = live_commit_sync_config.list_versions(repo_0).await.unwrap();
+    assert_eq!(
+        versions,
+        vec![
+            CommitSyncConfigVersion("first_version".to_string()),
+            CommitSyncConfigVersion("second_version".to_string()),
+        ]
+    );
+}
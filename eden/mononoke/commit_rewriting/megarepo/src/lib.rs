@@ -159,6 +159,33 @@ pub async fn perform_stack_move<'a>(
     .await
 }
 
+/// Compute what `perform_move` would do to the working copy of `bcs_id`
+/// without writing anything. Returns every source path together with the
+/// path it would be moved to, or `None` if the mover drops it, sorted by
+/// source path.
+pub async fn plan_move<'a>(
+    ctx: &'a CoreContext,
+    repo: &'a BlobRepo,
+    bcs_id: ChangesetId,
+    path_converter: Mover,
+) -> Result<Vec<(MPath, Option<MPath>)>, Error> {
+    let hg_cs_id = repo.derive_hg_changeset(ctx, bcs_id).await?;
+    let hg_cs = hg_cs_id.load(ctx, repo.repo_blobstore()).await?;
+
+    let mut plan: Vec<_> = hg_cs
+        .manifestid()
+        .list_leaf_entries(ctx.clone(), repo.repo_blobstore().clone())
+        .and_then(|(old_path, _)| {
+            let maybe_new_path = path_converter(&old_path);
+            future::ready(maybe_new_path.map(|maybe_new_path| (old_path, maybe_new_path)))
+        })
+        .try_collect()
+        .await?;
+
+    plan.sort_unstable_by(|first, second| first.0.cmp(&second.0));
+    Ok(plan)
+}
+
 async fn perform_stack_move_impl<'a, Chunker>(
     ctx: &'a CoreContext,
     repo: &'a BlobRepo,
@@ -394,6 +421,27 @@ mod test {
         assert_eq!(file_change.copy_from(), Some((old_path, bcs_id)).as_ref());
     }
 
+    #[fbinit::test]
+    async fn test_plan_move(fb: FacebookInit) -> Result<(), Error> {
+        let (ctx, repo, _hg_cs_id, bcs_id, _changeset_args) = prepare(fb).await;
+        let plan = plan_move(&ctx, &repo, bcs_id, Arc::new(shift_one_skip_another)).await?;
+
+        let plan: BTreeMap<_, _> = plan.into_iter().collect();
+        assert_eq!(
+            plan[&MPath::new("dir1/file_1_in_dir1")?],
+            Some(MPath::new("newdir/dir1/file_1_in_dir1")?)
+        );
+        assert_eq!(plan[&MPath::new("dir2/file_1_in_dir2")?], None);
+        assert_eq!(plan[&MPath::new("1")?], Some(MPath::new("1")?),);
+
+        // Every file of the working copy is present in the plan
+        let hg_cs_id = repo.derive_hg_changeset(&ctx, bcs_id).await?;
+        let working_copy = get_working_copy_contents(ctx.clone(), repo.clone(), hg_cs_id).await;
+        let paths: Vec<_> = working_copy.into_keys().collect();
+        assert_eq!(paths, plan.into_keys().collect::<Vec<_>>());
+        Ok(())
+    }
+
     async fn get_working_copy_contents(
         ctx: CoreContext,
         repo: BlobRepo,
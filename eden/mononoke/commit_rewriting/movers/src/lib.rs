@@ -31,6 +31,8 @@ pub enum ErrorKind {
     SmallRepoNotFound(RepositoryId),
     #[error("Provided map is not prefix-free (e.g. {0:?} and {1:?})")]
     NonPrefixFreeMap(MPath, MPath),
+    #[error("Paths {0:?} and {1:?} would both be moved to {2:?}")]
+    PathCollision(MPath, MPath, MPath),
 }
 
 /// A function to modify paths during repo sync
@@ -191,6 +193,91 @@ pub fn mover_factory(
     }))
 }
 
+/// Like `get_suffix_after`, but comparing the path elements of the prefix
+/// ignoring ASCII case
+fn get_suffix_after_ignore_case<'a, 'b>(
+    source_path: &'a MPath,
+    candidate_prefix: &'b MPath,
+) -> Option<Vec<&'a MPathElement>> {
+    if source_path.num_components() < candidate_prefix.num_components() {
+        return None;
+    }
+    let matches = source_path
+        .into_iter()
+        .zip(candidate_prefix.into_iter())
+        .all(|(element, prefix_element)| {
+            element
+                .as_ref()
+                .eq_ignore_ascii_case(prefix_element.as_ref())
+        });
+    if !matches {
+        None
+    } else {
+        Some(
+            source_path
+                .into_iter()
+                .skip(candidate_prefix.num_components())
+                .collect(),
+        )
+    }
+}
+
+/// Create a `Mover` for `paths`, e.g. the paths changed by a commit, that drops
+/// `prefix` from the paths under it, and doesn't sync the other paths. The
+/// prefix is matched ignoring ASCII case, and the rest of the path keeps its
+/// original case, which is what's needed for repos imported from
+/// case-insensitive filesystems. The forward mover doesn't match anything, it
+/// is the one of `DefaultAction::PrependPrefix(prefix)`.
+///
+/// Fails with `ErrorKind::PathCollision` if two of `paths` would be moved to
+/// the same path, e.g. `Prefix/file` and `prefix/file`.
+pub fn case_insensitive_reverse_prefix_mover<'a>(
+    prefix: MPath,
+    paths: impl IntoIterator<Item = &'a MPath>,
+) -> Result<Mover> {
+    let mover: Mover = Arc::new(move |source_path: &MPath| -> Result<Option<MPath>> {
+        let suffix_after = match get_suffix_after_ignore_case(source_path, &prefix) {
+            Some(suffix_after) => suffix_after,
+            None => return Ok(None),
+        };
+        get_path_action(suffix_after, &PrefixAction::RemovePrefix)
+            .map(|path_action| match path_action {
+                PathAction::Change(path) => Some(path),
+                PathAction::DoNotSync => None,
+            })
+            .with_context(|| {
+                ErrorKind::PrefixActionFailure(PrefixAction::RemovePrefix, source_path.clone())
+            })
+    });
+    check_path_collisions(&mover, paths)?;
+    Ok(mover)
+}
+
+/// Check that `mover` doesn't move two of `paths` to the same path.
+fn check_path_collisions<'a>(
+    mover: &Mover,
+    paths: impl IntoIterator<Item = &'a MPath>,
+) -> Result<()> {
+    let mut moved_from: HashMap<MPath, &MPath> = HashMap::new();
+    for source_path in paths {
+        if let Some(path) = mover(source_path)? {
+            match moved_from.get(&path) {
+                Some(other_source_path) if *other_source_path != source_path => {
+                    return Err(Error::from(ErrorKind::PathCollision(
+                        (*other_source_path).clone(),
+                        source_path.clone(),
+                        path,
+                    )));
+                }
+                _ => {
+                    moved_from.insert(path, source_path);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 // Given a full sync config and a small repo id,
 // split it into this repo the rest
 fn get_small_repo_and_others_from_config(
@@ -818,4 +905,56 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_case_insensitive_reverse_prefix_mover() -> Result<()> {
+        let mover = mover_factory(HashMap::new(), DefaultAction::PrependPrefix(mp("Prefix")))?;
+        assert_eq!(mover(&mp("Dir/File.txt"))?, Some(mp("Prefix/Dir/File.txt")));
+        assert_eq!(mover(&mp("dir/file.txt"))?, Some(mp("Prefix/dir/file.txt")));
+
+        let reverse_mover = case_insensitive_reverse_prefix_mover(mp("Prefix/Sub"), &[])?;
+        assert_eq!(
+            reverse_mover(&mp("Prefix/Sub/Dir/File.txt"))?,
+            Some(mp("Dir/File.txt"))
+        );
+        assert_eq!(
+            reverse_mover(&mp("pREFIX/sub/dir/file.txt"))?,
+            Some(mp("dir/file.txt"))
+        );
+        assert_eq!(reverse_mover(&mp("Prefix/Other/File.txt"))?, None);
+        assert_eq!(reverse_mover(&mp("Prefix"))?, None);
+        assert_eq!(reverse_mover(&mp("Prefixes/Sub/File.txt"))?, None);
+        assert!(reverse_mover(&mp("prefix/SUB")).is_err());
+
+        // The mover doesn't remember the paths it moved.
+        assert_eq!(
+            reverse_mover(&mp("prefix/sub/Dir/File.txt"))?,
+            Some(mp("Dir/File.txt"))
+        );
+
+        // Paths of a commit that only differ in the case of the prefix collide.
+        let paths = [
+            mp("Prefix/Sub/Dir/File.txt"),
+            mp("Prefix/Sub/Dir/File.txt"),
+            mp("Prefix/Sub/dir/file.txt"),
+            mp("Other/File.txt"),
+        ];
+        case_insensitive_reverse_prefix_mover(mp("Prefix/Sub"), &paths)?;
+        let paths = [mp("Prefix/Sub/Dir/File.txt"), mp("prefix/sub/Dir/File.txt")];
+        let err = case_insensitive_reverse_prefix_mover(mp("Prefix/Sub"), &paths)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<ErrorKind>(),
+            Some(ErrorKind::PathCollision(..))
+        ));
+
+        // Round trip through both movers keeps the case of the rest of the path.
+        let path = mp("MiXeD/CaSe.txt");
+        assert_eq!(reverse_mover(&mover(&path)?.unwrap())?, None);
+        let reverse_mover = case_insensitive_reverse_prefix_mover(mp("prefix"), &[])?;
+        assert_eq!(reverse_mover(&mover(&path)?.unwrap())?, Some(path));
+
+        Ok(())
+    }
 }
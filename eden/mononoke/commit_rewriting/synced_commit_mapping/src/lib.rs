@@ -5,6 +5,9 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashMap;
+use std::collections::HashSet;
+
 use anyhow::anyhow;
 use anyhow::Error;
 use async_trait::async_trait;
@@ -60,6 +63,8 @@ define_stats! {
     add_bulks: timeseries(Rate, Sum),
     insert_working_copy_eqivalence: timeseries(Rate, Sum),
     get_equivalent_working_copy: timeseries(Rate, Sum),
+    get_many: timeseries(Rate, Sum),
+    get_many_equivalent_working_copies: timeseries(Rate, Sum),
 }
 
 // Repo that originally contained the synced commit
@@ -162,6 +167,14 @@ pub struct EquivalentWorkingCopyEntry {
     pub version_name: Option<CommitSyncConfigVersion>,
 }
 
+/// A commit a source commit was rewritten as, with the version of the config and the repo the
+/// commit was originally in, as returned by `SyncedCommitMapping::get`.
+pub type SyncedCommitMappingTarget = (
+    ChangesetId,
+    Option<CommitSyncConfigVersion>,
+    Option<SyncedCommitSourceRepo>,
+);
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum WorkingCopyEquivalence {
     /// There's no matching working copy. It can happen if a pre-big-merge commit from one small
@@ -203,6 +216,28 @@ pub trait SyncedCommitMapping: Send + Sync {
         Error,
     >;
 
+    /// Find all the mapping entries for each of the given source commits and target repo, in
+    /// fewer queries than calling `get` for each of them. The commits without entries are left
+    /// out of the result.
+    async fn get_many(
+        &self,
+        ctx: &CoreContext,
+        source_repo_id: RepositoryId,
+        bcs_ids: &[ChangesetId],
+        target_repo_id: RepositoryId,
+    ) -> Result<HashMap<ChangesetId, Vec<SyncedCommitMappingTarget>>, Error> {
+        let mut result = HashMap::new();
+        for bcs_id in bcs_ids {
+            let entries = self
+                .get(ctx, source_repo_id, *bcs_id, target_repo_id)
+                .await?;
+            if !entries.is_empty() {
+                result.insert(*bcs_id, entries);
+            }
+        }
+        Ok(result)
+    }
+
     /// Inserts equivalent working copy of a large bcs id. It's similar to mapping entry,
     /// however there are a few differences:
     /// 1) For (large repo, small repo) pair, many large commits can map to the same small commit
@@ -233,6 +268,28 @@ pub trait SyncedCommitMapping: Send + Sync {
         target_repo_id: RepositoryId,
     ) -> Result<Option<WorkingCopyEquivalence>, Error>;
 
+    /// Finds equivalent working copy of each of the given source commits, in fewer queries than
+    /// calling `get_equivalent_working_copy` for each of them. The commits without one are left
+    /// out of the result.
+    async fn get_many_equivalent_working_copies(
+        &self,
+        ctx: &CoreContext,
+        source_repo_id: RepositoryId,
+        source_bcs_ids: &[ChangesetId],
+        target_repo_id: RepositoryId,
+    ) -> Result<HashMap<ChangesetId, WorkingCopyEquivalence>, Error> {
+        let mut result = HashMap::new();
+        for source_bcs_id in source_bcs_ids {
+            if let Some(equivalence) = self
+                .get_equivalent_working_copy(ctx, source_repo_id, *source_bcs_id, target_repo_id)
+                .await?
+            {
+                result.insert(*source_bcs_id, equivalence);
+            }
+        }
+        Ok(result)
+    }
+
     /// Get version for large repo commit
     async fn get_large_repo_commit_version(
         &self,
@@ -273,6 +330,17 @@ mononoke_queries! {
           (small_repo_id = {source_repo_id} AND small_bcs_id = {bcs_id} AND large_repo_id = {target_repo_id})"
     }
 
+    read SelectManyMappings(
+        source_repo_id: RepositoryId,
+        target_repo_id: RepositoryId,
+        >list bcs_ids: ChangesetId
+    ) -> (RepositoryId, ChangesetId, RepositoryId, ChangesetId, Option<CommitSyncConfigVersion>, Option<SyncedCommitSourceRepo>) {
+        "SELECT large_repo_id, large_bcs_id, small_repo_id, small_bcs_id, sync_map_version_name, source_repo
+          FROM synced_commit_mapping
+          WHERE (large_repo_id = {source_repo_id} AND large_bcs_id IN {bcs_ids} AND small_repo_id = {target_repo_id}) OR
+          (small_repo_id = {source_repo_id} AND small_bcs_id IN {bcs_ids} AND large_repo_id = {target_repo_id})"
+    }
+
     write InsertWorkingCopyEquivalence(values: (
         large_repo_id: RepositoryId,
         large_bcs_id: ChangesetId,
@@ -315,6 +383,19 @@ mononoke_queries! {
           "
     }
 
+    read SelectManyWorkingCopyEquivalences(
+        source_repo_id: RepositoryId,
+        target_repo_id: RepositoryId,
+        >list bcs_ids: ChangesetId
+    ) -> (RepositoryId, ChangesetId, RepositoryId, Option<ChangesetId>, Option<CommitSyncConfigVersion>) {
+        "SELECT large_repo_id, large_bcs_id, small_repo_id, small_bcs_id, sync_map_version_name
+          FROM synced_working_copy_equivalence
+          WHERE (large_repo_id = {source_repo_id} AND small_repo_id = {target_repo_id} AND large_bcs_id IN {bcs_ids})
+          OR (large_repo_id = {target_repo_id} AND small_repo_id = {source_repo_id} AND small_bcs_id IN {bcs_ids})
+          ORDER BY mapping_id ASC
+          "
+    }
+
     write InsertVersionForLargeRepoCommit(values: (
         large_repo_id: RepositoryId,
         large_bcs_id: ChangesetId,
@@ -597,6 +678,83 @@ impl SyncedCommitMapping for SqlSyncedCommitMapping {
             .collect())
     }
 
+    async fn get_many(
+        &self,
+        ctx: &CoreContext,
+        source_repo_id: RepositoryId,
+        bcs_ids: &[ChangesetId],
+        target_repo_id: RepositoryId,
+    ) -> Result<HashMap<ChangesetId, Vec<SyncedCommitMappingTarget>>, Error> {
+        STATS::get_many.add_value(1);
+        if bcs_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlReadsReplica);
+        let mut rows = SelectManyMappings::query(
+            &self.read_connection,
+            &source_repo_id,
+            &target_repo_id,
+            bcs_ids,
+        )
+        .await?;
+
+        // Like `get`, look for the commits that have no entries on the replica on the master.
+        let found: HashSet<_> = rows
+            .iter()
+            .map(|row| {
+                if target_repo_id == row.0 {
+                    row.3
+                } else {
+                    row.1
+                }
+            })
+            .collect();
+        let missing: Vec<_> = bcs_ids
+            .iter()
+            .filter(|bcs_id| !found.contains(bcs_id))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            STATS::gets_master.add_value(1);
+            ctx.perf_counters()
+                .increment_counter(PerfCounterType::SqlReadsMaster);
+            rows.extend(
+                SelectManyMappings::query(
+                    &self.read_master_connection,
+                    &source_repo_id,
+                    &target_repo_id,
+                    &missing,
+                )
+                .await?,
+            );
+        }
+
+        let mut result: HashMap<_, Vec<_>> = HashMap::new();
+        for row in rows {
+            let (
+                large_repo_id,
+                large_bcs_id,
+                _small_repo_id,
+                small_bcs_id,
+                maybe_version_name,
+                maybe_source_repo,
+            ) = row;
+            let (source_bcs_id, target_bcs_id) = if target_repo_id == large_repo_id {
+                (small_bcs_id, large_bcs_id)
+            } else {
+                (large_bcs_id, small_bcs_id)
+            };
+            result.entry(source_bcs_id).or_default().push((
+                target_bcs_id,
+                maybe_version_name,
+                maybe_source_repo,
+            ));
+        }
+        Ok(result)
+    }
+
     async fn insert_equivalent_working_copy(
         &self,
         ctx: &CoreContext,
@@ -653,37 +811,90 @@ impl SyncedCommitMapping for SqlSyncedCommitMapping {
             .map(|rows| rows.get(0).cloned())?
         };
 
-        Ok(match maybe_row {
-            Some(row) => {
-                let (
-                    large_repo_id,
-                    large_bcs_id,
-                    _small_repo_id,
-                    maybe_small_bcs_id,
-                    maybe_mapping,
-                ) = row;
+        maybe_row
+            .map(|row| {
+                working_copy_equivalence_from_row(
+                    row,
+                    source_repo_id,
+                    source_bcs_id,
+                    target_repo_id,
+                )
+            })
+            .transpose()
+    }
 
-                let mapping = maybe_mapping.ok_or_else(|| {
-                    anyhow!(
-                        "unexpected empty mapping for {}, {}->{}",
-                        source_bcs_id,
-                        source_repo_id,
-                        target_repo_id
-                    )
-                })?;
-                if target_repo_id == large_repo_id {
-                    Some(WorkingCopyEquivalence::WorkingCopy(large_bcs_id, mapping))
+    async fn get_many_equivalent_working_copies(
+        &self,
+        ctx: &CoreContext,
+        source_repo_id: RepositoryId,
+        source_bcs_ids: &[ChangesetId],
+        target_repo_id: RepositoryId,
+    ) -> Result<HashMap<ChangesetId, WorkingCopyEquivalence>, Error> {
+        STATS::get_many_equivalent_working_copies.add_value(1);
+        if source_bcs_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        // Like `get_equivalent_working_copy`, only the first equivalence of each commit is used.
+        let mut rows = HashMap::new();
+        let add_rows = |rows: &mut HashMap<ChangesetId, WorkingCopyEquivalenceRow>,
+                        new_rows: Vec<WorkingCopyEquivalenceRow>| {
+            for row in new_rows {
+                let source_bcs_id = if row.0 == source_repo_id {
+                    Some(row.1)
                 } else {
-                    match maybe_small_bcs_id {
-                        Some(small_bcs_id) => {
-                            Some(WorkingCopyEquivalence::WorkingCopy(small_bcs_id, mapping))
-                        }
-                        None => Some(WorkingCopyEquivalence::NoWorkingCopy(mapping)),
-                    }
+                    row.3
+                };
+                if let Some(source_bcs_id) = source_bcs_id {
+                    rows.entry(source_bcs_id).or_insert(row);
                 }
             }
-            None => None,
-        })
+        };
+
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlReadsReplica);
+        add_rows(
+            &mut rows,
+            SelectManyWorkingCopyEquivalences::query(
+                &self.read_connection,
+                &source_repo_id,
+                &target_repo_id,
+                source_bcs_ids,
+            )
+            .await?,
+        );
+
+        let missing: Vec<_> = source_bcs_ids
+            .iter()
+            .filter(|bcs_id| !rows.contains_key(bcs_id))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            ctx.perf_counters()
+                .increment_counter(PerfCounterType::SqlReadsMaster);
+            add_rows(
+                &mut rows,
+                SelectManyWorkingCopyEquivalences::query(
+                    &self.read_master_connection,
+                    &source_repo_id,
+                    &target_repo_id,
+                    &missing,
+                )
+                .await?,
+            );
+        }
+
+        rows.into_iter()
+            .map(|(source_bcs_id, row)| {
+                let equivalence = working_copy_equivalence_from_row(
+                    row,
+                    source_repo_id,
+                    source_bcs_id,
+                    target_repo_id,
+                )?;
+                Ok((source_bcs_id, equivalence))
+            })
+            .collect()
     }
 
     async fn get_large_repo_commit_version(
@@ -720,6 +931,40 @@ impl SyncedCommitMapping for SqlSyncedCommitMapping {
     }
 }
 
+type WorkingCopyEquivalenceRow = (
+    RepositoryId,
+    ChangesetId,
+    RepositoryId,
+    Option<ChangesetId>,
+    Option<CommitSyncConfigVersion>,
+);
+
+fn working_copy_equivalence_from_row(
+    row: WorkingCopyEquivalenceRow,
+    source_repo_id: RepositoryId,
+    source_bcs_id: ChangesetId,
+    target_repo_id: RepositoryId,
+) -> Result<WorkingCopyEquivalence, Error> {
+    let (large_repo_id, large_bcs_id, _small_repo_id, maybe_small_bcs_id, maybe_mapping) = row;
+
+    let mapping = maybe_mapping.ok_or_else(|| {
+        anyhow!(
+            "unexpected empty mapping for {}, {}->{}",
+            source_bcs_id,
+            source_repo_id,
+            target_repo_id
+        )
+    })?;
+    if target_repo_id == large_repo_id {
+        Ok(WorkingCopyEquivalence::WorkingCopy(large_bcs_id, mapping))
+    } else {
+        match maybe_small_bcs_id {
+            Some(small_bcs_id) => Ok(WorkingCopyEquivalence::WorkingCopy(small_bcs_id, mapping)),
+            None => Ok(WorkingCopyEquivalence::NoWorkingCopy(mapping)),
+        }
+    }
+}
+
 pub async fn add_many_in_txn(
     txn: Transaction,
     entries: Vec<SyncedCommitMappingEntry>,
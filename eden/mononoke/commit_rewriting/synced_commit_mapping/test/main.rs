@@ -333,3 +333,100 @@ async fn test_overwrite(fb: FacebookInit) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[fbinit::test]
+async fn test_get_many(fb: FacebookInit) -> Result<(), Error> {
+    let mapping = SqlSyncedCommitMapping::with_sqlite_in_memory()?;
+    let ctx = CoreContext::test_mock(fb);
+    let version_name = CommitSyncConfigVersion("TEST_VERSION_NAME".to_string());
+
+    mapping
+        .add_bulk(
+            &ctx,
+            vec![
+                SyncedCommitMappingEntry::new(
+                    REPO_ZERO,
+                    bonsai::ONES_CSID,
+                    REPO_ONE,
+                    bonsai::TWOS_CSID,
+                    version_name.clone(),
+                    SyncedCommitSourceRepo::Large,
+                ),
+                SyncedCommitMappingEntry::new(
+                    REPO_ZERO,
+                    bonsai::THREES_CSID,
+                    REPO_ONE,
+                    bonsai::FOURS_CSID,
+                    version_name.clone(),
+                    SyncedCommitSourceRepo::Small,
+                ),
+            ],
+        )
+        .await?;
+    mapping
+        .insert_equivalent_working_copy(
+            &ctx,
+            EquivalentWorkingCopyEntry {
+                large_repo_id: REPO_ZERO,
+                large_bcs_id: bonsai::FIVES_CSID,
+                small_repo_id: REPO_ONE,
+                small_bcs_id: None,
+                version_name: Some(version_name.clone()),
+            },
+        )
+        .await?;
+
+    // The batched lookups agree with the single ones, in both directions
+    for (source_repo_id, target_repo_id) in [(REPO_ZERO, REPO_ONE), (REPO_ONE, REPO_ZERO)] {
+        let bcs_ids = [
+            bonsai::ONES_CSID,
+            bonsai::TWOS_CSID,
+            bonsai::THREES_CSID,
+            bonsai::FOURS_CSID,
+            bonsai::FIVES_CSID,
+        ];
+        let many = mapping
+            .get_many(&ctx, source_repo_id, &bcs_ids, target_repo_id)
+            .await?;
+        let many_equivalences = mapping
+            .get_many_equivalent_working_copies(&ctx, source_repo_id, &bcs_ids, target_repo_id)
+            .await?;
+        for bcs_id in bcs_ids {
+            let single = mapping
+                .get(&ctx, source_repo_id, bcs_id, target_repo_id)
+                .await?;
+            assert_eq!(many.get(&bcs_id).cloned().unwrap_or_default(), single);
+            let single_equivalence = mapping
+                .get_equivalent_working_copy(&ctx, source_repo_id, bcs_id, target_repo_id)
+                .await?;
+            assert_eq!(many_equivalences.get(&bcs_id), single_equivalence.as_ref());
+        }
+    }
+
+    assert_eq!(
+        mapping
+            .get_many(&ctx, REPO_ZERO, &[bonsai::ONES_CSID], REPO_ONE)
+            .await?
+            .get(&bonsai::ONES_CSID),
+        Some(&vec![(
+            bonsai::TWOS_CSID,
+            Some(version_name.clone()),
+            Some(SyncedCommitSourceRepo::Large)
+        )])
+    );
+    assert_eq!(
+        mapping
+            .get_many_equivalent_working_copies(&ctx, REPO_ZERO, &[bonsai::FIVES_CSID], REPO_ONE)
+            .await?
+            .get(&bonsai::FIVES_CSID),
+        Some(&WorkingCopyEquivalence::NoWorkingCopy(version_name))
+    );
+    assert!(
+        mapping
+            .get_many(&ctx, REPO_ZERO, &[], REPO_ONE)
+            .await?
+            .is_empty()
+    );
+
+    Ok(())
+}
@@ -1268,6 +1268,130 @@ pub struct CommitSyncConfig {
     pub version_name: CommitSyncConfigVersion,
 }
 
+impl CommitSyncConfig {
+    /// Check that the paths of the small repos can't be synced over one another in the large
+    /// repo, as one of them would silently be overwritten:
+    ///
+    /// - the paths of two small repos can't be moved under the same prefix of the large repo.
+    ///
+    /// - the paths of a small repo matching an entry of its `map` can't be moved where its
+    /// `default_action` or another entry of its `map` moves paths.
+    pub fn validate(&self) -> Result<()> {
+        let mut small_repos: Vec<_> = self.small_repos.iter().collect();
+        small_repos.sort_by_key(|(repo_id, _)| **repo_id);
+        let regions: Vec<_> = small_repos
+            .into_iter()
+            .map(|(repo_id, config)| (repo_id, config.large_repo_regions()))
+            .collect();
+
+        for (index, (repo_id, repo_regions)) in regions.iter().enumerate() {
+            for (region_index, region) in repo_regions.iter().enumerate() {
+                for other in &repo_regions[region_index + 1..] {
+                    if region.overlaps(other) {
+                        return Err(anyhow!(
+                            "The {} and the {} of small repo {} move paths to the same place in large repo {}",
+                            region.source,
+                            other.source,
+                            repo_id,
+                            self.large_repo_id,
+                        ));
+                    }
+                }
+            }
+
+            for (other_repo_id, other_regions) in &regions[index + 1..] {
+                for region in repo_regions {
+                    for other in other_regions {
+                        if region.overlaps(other) {
+                            return Err(anyhow!(
+                                "The {} of small repo {} and the {} of small repo {} move paths to the same place in large repo {}",
+                                region.source,
+                                repo_id,
+                                other.source,
+                                other_repo_id,
+                                self.large_repo_id,
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SmallRepoCommitSyncConfig {
+    /// The regions of the large repo that the paths of this small repo are moved to, one per
+    /// entry of the `map` and one for the `default_action`.
+    fn large_repo_regions(&self) -> Vec<LargeRepoRegion> {
+        let mut regions: Vec<_> = self
+            .map
+            .iter()
+            .map(|(small_prefix, large_prefix)| {
+                // The paths matching a longer prefix of the map are moved by its entry instead.
+                let excluded = self
+                    .map
+                    .keys()
+                    .filter_map(|other| other.remove_prefix_component(small_prefix))
+                    .map(|suffix| large_prefix.join(&suffix))
+                    .collect();
+                LargeRepoRegion {
+                    source: format!("mapping {} => {}", small_prefix, large_prefix),
+                    prefix: Some(large_prefix.clone()),
+                    excluded,
+                }
+            })
+            .collect();
+
+        let (source, prefix) = match &self.default_action {
+            DefaultSmallToLargeCommitSyncPathAction::Preserve => {
+                ("default action preserve".to_owned(), None)
+            }
+            DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(prefix) => {
+                (format!("default prefix {}", prefix), Some(prefix.clone()))
+            }
+        };
+        let excluded = self
+            .map
+            .keys()
+            .filter_map(|small_prefix| MPath::join_opt(prefix.as_ref(), small_prefix))
+            .collect();
+        regions.push(LargeRepoRegion {
+            source,
+            prefix,
+            excluded,
+        });
+
+        regions
+    }
+}
+
+/// The paths under `prefix` of the large repo (all of them if `None`), except those under one of
+/// `excluded`.
+struct LargeRepoRegion {
+    /// What moves the paths of the small repo to this region, for error messages.
+    source: String,
+    prefix: Option<MPath>,
+    excluded: Vec<MPath>,
+}
+
+impl LargeRepoRegion {
+    fn contains(&self, path: Option<&MPath>) -> bool {
+        MPath::is_prefix_of_opt(self.prefix.as_ref(), MPath::iter_opt(path))
+            && !self
+                .excluded
+                .iter()
+                .any(|excluded| excluded.is_prefix_of(MPath::iter_opt(path)))
+    }
+
+    /// Two regions overlap if and only if one of them contains the prefix of the other one, as
+    /// the exclusions of a region never cover all the paths under a longer prefix.
+    fn overlaps(&self, other: &Self) -> bool {
+        self.contains(other.prefix.as_ref()) || other.contains(self.prefix.as_ref())
+    }
+}
+
 /// Config that applies to all mapping versions
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct CommonCommitSyncConfig {
@@ -1752,3 +1876,101 @@ pub struct CommitGraphConfig {
     /// Scuba table to log commit graph operations to
     pub scuba_table: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_repo_config(
+        default_action: DefaultSmallToLargeCommitSyncPathAction,
+        map: &[(&str, &str)],
+    ) -> SmallRepoCommitSyncConfig {
+        SmallRepoCommitSyncConfig {
+            default_action,
+            map: map
+                .iter()
+                .map(|(small, large)| (MPath::new(small).unwrap(), MPath::new(large).unwrap()))
+                .collect(),
+        }
+    }
+
+    fn prepend_prefix(prefix: &str) -> DefaultSmallToLargeCommitSyncPathAction {
+        DefaultSmallToLargeCommitSyncPathAction::PrependPrefix(MPath::new(prefix).unwrap())
+    }
+
+    fn commit_sync_config(small_repos: Vec<(i32, SmallRepoCommitSyncConfig)>) -> CommitSyncConfig {
+        CommitSyncConfig {
+            large_repo_id: RepositoryId::new(1),
+            common_pushrebase_bookmarks: vec![],
+            small_repos: small_repos
+                .into_iter()
+                .map(|(repo_id, config)| (RepositoryId::new(repo_id), config))
+                .collect(),
+            version_name: CommitSyncConfigVersion("TEST_VERSION_NAME".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_commit_sync_config_validate_clean() {
+        let config = commit_sync_config(vec![
+            (
+                2,
+                small_repo_config(
+                    DefaultSmallToLargeCommitSyncPathAction::Preserve,
+                    &[
+                        ("p1", ".r2-legacy/p1"),
+                        ("p5", ".r2-legacy/p5"),
+                        ("p6", ".r2-legacy/p6"),
+                    ],
+                ),
+            ),
+            (
+                3,
+                small_repo_config(
+                    prepend_prefix("p5"),
+                    &[("p1", "p1"), ("p4", "p6/p4"), ("p4/p6", "p5/p4")],
+                ),
+            ),
+        ]);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_commit_sync_config_validate_colliding_small_repos() {
+        let config = commit_sync_config(vec![
+            (2, small_repo_config(prepend_prefix("subdir"), &[])),
+            (3, small_repo_config(prepend_prefix("subdir/p3"), &[])),
+        ]);
+        let msg = format!("{:#}", config.validate().unwrap_err());
+        assert!(msg.contains("default prefix subdir of small repo 2"));
+        assert!(msg.contains("default prefix subdir/p3 of small repo 3"));
+
+        let config = commit_sync_config(vec![
+            (2, small_repo_config(prepend_prefix("subdir"), &[])),
+            (
+                3,
+                small_repo_config(prepend_prefix("p3"), &[("p1", "subdir/p1")]),
+            ),
+        ]);
+        let msg = format!("{:#}", config.validate().unwrap_err());
+        assert!(msg.contains("mapping p1 => subdir/p1 of small repo 3"));
+    }
+
+    #[test]
+    fn test_commit_sync_config_validate_colliding_map() {
+        // The paths under p5/p4 are moved to subdir/p5/p4 too.
+        let config = commit_sync_config(vec![(
+            2,
+            small_repo_config(prepend_prefix("subdir"), &[("p4", "subdir/p5/p4")]),
+        )]);
+        let msg = format!("{:#}", config.validate().unwrap_err());
+        assert!(msg.contains("mapping p4 => subdir/p5/p4"));
+        assert!(msg.contains("default prefix subdir of small repo 2"));
+
+        let config = commit_sync_config(vec![(
+            2,
+            small_repo_config(prepend_prefix("subdir"), &[("p1", "p"), ("p2", "p/p2")]),
+        )]);
+        assert!(config.validate().is_err());
+    }
+}
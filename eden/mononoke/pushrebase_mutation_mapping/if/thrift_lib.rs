@@ -0,0 +1,2 @@
+// @generated by autocargo
+::codegen_includer_proc_macro::include!();
@@ -0,0 +1,22 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+CREATE TABLE IF NOT EXISTS `pushrebase_mutation_mapping` (
+  `id` BIGINT UNSIGNED NOT NULL AUTO_INCREMENT,
+  `repo_id` INT UNSIGNED NOT NULL,
+  `predecessor_bcs_id` BINARY(32) NOT NULL,
+  `successor_bcs_id` BINARY(32) NOT NULL,
+  PRIMARY KEY (`id`),
+  -- Duplicate entries are skipped on insertion, which `{insert_or_ignore}` relies on. The key
+  -- also serves the lookups of the successors of a predecessor (forward navigation).
+  UNIQUE KEY `repo_predecessor_successor_key` (`repo_id`, `predecessor_bcs_id`, `successor_bcs_id`),
+  KEY `repo_successor_key` (`repo_id`, `successor_bcs_id`)
+);
+
+-- An existing table gets the unique key once its duplicate rows are removed:
+--   ALTER TABLE `pushrebase_mutation_mapping`
+--     ADD UNIQUE KEY `repo_predecessor_successor_key` (`repo_id`, `predecessor_bcs_id`, `successor_bcs_id`);
@@ -13,3 +13,8 @@ CREATE TABLE IF NOT EXISTS pushrebase_mutation_mapping (
 );
 
 CREATE INDEX IF NOT EXISTS repo_successor_key ON pushrebase_mutation_mapping (repo_id, successor_bcs_id);
+
+-- Duplicate entries are skipped on insertion, which `{insert_or_ignore}` relies on. The index
+-- also serves the lookups of the successors of a predecessor (forward navigation). The MySQL
+-- table has the same key, see mysql-pushrebase-mutation-mapping.sql.
+CREATE UNIQUE INDEX IF NOT EXISTS repo_predecessor_successor_key ON pushrebase_mutation_mapping (repo_id, predecessor_bcs_id, successor_bcs_id);
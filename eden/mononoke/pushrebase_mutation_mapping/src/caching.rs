@@ -0,0 +1,245 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use abomonation_derive::Abomonation;
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use caching_ext::get_or_fill;
+use caching_ext::CacheDisposition;
+use caching_ext::CacheHandlerFactory;
+use caching_ext::CacheTtl;
+use caching_ext::CachelibHandler;
+use caching_ext::EntityStore;
+use caching_ext::KeyedEntityStore;
+use caching_ext::McErrorKind;
+use caching_ext::McResult;
+use caching_ext::MemcacheEntity;
+use caching_ext::MemcacheHandler;
+use context::CoreContext;
+use fbthrift::compact_protocol;
+use futures::future::try_join_all;
+use memcache::KeyGen;
+use mononoke_types::ChangesetId;
+use mononoke_types::RepositoryId;
+use pushrebase_hook::PushrebaseHook;
+use pushrebase_mutation_mapping_thrift as thrift;
+
+use crate::PushrebaseMutationMapping;
+
+/// Predecessors of a single successor changeset.
+#[derive(Abomonation, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PushrebaseMutationMappingCacheEntry {
+    pub repo_id: RepositoryId,
+    pub predecessor_bcs_ids: Vec<ChangesetId>,
+}
+
+impl PushrebaseMutationMappingCacheEntry {
+    fn into_predecessors(self, repo_id: RepositoryId) -> Result<Vec<ChangesetId>> {
+        if self.repo_id == repo_id {
+            Ok(self.predecessor_bcs_ids)
+        } else {
+            Err(anyhow!(
+                "Cache returned invalid entry: repo {} returned for query to repo {}",
+                self.repo_id,
+                repo_id
+            ))
+        }
+    }
+}
+
+/// How long the predecessors of a changeset are cached for.
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Caches the predecessors of the successor changesets.
+///
+/// The predecessors of a changeset are written in the same transaction that
+/// makes the pushrebased changeset public, and are only changed afterwards
+/// when the entries are deleted (see `delete_entries` and
+/// `delete_mapping_for_repo`). The deletions don't invalidate the cache, so the
+/// entries expire after `CACHE_TTL`, bounding how long a deleted mapping can
+/// still be returned. Empty results are not cached, as the changeset may not
+/// have been pushrebased yet. The successors of a changeset can grow over time
+/// (it can be pushrebased again), so successor queries are not cached.
+pub struct CachingPushrebaseMutationMapping {
+    cachelib: CachelibHandler<PushrebaseMutationMappingCacheEntry>,
+    memcache: MemcacheHandler,
+    keygen: KeyGen,
+    repo_id: RepositoryId,
+    inner: Arc<dyn PushrebaseMutationMapping>,
+}
+
+impl CachingPushrebaseMutationMapping {
+    pub fn new(
+        inner: Arc<dyn PushrebaseMutationMapping>,
+        repo_id: RepositoryId,
+        cache_handler_factory: CacheHandlerFactory,
+    ) -> Self {
+        Self {
+            inner,
+            repo_id,
+            cachelib: cache_handler_factory.cachelib(),
+            memcache: cache_handler_factory.memcache(),
+            keygen: Self::create_key_gen(),
+        }
+    }
+
+    pub fn new_test(inner: Arc<dyn PushrebaseMutationMapping>, repo_id: RepositoryId) -> Self {
+        Self::new(inner, repo_id, CacheHandlerFactory::Mocked)
+    }
+
+    fn create_key_gen() -> KeyGen {
+        let key_prefix = "scm.mononoke.pushrebase_mutation_mapping";
+
+        KeyGen::new(
+            key_prefix,
+            thrift::MC_CODEVER as u32,
+            thrift::MC_SITEVER as u32,
+        )
+    }
+
+    pub fn cachelib(&self) -> &CachelibHandler<PushrebaseMutationMappingCacheEntry> {
+        &self.cachelib
+    }
+}
+
+#[async_trait]
+impl PushrebaseMutationMapping for CachingPushrebaseMutationMapping {
+    fn get_hook(&self) -> Option<Box<dyn PushrebaseHook>> {
+        self.inner.get_hook()
+    }
+
+    async fn get_prepushrebase_ids(
+        &self,
+        ctx: &CoreContext,
+        successor_bcs_id: ChangesetId,
+    ) -> Result<Vec<ChangesetId>> {
+        let cache_request = (ctx, self);
+
+        let entry = get_or_fill(&cache_request, HashSet::from([successor_bcs_id]))
+            .await
+            .with_context(|| "Error fetching prepushrebase ids via cache")?
+            .remove(&successor_bcs_id);
+
+        match entry {
+            Some(entry) => entry.into_predecessors(self.repo_id),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    async fn get_successor_ids(
+        &self,
+        ctx: &CoreContext,
+        predecessor_bcs_id: ChangesetId,
+    ) -> Result<Vec<ChangesetId>> {
+        self.inner.get_successor_ids(ctx, predecessor_bcs_id).await
+    }
+}
+
+impl MemcacheEntity for PushrebaseMutationMappingCacheEntry {
+    fn serialize(&self) -> Bytes {
+        let entry = thrift::PushrebaseMutationMappingCacheEntry {
+            repo_id: self.repo_id.id(),
+            predecessor_bcs_ids: self
+                .predecessor_bcs_ids
+                .iter()
+                .map(|bcs_id| bcs_id.into_thrift())
+                .collect(),
+        };
+        compact_protocol::serialize(&entry)
+    }
+
+    fn deserialize(bytes: Bytes) -> McResult<Self> {
+        let thrift::PushrebaseMutationMappingCacheEntry {
+            repo_id,
+            predecessor_bcs_ids,
+        } = compact_protocol::deserialize(bytes).map_err(|_| McErrorKind::Deserialization)?;
+
+        let repo_id = RepositoryId::new(repo_id);
+        let predecessor_bcs_ids = predecessor_bcs_ids
+            .into_iter()
+            .map(ChangesetId::from_thrift)
+            .collect::<Result<_>>()
+            .map_err(|_| McErrorKind::Deserialization)?;
+
+        Ok(PushrebaseMutationMappingCacheEntry {
+            repo_id,
+            predecessor_bcs_ids,
+        })
+    }
+}
+
+type CacheRequest<'a> = (&'a CoreContext, &'a CachingPushrebaseMutationMapping);
+
+impl EntityStore<PushrebaseMutationMappingCacheEntry> for CacheRequest<'_> {
+    fn cachelib(&self) -> &CachelibHandler<PushrebaseMutationMappingCacheEntry> {
+        let (_, mapping) = self;
+        &mapping.cachelib
+    }
+
+    fn keygen(&self) -> &KeyGen {
+        let (_, mapping) = self;
+        &mapping.keygen
+    }
+
+    fn memcache(&self) -> &MemcacheHandler {
+        let (_, mapping) = self;
+        &mapping.memcache
+    }
+
+    fn cache_determinator(&self, entry: &PushrebaseMutationMappingCacheEntry) -> CacheDisposition {
+        if entry.predecessor_bcs_ids.is_empty() {
+            CacheDisposition::Ignore
+        } else {
+            CacheDisposition::Cache(CacheTtl::Ttl(CACHE_TTL))
+        }
+    }
+
+    caching_ext::impl_singleton_stats!("pushrebase_mutation_mapping");
+}
+
+#[async_trait]
+impl KeyedEntityStore<ChangesetId, PushrebaseMutationMappingCacheEntry> for CacheRequest<'_> {
+    fn get_cache_key(&self, key: &ChangesetId) -> String {
+        let (_, mapping) = self;
+        format!("{}.successor.{}", mapping.repo_id, key)
+    }
+
+    async fn get_from_db(
+        &self,
+        keys: HashSet<ChangesetId>,
+    ) -> Result<HashMap<ChangesetId, PushrebaseMutationMappingCacheEntry>, Error> {
+        let (ctx, mapping) = self;
+        let repo_id = mapping.repo_id;
+
+        let res = try_join_all(keys.into_iter().map(|successor_bcs_id| async move {
+            let predecessor_bcs_ids = mapping
+                .inner
+                .get_prepushrebase_ids(ctx, successor_bcs_id)
+                .await?;
+            anyhow::Ok((
+                successor_bcs_id,
+                PushrebaseMutationMappingCacheEntry {
+                    repo_id,
+                    predecessor_bcs_ids,
+                },
+            ))
+        }))
+        .await
+        .with_context(|| "Error fetching prepushrebase ids from SQL")?;
+
+        Ok(res.into_iter().collect())
+    }
+}
@@ -5,6 +5,7 @@
  * GNU General Public License version 2.
  */
 
+mod caching;
 mod save_mapping_pushrebase_hook;
 mod sql_queries;
 #[cfg(test)]
@@ -12,15 +13,24 @@ mod test;
 
 use anyhow::Result;
 use async_trait::async_trait;
+pub use caching::CachingPushrebaseMutationMapping;
 use context::CoreContext;
 use mononoke_types::ChangesetId;
 use mononoke_types::RepositoryId;
 use pushrebase_hook::PushrebaseHook;
 pub use sql_queries::add_pushrebase_mapping;
+pub use sql_queries::count_distinct_successors;
+pub use sql_queries::count_mappings;
+pub use sql_queries::delete_entries;
+pub use sql_queries::delete_mapping_for_repo;
 pub use sql_queries::get_prepushrebase_ids;
+pub use sql_queries::get_prepushrebase_ids_many;
+pub use sql_queries::get_successor_ids;
+pub use sql_queries::list_mappings;
 pub use sql_queries::SqlPushrebaseMutationMapping;
 pub use sql_queries::SqlPushrebaseMutationMappingConnection;
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PushrebaseMutationMappingEntry {
     repo_id: RepositoryId,
     predecessor_bcs_id: ChangesetId,
@@ -39,6 +49,18 @@ impl PushrebaseMutationMappingEntry {
             successor_bcs_id,
         }
     }
+
+    pub fn repo_id(&self) -> RepositoryId {
+        self.repo_id
+    }
+
+    pub fn predecessor_bcs_id(&self) -> ChangesetId {
+        self.predecessor_bcs_id
+    }
+
+    pub fn successor_bcs_id(&self) -> ChangesetId {
+        self.successor_bcs_id
+    }
 }
 
 #[async_trait]
@@ -50,4 +72,10 @@ pub trait PushrebaseMutationMapping: Send + Sync {
         ctx: &CoreContext,
         successor_bcs_id: ChangesetId,
     ) -> Result<Vec<ChangesetId>>;
+    /// Changesets the given changeset was pushrebased to.
+    async fn get_successor_ids(
+        &self,
+        ctx: &CoreContext,
+        predecessor_bcs_id: ChangesetId,
+    ) -> Result<Vec<ChangesetId>>;
 }
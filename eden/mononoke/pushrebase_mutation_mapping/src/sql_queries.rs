@@ -5,6 +5,9 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashMap;
+
+use anyhow::anyhow;
 use anyhow::Result;
 use async_trait::async_trait;
 use context::CoreContext;
@@ -34,6 +37,59 @@ mononoke_queries! {
         WHERE repo_id = {repo_id} AND successor_bcs_id = {successor_bcs_id}"
     }
 
+    read SelectPrepushrebaseIdsMany(
+        repo_id: RepositoryId,
+        >list successor_bcs_ids: ChangesetId
+    ) -> (ChangesetId, ChangesetId) {
+        "SELECT successor_bcs_id, predecessor_bcs_id
+        FROM pushrebase_mutation_mapping
+        WHERE repo_id = {repo_id} AND successor_bcs_id IN {successor_bcs_ids}"
+    }
+
+    read SelectSuccessorIds(
+        repo_id: RepositoryId,
+        predecessor_bcs_id: ChangesetId,
+    ) -> (ChangesetId,) {
+        "SELECT successor_bcs_id
+        FROM pushrebase_mutation_mapping
+        WHERE repo_id = {repo_id} AND predecessor_bcs_id = {predecessor_bcs_id}"
+    }
+
+    read SelectSuccessorsPage(
+        repo_id: RepositoryId,
+        limit: usize,
+    ) -> (ChangesetId,) {
+        "SELECT DISTINCT successor_bcs_id
+        FROM pushrebase_mutation_mapping
+        WHERE repo_id = {repo_id}
+        ORDER BY successor_bcs_id
+        LIMIT {limit}"
+    }
+
+    read SelectSuccessorsPageAfter(
+        repo_id: RepositoryId,
+        after: ChangesetId,
+        limit: usize,
+    ) -> (ChangesetId,) {
+        "SELECT DISTINCT successor_bcs_id
+        FROM pushrebase_mutation_mapping
+        WHERE repo_id = {repo_id} AND successor_bcs_id > {after}
+        ORDER BY successor_bcs_id
+        LIMIT {limit}"
+    }
+
+    read CountMappings(repo_id: RepositoryId) -> (u64) {
+        "SELECT COUNT(*)
+        FROM pushrebase_mutation_mapping
+        WHERE repo_id = {repo_id}"
+    }
+
+    read CountDistinctSuccessors(repo_id: RepositoryId) -> (u64) {
+        "SELECT COUNT(DISTINCT successor_bcs_id)
+        FROM pushrebase_mutation_mapping
+        WHERE repo_id = {repo_id}"
+    }
+
     write InsertMappingEntries(values:(
         repo_id: RepositoryId,
         predecessor_bcs_id: ChangesetId,
@@ -45,6 +101,21 @@ mononoke_queries! {
        (repo_id, predecessor_bcs_id, successor_bcs_id)
        VALUES {values}"
     }
+
+    write DeleteMappingForRepo(repo_id: RepositoryId) {
+        none,
+        "DELETE FROM pushrebase_mutation_mapping
+        WHERE repo_id = {repo_id}"
+    }
+
+    write DeleteMappingEntries(
+        repo_id: RepositoryId,
+        >list successor_bcs_ids: ChangesetId
+    ) {
+        none,
+        "DELETE FROM pushrebase_mutation_mapping
+        WHERE repo_id = {repo_id} AND successor_bcs_id IN {successor_bcs_ids}"
+    }
 }
 
 pub async fn add_pushrebase_mapping(
@@ -68,6 +139,49 @@ pub async fn add_pushrebase_mapping(
     Ok(transaction)
 }
 
+/// Delete all the mapping entries of the repo, returning the number of deleted rows.
+///
+/// The mapping is written by `SaveMappingPushrebaseHook` in the same transaction
+/// that moves the bookmark. The deletion only removes the rows visible to the
+/// transaction, so a pushrebase landing concurrently can still add entries: the
+/// caller is expected to stop the writes to the repo (e.g. lock it) first.
+///
+/// The cached predecessors aren't invalidated, and can still be returned until
+/// they expire.
+pub async fn delete_mapping_for_repo(
+    transaction: Transaction,
+    repo_id: RepositoryId,
+) -> Result<(Transaction, u64)> {
+    let (transaction, result) =
+        DeleteMappingForRepo::query_with_transaction(transaction, &repo_id).await?;
+
+    Ok((transaction, result.affected_rows()))
+}
+
+/// Delete the mapping entries of the given successors, returning the number of
+/// deleted rows. As the entries are inserted with `insert_or_ignore`, a concurrent
+/// pushrebase re-adding the same entry either commits before the deletion (and the
+/// entry is deleted) or after it (and the entry stays), depending on the order the
+/// database locks the rows in.
+///
+/// The cached predecessors aren't invalidated, and can still be returned until
+/// they expire.
+pub async fn delete_entries(
+    transaction: Transaction,
+    repo_id: RepositoryId,
+    successor_bcs_ids: &[ChangesetId],
+) -> Result<(Transaction, u64)> {
+    if successor_bcs_ids.is_empty() {
+        return Ok((transaction, 0));
+    }
+
+    let (transaction, result) =
+        DeleteMappingEntries::query_with_transaction(transaction, &repo_id, successor_bcs_ids)
+            .await?;
+
+    Ok((transaction, result.affected_rows()))
+}
+
 pub async fn get_prepushrebase_ids(
     connection: &Connection,
     repo_id: RepositoryId,
@@ -78,14 +192,159 @@ pub async fn get_prepushrebase_ids(
     Ok(rows.into_iter().map(|r| r.0).collect())
 }
 
+pub async fn get_prepushrebase_ids_many(
+    connection: &Connection,
+    repo_id: RepositoryId,
+    successor_bcs_ids: &[ChangesetId],
+) -> Result<HashMap<ChangesetId, Vec<ChangesetId>>> {
+    let mut ids: HashMap<_, Vec<_>> = HashMap::new();
+    if successor_bcs_ids.is_empty() {
+        return Ok(ids);
+    }
+
+    let rows = SelectPrepushrebaseIdsMany::query(connection, &repo_id, successor_bcs_ids).await?;
+    for (successor_bcs_id, predecessor_bcs_id) in rows {
+        ids.entry(successor_bcs_id)
+            .or_default()
+            .push(predecessor_bcs_id);
+    }
+
+    Ok(ids)
+}
+
+pub async fn get_successor_ids(
+    connection: &Connection,
+    repo_id: RepositoryId,
+    predecessor_bcs_id: ChangesetId,
+) -> Result<Vec<ChangesetId>> {
+    let rows = SelectSuccessorIds::query(connection, &repo_id, &predecessor_bcs_id).await?;
+
+    Ok(rows.into_iter().map(|r| r.0).collect())
+}
+
+/// List the mapping entries of the repo one page at a time, for the callers that can't load
+/// all of them at once. A page holds all the entries of the first `limit` successors after
+/// `after` (or of the repo if `None`), ordered by successor and then predecessor. The cursor
+/// to pass as `after` to get the next page is returned along with it, `None` meaning this
+/// was the last page.
+///
+/// As pages never split the entries of a successor, the successors are only ever listed
+/// once even if entries are inserted concurrently. The entries of successors that were
+/// already listed when they got inserted are missed though.
+pub async fn list_mappings(
+    connection: &Connection,
+    repo_id: RepositoryId,
+    after: Option<ChangesetId>,
+    limit: usize,
+) -> Result<(Vec<PushrebaseMutationMappingEntry>, Option<ChangesetId>)> {
+    let successor_rows = match after {
+        Some(after) => {
+            SelectSuccessorsPageAfter::query(connection, &repo_id, &after, &limit).await?
+        }
+        None => SelectSuccessorsPage::query(connection, &repo_id, &limit).await?,
+    };
+    let successor_bcs_ids: Vec<_> = successor_rows.into_iter().map(|r| r.0).collect();
+
+    let cursor = match successor_bcs_ids.last() {
+        Some(last) if successor_bcs_ids.len() == limit => Some(*last),
+        _ => None,
+    };
+
+    let prepushrebase_ids =
+        get_prepushrebase_ids_many(connection, repo_id, &successor_bcs_ids).await?;
+    let mut entries = Vec::new();
+    for successor_bcs_id in successor_bcs_ids {
+        let mut predecessor_bcs_ids = prepushrebase_ids
+            .get(&successor_bcs_id)
+            .cloned()
+            .unwrap_or_default();
+        predecessor_bcs_ids.sort();
+        entries.extend(predecessor_bcs_ids.into_iter().map(|predecessor_bcs_id| {
+            PushrebaseMutationMappingEntry::new(repo_id, predecessor_bcs_id, successor_bcs_id)
+        }));
+    }
+
+    Ok((entries, cursor))
+}
+
+/// Number of mapping entries of the repo.
+pub async fn count_mappings(connection: &Connection, repo_id: RepositoryId) -> Result<u64> {
+    let rows = CountMappings::query(connection, &repo_id).await?;
+    rows.into_iter()
+        .next()
+        .map(|count| count.0)
+        .ok_or_else(|| anyhow!("Failed to count the pushrebase mutation mappings"))
+}
+
+/// Number of changesets of the repo that are the result of a pushrebase.
+pub async fn count_distinct_successors(
+    connection: &Connection,
+    repo_id: RepositoryId,
+) -> Result<u64> {
+    let rows = CountDistinctSuccessors::query(connection, &repo_id).await?;
+    rows.into_iter()
+        .next()
+        .map(|count| count.0)
+        .ok_or_else(|| anyhow!("Failed to count the pushrebase successors"))
+}
+
 pub struct SqlPushrebaseMutationMapping {
     repo_id: RepositoryId,
+    /// Name of the repo, used to look up the per repo tunables.
+    repo_name: Option<String>,
     sql_conn: SqlPushrebaseMutationMappingConnection,
 }
 
 impl SqlPushrebaseMutationMapping {
     pub fn new(repo_id: RepositoryId, sql_conn: SqlPushrebaseMutationMappingConnection) -> Self {
-        Self { repo_id, sql_conn }
+        Self {
+            repo_id,
+            repo_name: None,
+            sql_conn,
+        }
+    }
+
+    /// Let the per repo tunables of `repo_name` override the global ones.
+    pub fn with_repo_name(mut self, repo_name: String) -> Self {
+        self.repo_name = Some(repo_name);
+        self
+    }
+
+    /// Whether `SaveMappingPushrebaseHook` is disabled for the repo, falling back to the
+    /// global tunable if the repo doesn't override it.
+    fn save_mapping_hook_disabled(&self) -> bool {
+        let tunables = tunables();
+        self.repo_name
+            .as_ref()
+            .and_then(|repo_name| {
+                tunables.by_repo_disable_save_mapping_pushrebase_hook_for_repo(repo_name)
+            })
+            .or_else(|| tunables.disable_save_mapping_pushrebase_hook())
+            .unwrap_or_default()
+    }
+
+    /// Changesets that were pushrebased to the given changeset. Reads from the
+    /// replica, falling back to the master if the replica doesn't know about it yet.
+    pub async fn get_prepushrebase_ids(
+        &self,
+        ctx: &CoreContext,
+        successor_bcs_id: ChangesetId,
+    ) -> Result<Vec<ChangesetId>> {
+        self.sql_conn
+            .get_prepushrebase_ids(ctx, self.repo_id, successor_bcs_id)
+            .await
+    }
+
+    /// Changesets the given changeset was pushrebased to. Reads from the
+    /// replica, falling back to the master if the replica doesn't know about it yet.
+    pub async fn get_successor_ids(
+        &self,
+        ctx: &CoreContext,
+        predecessor_bcs_id: ChangesetId,
+    ) -> Result<Vec<ChangesetId>> {
+        self.sql_conn
+            .get_successor_ids(ctx, self.repo_id, predecessor_bcs_id)
+            .await
     }
 }
 
@@ -120,6 +379,24 @@ impl SqlPushrebaseMutationMappingConnection {
         }
         Ok(ids)
     }
+
+    async fn get_successor_ids(
+        &self,
+        ctx: &CoreContext,
+        repo_id: RepositoryId,
+        predecessor_bcs_id: ChangesetId,
+    ) -> Result<Vec<ChangesetId>> {
+        ctx.perf_counters()
+            .increment_counter(PerfCounterType::SqlReadsReplica);
+        let mut ids = get_successor_ids(&self.read_connection, repo_id, predecessor_bcs_id).await?;
+        if ids.is_empty() {
+            ctx.perf_counters()
+                .increment_counter(PerfCounterType::SqlReadsMaster);
+            ids = get_successor_ids(&self.read_master_connection, repo_id, predecessor_bcs_id)
+                .await?;
+        }
+        Ok(ids)
+    }
 }
 
 impl SqlConstruct for SqlPushrebaseMutationMappingConnection {
@@ -128,8 +405,8 @@ impl SqlConstruct for SqlPushrebaseMutationMappingConnection {
     const CREATION_QUERY: &'static str =
         include_str!("../schemas/sqlite-pushrebase-mutation-mapping.sql");
 
-    // We don't need the connections because we never use them.
-    // But we need SqlConstruct to get our SQL tables created in tests.
+    // The write connection is unused, as the mapping is written as part of the
+    // pushrebase transaction, see `SaveMappingPushrebaseHook`.
     fn from_sql_connections(connections: SqlConnections) -> Self {
         Self {
             write_connection: connections.write_connection,
@@ -144,10 +421,7 @@ impl SqlConstructFromMetadataDatabaseConfig for SqlPushrebaseMutationMappingConn
 #[async_trait]
 impl PushrebaseMutationMapping for SqlPushrebaseMutationMapping {
     fn get_hook(&self) -> Option<Box<dyn PushrebaseHook>> {
-        if tunables()
-            .disable_save_mapping_pushrebase_hook()
-            .unwrap_or_default()
-        {
+        if self.save_mapping_hook_disabled() {
             None
         } else {
             Some(SaveMappingPushrebaseHook::new(self.repo_id))
@@ -159,8 +433,14 @@ impl PushrebaseMutationMapping for SqlPushrebaseMutationMapping {
         ctx: &CoreContext,
         successor_bcs_id: ChangesetId,
     ) -> Result<Vec<ChangesetId>> {
-        self.sql_conn
-            .get_prepushrebase_ids(ctx, self.repo_id, successor_bcs_id)
-            .await
+        SqlPushrebaseMutationMapping::get_prepushrebase_ids(self, ctx, successor_bcs_id).await
+    }
+
+    async fn get_successor_ids(
+        &self,
+        ctx: &CoreContext,
+        predecessor_bcs_id: ChangesetId,
+    ) -> Result<Vec<ChangesetId>> {
+        SqlPushrebaseMutationMapping::get_successor_ids(self, ctx, predecessor_bcs_id).await
     }
 }
@@ -5,16 +5,32 @@
  * GNU General Public License version 2.
  */
 
+use std::sync::Arc;
+
 use anyhow::Result;
+use context::CoreContext;
 use fbinit::FacebookInit;
+use maplit::hashmap;
 use mononoke_types_mocks::changesetid;
 use mononoke_types_mocks::repo;
 use sql::Connection;
+use sql::SqlConnections;
 use sql_construct::SqlConstruct;
 use sql_ext::open_sqlite_in_memory;
+use tunables::with_tunables;
+use tunables::MononokeTunables;
 
 use crate::add_pushrebase_mapping;
+use crate::count_distinct_successors;
+use crate::count_mappings;
+use crate::delete_entries;
+use crate::delete_mapping_for_repo;
 use crate::get_prepushrebase_ids;
+use crate::get_prepushrebase_ids_many;
+use crate::get_successor_ids;
+use crate::list_mappings;
+use crate::CachingPushrebaseMutationMapping;
+use crate::PushrebaseMutationMapping;
 use crate::PushrebaseMutationMappingEntry;
 use crate::SqlPushrebaseMutationMappingConnection;
 
@@ -62,3 +78,503 @@ async fn test_add_and_get(_fb: FacebookInit) -> Result<()> {
 
     Ok(())
 }
+
+#[fbinit::test]
+async fn test_get_successors(_fb: FacebookInit) -> Result<()> {
+    let conn = open_sqlite_in_memory()?;
+    conn.execute_batch(SqlPushrebaseMutationMappingConnection::CREATION_QUERY)?;
+    let conn = Connection::with_sqlite(conn);
+
+    let entries = vec![
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::TWOS_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::THREES_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::TWOS_CSID,
+            changesetid::FOURS_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ZERO,
+            changesetid::ONES_CSID,
+            changesetid::FOURS_CSID,
+        ),
+    ];
+
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &entries).await?;
+    txn.commit().await?;
+
+    let mut successor_ids =
+        get_successor_ids(&conn, repo::REPO_ONE, changesetid::ONES_CSID).await?;
+    successor_ids.sort();
+
+    assert_eq!(
+        successor_ids,
+        vec![changesetid::TWOS_CSID, changesetid::THREES_CSID]
+    );
+
+    let successor_ids = get_successor_ids(&conn, repo::REPO_ONE, changesetid::THREES_CSID).await?;
+    assert!(successor_ids.is_empty());
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_get_many(_fb: FacebookInit) -> Result<()> {
+    let conn = open_sqlite_in_memory()?;
+    conn.execute_batch(SqlPushrebaseMutationMappingConnection::CREATION_QUERY)?;
+    let conn = Connection::with_sqlite(conn);
+
+    let entries = vec![
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::THREES_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::TWOS_CSID,
+            changesetid::THREES_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::FOURS_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ZERO,
+            changesetid::TWOS_CSID,
+            changesetid::FOURS_CSID,
+        ),
+    ];
+
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &entries).await?;
+    txn.commit().await?;
+
+    let mut prepushrebase_ids = get_prepushrebase_ids_many(
+        &conn,
+        repo::REPO_ONE,
+        &[
+            changesetid::THREES_CSID,
+            changesetid::FOURS_CSID,
+            changesetid::FIVES_CSID,
+        ],
+    )
+    .await?;
+    prepushrebase_ids.values_mut().for_each(|ids| ids.sort());
+
+    assert_eq!(
+        prepushrebase_ids,
+        hashmap! {
+            changesetid::THREES_CSID => vec![changesetid::ONES_CSID, changesetid::TWOS_CSID],
+            changesetid::FOURS_CSID => vec![changesetid::ONES_CSID],
+        }
+    );
+
+    assert!(
+        get_prepushrebase_ids_many(&conn, repo::REPO_ONE, &[])
+            .await?
+            .is_empty()
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_mapping_read_path(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let conn = open_sqlite_in_memory()?;
+    conn.execute_batch(SqlPushrebaseMutationMappingConnection::CREATION_QUERY)?;
+    let conn = Connection::with_sqlite(conn);
+    let mapping = SqlPushrebaseMutationMappingConnection::from_sql_connections(
+        SqlConnections::new_single(conn.clone()),
+    )
+    .with_repo_id(repo::REPO_ONE);
+
+    let entries = vec![
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::TWOS_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ZERO,
+            changesetid::THREES_CSID,
+            changesetid::TWOS_CSID,
+        ),
+    ];
+
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &entries).await?;
+    txn.commit().await?;
+
+    assert_eq!(
+        mapping
+            .get_prepushrebase_ids(&ctx, changesetid::TWOS_CSID)
+            .await?,
+        vec![changesetid::ONES_CSID]
+    );
+    assert_eq!(
+        mapping
+            .get_successor_ids(&ctx, changesetid::ONES_CSID)
+            .await?,
+        vec![changesetid::TWOS_CSID]
+    );
+    assert!(
+        mapping
+            .get_successor_ids(&ctx, changesetid::THREES_CSID)
+            .await?
+            .is_empty()
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_caching(fb: FacebookInit) -> Result<()> {
+    let ctx = CoreContext::test_mock(fb);
+    let conn = open_sqlite_in_memory()?;
+    conn.execute_batch(SqlPushrebaseMutationMappingConnection::CREATION_QUERY)?;
+    let conn = Connection::with_sqlite(conn);
+    let mapping = Arc::new(
+        SqlPushrebaseMutationMappingConnection::from_sql_connections(SqlConnections::new_single(
+            conn.clone(),
+        ))
+        .with_repo_id(repo::REPO_ONE),
+    );
+    let caching = CachingPushrebaseMutationMapping::new_test(mapping, repo::REPO_ONE);
+
+    let store = caching
+        .cachelib()
+        .mock_store()
+        .expect("new_test gives us a MockStore");
+
+    let entries = vec![PushrebaseMutationMappingEntry::new(
+        repo::REPO_ONE,
+        changesetid::ONES_CSID,
+        changesetid::TWOS_CSID,
+    )];
+
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &entries).await?;
+    txn.commit().await?;
+
+    assert_eq!(
+        caching
+            .get_prepushrebase_ids(&ctx, changesetid::TWOS_CSID)
+            .await?,
+        vec![changesetid::ONES_CSID]
+    );
+
+    assert_eq!(store.stats().gets, 1);
+    assert_eq!(store.stats().hits, 0);
+    assert_eq!(store.stats().sets, 1);
+
+    // The second read is served from the cache
+    assert_eq!(
+        caching
+            .get_prepushrebase_ids(&ctx, changesetid::TWOS_CSID)
+            .await?,
+        vec![changesetid::ONES_CSID]
+    );
+
+    assert_eq!(store.stats().gets, 2);
+    assert_eq!(store.stats().hits, 1);
+    assert_eq!(store.stats().sets, 1);
+
+    // Empty results are not cached
+    assert!(
+        caching
+            .get_prepushrebase_ids(&ctx, changesetid::THREES_CSID)
+            .await?
+            .is_empty()
+    );
+
+    assert_eq!(store.stats().gets, 3);
+    assert_eq!(store.stats().hits, 1);
+    assert_eq!(store.stats().sets, 1);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_delete(_fb: FacebookInit) -> Result<()> {
+    let conn = open_sqlite_in_memory()?;
+    conn.execute_batch(SqlPushrebaseMutationMappingConnection::CREATION_QUERY)?;
+    let conn = Connection::with_sqlite(conn);
+
+    let entries = vec![
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ZERO,
+            changesetid::ONES_CSID,
+            changesetid::TWOS_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::TWOS_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::THREES_CSID,
+            changesetid::TWOS_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::FOURS_CSID,
+        ),
+    ];
+
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &entries).await?;
+    txn.commit().await?;
+
+    // Delete the entries of a single successor
+    let txn = conn.start_transaction().await?;
+    let (txn, deleted) = delete_entries(txn, repo::REPO_ONE, &[changesetid::TWOS_CSID]).await?;
+    txn.commit().await?;
+    assert_eq!(deleted, 2);
+
+    assert!(
+        get_prepushrebase_ids(&conn, repo::REPO_ONE, changesetid::TWOS_CSID)
+            .await?
+            .is_empty()
+    );
+    assert_eq!(
+        get_prepushrebase_ids(&conn, repo::REPO_ONE, changesetid::FOURS_CSID).await?,
+        vec![changesetid::ONES_CSID]
+    );
+
+    // Delete all the remaining entries of the repo, other repos are not affected
+    let txn = conn.start_transaction().await?;
+    let (txn, deleted) = delete_mapping_for_repo(txn, repo::REPO_ONE).await?;
+    txn.commit().await?;
+    assert_eq!(deleted, 1);
+
+    assert!(
+        get_prepushrebase_ids(&conn, repo::REPO_ONE, changesetid::FOURS_CSID)
+            .await?
+            .is_empty()
+    );
+    assert_eq!(
+        get_prepushrebase_ids(&conn, repo::REPO_ZERO, changesetid::TWOS_CSID).await?,
+        vec![changesetid::ONES_CSID]
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_list_mappings(_fb: FacebookInit) -> Result<()> {
+    let conn = open_sqlite_in_memory()?;
+    conn.execute_batch(SqlPushrebaseMutationMappingConnection::CREATION_QUERY)?;
+    let conn = Connection::with_sqlite(conn);
+
+    let entry = |predecessor_bcs_id, successor_bcs_id| {
+        PushrebaseMutationMappingEntry::new(repo::REPO_ONE, predecessor_bcs_id, successor_bcs_id)
+    };
+    let entries = vec![
+        entry(changesetid::THREES_CSID, changesetid::TWOS_CSID),
+        entry(changesetid::ONES_CSID, changesetid::TWOS_CSID),
+        entry(changesetid::ONES_CSID, changesetid::THREES_CSID),
+        entry(changesetid::TWOS_CSID, changesetid::FOURS_CSID),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ZERO,
+            changesetid::ONES_CSID,
+            changesetid::FIVES_CSID,
+        ),
+    ];
+
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &entries).await?;
+    txn.commit().await?;
+
+    // The entries of a successor are never split across pages
+    let (page, cursor) = list_mappings(&conn, repo::REPO_ONE, None, 2).await?;
+    assert_eq!(
+        page,
+        vec![
+            entry(changesetid::ONES_CSID, changesetid::TWOS_CSID),
+            entry(changesetid::THREES_CSID, changesetid::TWOS_CSID),
+            entry(changesetid::ONES_CSID, changesetid::THREES_CSID),
+        ]
+    );
+    assert_eq!(cursor, Some(changesetid::THREES_CSID));
+
+    // Entries inserted in between only show up if their successor wasn't listed yet
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(
+        txn,
+        &[
+            entry(changesetid::FOURS_CSID, changesetid::ONES_CSID),
+            entry(changesetid::ONES_CSID, changesetid::FIVES_CSID),
+        ],
+    )
+    .await?;
+    txn.commit().await?;
+
+    let (page, cursor) = list_mappings(&conn, repo::REPO_ONE, cursor, 2).await?;
+    assert_eq!(
+        page,
+        vec![
+            entry(changesetid::TWOS_CSID, changesetid::FOURS_CSID),
+            entry(changesetid::ONES_CSID, changesetid::FIVES_CSID),
+        ]
+    );
+    assert_eq!(cursor, Some(changesetid::FIVES_CSID));
+
+    let (page, cursor) = list_mappings(&conn, repo::REPO_ONE, cursor, 2).await?;
+    assert!(page.is_empty());
+    assert_eq!(cursor, None);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_count_mappings(_fb: FacebookInit) -> Result<()> {
+    let conn = open_sqlite_in_memory()?;
+    conn.execute_batch(SqlPushrebaseMutationMappingConnection::CREATION_QUERY)?;
+    let conn = Connection::with_sqlite(conn);
+
+    assert_eq!(count_mappings(&conn, repo::REPO_ONE).await?, 0);
+    assert_eq!(count_distinct_successors(&conn, repo::REPO_ONE).await?, 0);
+
+    let entries = vec![
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::THREES_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::TWOS_CSID,
+            changesetid::THREES_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ONE,
+            changesetid::ONES_CSID,
+            changesetid::FOURS_CSID,
+        ),
+        PushrebaseMutationMappingEntry::new(
+            repo::REPO_ZERO,
+            changesetid::ONES_CSID,
+            changesetid::FIVES_CSID,
+        ),
+    ];
+
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &entries).await?;
+    txn.commit().await?;
+
+    assert_eq!(count_mappings(&conn, repo::REPO_ONE).await?, 3);
+    assert_eq!(count_distinct_successors(&conn, repo::REPO_ONE).await?, 2);
+    assert_eq!(count_mappings(&conn, repo::REPO_ZERO).await?, 1);
+    assert_eq!(count_distinct_successors(&conn, repo::REPO_ZERO).await?, 1);
+
+    Ok(())
+}
+
+#[fbinit::test]
+async fn test_add_duplicates(_fb: FacebookInit) -> Result<()> {
+    let conn = open_sqlite_in_memory()?;
+    conn.execute_batch(SqlPushrebaseMutationMappingConnection::CREATION_QUERY)?;
+    let conn = Connection::with_sqlite(conn);
+
+    let entry = PushrebaseMutationMappingEntry::new(
+        repo::REPO_ONE,
+        changesetid::ONES_CSID,
+        changesetid::TWOS_CSID,
+    );
+    let other = PushrebaseMutationMappingEntry::new(
+        repo::REPO_ONE,
+        changesetid::THREES_CSID,
+        changesetid::TWOS_CSID,
+    );
+
+    // Duplicates within a batch are ignored
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &[entry.clone(), entry.clone()]).await?;
+    txn.commit().await?;
+    assert_eq!(count_mappings(&conn, repo::REPO_ONE).await?, 1);
+
+    // As are the entries already present, without preventing the new ones from being added
+    let txn = conn.start_transaction().await?;
+    let txn = add_pushrebase_mapping(txn, &[entry, other]).await?;
+    txn.commit().await?;
+    assert_eq!(count_mappings(&conn, repo::REPO_ONE).await?, 2);
+
+    let mut prepushrebase_ids =
+        get_prepushrebase_ids(&conn, repo::REPO_ONE, changesetid::TWOS_CSID).await?;
+    prepushrebase_ids.sort();
+    assert_eq!(
+        prepushrebase_ids,
+        vec![changesetid::ONES_CSID, changesetid::THREES_CSID]
+    );
+
+    Ok(())
+}
+
+#[fbinit::test]
+fn test_disable_hook(_fb: FacebookInit) -> Result<()> {
+    let mapping = |repo_name: &str| -> Result<_> {
+        Ok(
+            SqlPushrebaseMutationMappingConnection::with_sqlite_in_memory()?
+                .with_repo_id(repo::REPO_ONE)
+                .with_repo_name(repo_name.to_string()),
+        )
+    };
+    let first = mapping("first")?;
+    let second = mapping("second")?;
+
+    // Enabled by default
+    assert!(first.get_hook().is_some());
+
+    // Disabled globally
+    let tunables = MononokeTunables::default();
+    tunables.update_bools(&hashmap! {
+        "disable_save_mapping_pushrebase_hook".to_string() => true,
+    });
+    with_tunables(tunables, || {
+        assert!(first.get_hook().is_none());
+        assert!(second.get_hook().is_none());
+    });
+
+    // Disabled for a single repo
+    let tunables = MononokeTunables::default();
+    tunables.update_by_repo_bools(&hashmap! {
+        "first".to_string() => hashmap! {
+            "disable_save_mapping_pushrebase_hook_for_repo".to_string() => true,
+        },
+    });
+    with_tunables(tunables, || {
+        assert!(first.get_hook().is_none());
+        assert!(second.get_hook().is_some());
+    });
+
+    // The repo override wins over the global tunable
+    let tunables = MononokeTunables::default();
+    tunables.update_bools(&hashmap! {
+        "disable_save_mapping_pushrebase_hook".to_string() => true,
+    });
+    tunables.update_by_repo_bools(&hashmap! {
+        "first".to_string() => hashmap! {
+            "disable_save_mapping_pushrebase_hook_for_repo".to_string() => false,
+        },
+    });
+    with_tunables(tunables, || {
+        assert!(first.get_hook().is_some());
+        assert!(second.get_hook().is_none());
+    });
+
+    Ok(())
+}
@@ -847,12 +847,16 @@ impl RepoFactory {
     pub async fn pushrebase_mutation_mapping(
         &self,
         repo_config: &ArcRepoConfig,
+        repo_identity: &ArcRepoIdentity,
     ) -> Result<ArcPushrebaseMutationMapping> {
         let conn = self
             .open::<SqlPushrebaseMutationMappingConnection>(&repo_config.storage_config.metadata)
             .await
             .context(RepoFactoryError::PushrebaseMutationMapping)?;
-        Ok(Arc::new(conn.with_repo_id(repo_config.repoid)))
+        Ok(Arc::new(
+            conn.with_repo_id(repo_config.repoid)
+                .with_repo_name(repo_identity.name().to_string()),
+        ))
     }
 
     pub async fn permission_checker(
@@ -446,7 +446,8 @@ impl TestRepoFactory {
             SqlPushrebaseMutationMappingConnection::from_sql_connections(
                 self.metadata_db.clone().into(),
             )
-            .with_repo_id(repo_identity.id()),
+            .with_repo_id(repo_identity.id())
+            .with_repo_name(repo_identity.name().to_string()),
         ))
     }
 
@@ -65,6 +65,7 @@ define_perf_counters! {
         BlobPutsMaxLatency,
         BlobPutsDeduplicated,
         BlobPutsTotalSize,
+        BlobPutsWalFailures,
         BytesSent,
         CachelibHits,
         CachelibMisses,
@@ -167,6 +168,7 @@ impl PerfCounterType {
             | BlobPutsShardAccessWait
             | BlobPutsDeduplicated
             | BlobPutsTotalSize
+            | BlobPutsWalFailures
             | BytesSent
             | CachelibHits
             | CachelibMisses
@@ -186,6 +186,8 @@ pub struct MononokeTunables {
 
     // Disable running SaveMappingPushrebaseHook on every Pushrebase
     disable_save_mapping_pushrebase_hook: TunableBool,
+    // Per repo override of disable_save_mapping_pushrebase_hook
+    disable_save_mapping_pushrebase_hook_for_repo: TunableBoolByRepo,
 
     // Set to 0 to disable compression
     zstd_compression_level: TunableI64,
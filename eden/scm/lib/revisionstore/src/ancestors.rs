@@ -0,0 +1,492 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Ancestor traversal on top of `HgIdHistoryStore::get_node_info`.
+
+use std::collections::hash_map::Entry;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+use anyhow::format_err;
+use anyhow::Result;
+use types::Key;
+use types::NodeInfo;
+
+use crate::error::ConflictingNodeInfo;
+use crate::error::HistoryCycle;
+use crate::historystore::HgIdHistoryStore;
+
+/// The full history of a key: every ancestor along with its `NodeInfo`.
+pub type Ancestors = HashMap<Key, NodeInfo>;
+
+/// Combines two histories, e.g. fetched from different stores. Fails with `ConflictingNodeInfo`
+/// if they disagree on the `NodeInfo` of a key.
+pub fn merge_ancestors(mut a: Ancestors, b: Ancestors) -> Result<Ancestors> {
+    for (key, info) in b {
+        match a.entry(key) {
+            Entry::Occupied(entry) => {
+                if entry.get() != &info {
+                    return Err(ConflictingNodeInfo {
+                        key: entry.key().clone(),
+                        first: entry.get().clone(),
+                        second: info,
+                    }
+                    .into());
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(info);
+            }
+        }
+    }
+    Ok(a)
+}
+
+/// Sorts a history so that every node comes before its parents. The nodes the graph doesn't
+/// order, e.g. the two sides of a merge, are sorted by key, which makes the order stable.
+///
+/// The history must be acyclic, as returned by `get_ancestors`.
+pub fn sort_ancestors_topo(mut ancestors: Ancestors) -> Vec<(Key, NodeInfo)> {
+    // Number of children of each node within the history.
+    let mut children = HashMap::<&Key, usize>::with_capacity(ancestors.len());
+    for info in ancestors.values() {
+        for parent in unique_parents(info) {
+            if ancestors.contains_key(parent) {
+                *children.entry(parent).or_default() += 1;
+            }
+        }
+    }
+
+    let mut ready: BTreeSet<&Key> = ancestors
+        .keys()
+        .filter(|key| !children.contains_key(key))
+        .collect();
+    let mut sorted = Vec::with_capacity(ancestors.len());
+    while let Some(key) = ready.iter().next().cloned() {
+        ready.remove(key);
+        sorted.push(key.clone());
+        for parent in unique_parents(&ancestors[key]) {
+            if let Some(count) = children.get_mut(parent) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(parent);
+                }
+            }
+        }
+    }
+
+    sorted
+        .into_iter()
+        .filter_map(|key| ancestors.remove_entry(&key))
+        .collect()
+}
+
+/// The parents of a node, without the null ones and without repeating a parent.
+fn unique_parents(info: &NodeInfo) -> impl Iterator<Item = &Key> {
+    let [p1, p2] = &info.parents;
+    let p2 = if p2 == p1 { None } else { Some(p2) };
+    std::iter::once(p1)
+        .chain(p2)
+        .filter(|parent| !parent.hgid.is_null())
+}
+
+/// Lazily walks the history graph of a key in breadth-first order, starting with the key itself.
+///
+/// Null parents terminate a branch, and every ancestor is yielded exactly once. The walk can be
+/// bounded with `with_max_depth`. The traversal stops after the first error, which is returned
+/// when a node of the graph can't be found in the store, or as a `HistoryCycle` when a node turns
+/// out to be its own ancestor.
+///
+/// Cycles are looked for whenever a node is reached a second time, which otherwise happens on
+/// merges, by walking the parent edges seen so far. Linear history never pays for that check.
+pub struct AncestorIterator<'a, S: ?Sized> {
+    store: &'a S,
+    queue: VecDeque<(Key, usize)>,
+    seen: HashSet<Key>,
+    /// The non-null parents of the visited nodes.
+    edges: HashMap<Key, Vec<Key>>,
+    max_depth: Option<usize>,
+    stop: HashSet<Key>,
+    truncated: bool,
+    failed: bool,
+}
+
+/// The ancestors of a key found within a bounded distance from it.
+#[derive(Debug, Default)]
+pub struct LimitedAncestors {
+    pub ancestors: Ancestors,
+    /// Whether some ancestors were left out because they were further than the depth limit.
+    pub truncated: bool,
+}
+
+impl<'a, S: HgIdHistoryStore + ?Sized> AncestorIterator<'a, S> {
+    pub fn new(store: &'a S, key: Key) -> Self {
+        let mut seen = HashSet::new();
+        seen.insert(key.clone());
+        AncestorIterator {
+            store,
+            queue: VecDeque::from(vec![(key, 0)]),
+            seen,
+            edges: HashMap::new(),
+            max_depth: None,
+            stop: HashSet::new(),
+            truncated: false,
+            failed: false,
+        }
+    }
+
+    /// Only visit the ancestors that are at most `max_depth` parent edges away from the starting
+    /// key. The depth of a node is the length of the shortest path to it, so both parents of a
+    /// merge are one level deeper than the merge itself.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Don't visit the nodes of `stop`, nor the ancestors that are only reachable through them.
+    /// The starting key is skipped too if it's part of `stop`.
+    pub fn with_stop(mut self, stop: HashSet<Key>) -> Self {
+        self.queue.retain(|(key, _)| !stop.contains(key));
+        self.stop = stop;
+        self
+    }
+
+    /// Whether the depth limit prevented some ancestors from being visited. Only meaningful once
+    /// the iterator has been exhausted.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn visit(&mut self, key: Key, depth: usize) -> Result<(Key, NodeInfo)> {
+        let info = self
+            .store
+            .get_node_info(&key)?
+            .ok_or_else(|| format_err!("{:?} not found in store", key))?;
+
+        let parents = info
+            .parents
+            .iter()
+            .filter(|parent| !parent.hgid.is_null())
+            .cloned()
+            .collect::<Vec<_>>();
+        self.edges.insert(key.clone(), parents.clone());
+
+        for parent in parents {
+            if self.stop.contains(&parent) {
+                continue;
+            }
+
+            if self.seen.contains(&parent) {
+                if self.reaches(&parent, &key) {
+                    return Err(HistoryCycle(parent).into());
+                }
+                continue;
+            }
+
+            if self.max_depth.map_or(false, |max_depth| depth >= max_depth) {
+                self.truncated = true;
+            } else {
+                self.seen.insert(parent.clone());
+                self.queue.push_back((parent, depth + 1));
+            }
+        }
+
+        Ok((key, info))
+    }
+
+    /// Whether `to` is `from` or one of its ancestors, only following the edges visited so far.
+    fn reaches(&self, from: &Key, to: &Key) -> bool {
+        let mut stack = vec![from];
+        let mut visited = HashSet::new();
+        while let Some(current) = stack.pop() {
+            if current == to {
+                return true;
+            }
+            if visited.insert(current) {
+                if let Some(parents) = self.edges.get(current) {
+                    stack.extend(parents.iter());
+                }
+            }
+        }
+        false
+    }
+}
+
+impl<'a, S: HgIdHistoryStore + ?Sized> Iterator for AncestorIterator<'a, S> {
+    type Item = Result<(Key, NodeInfo)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let (key, depth) = self.queue.pop_front()?;
+        let res = self.visit(key, depth);
+        self.failed = res.is_err();
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use quickcheck::quickcheck;
+    use types::testutil::*;
+    use types::HgId;
+
+    use super::*;
+    use crate::historystore::HgIdMutableHistoryStore;
+    use crate::memoryhistorystore::MemoryHgIdHistoryStore;
+
+    /// Builds the following graph, where `4` is a merge of `2` and `3`:
+    ///
+    ///   1 <- 2 <- 4
+    ///   1 <- 3 <-/
+    fn merge_graph() -> Result<MemoryHgIdHistoryStore> {
+        let store = MemoryHgIdHistoryStore::new();
+        let info = |p1: Key, p2: Key| NodeInfo {
+            parents: [p1, p2],
+            linknode: hgid("9"),
+        };
+
+        store.add(&key("f", "1"), &info(null_key("f"), null_key("f")))?;
+        store.add(&key("f", "2"), &info(key("f", "1"), null_key("f")))?;
+        store.add(&key("f", "3"), &info(key("f", "1"), null_key("f")))?;
+        store.add(&key("f", "4"), &info(key("f", "2"), key("f", "3")))?;
+        Ok(store)
+    }
+
+    #[test]
+    fn test_bfs_order() -> Result<()> {
+        let store = merge_graph()?;
+
+        let keys = store
+            .get_ancestors_iter(&key("f", "4"))
+            .map(|res| res.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            keys,
+            vec![key("f", "4"), key("f", "2"), key("f", "3"), key("f", "1")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ancestors_topo() -> Result<()> {
+        let store = merge_graph()?;
+
+        let sorted = store.get_ancestors_topo(&key("f", "4"))?;
+        let keys = sorted.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>();
+        // The sides of the merge are ordered by key.
+        assert_eq!(
+            keys,
+            vec![key("f", "4"), key("f", "2"), key("f", "3"), key("f", "1")]
+        );
+        for (position, (key, info)) in sorted.iter().enumerate() {
+            assert_eq!(store.get_node_info(key)?.as_ref(), Some(info));
+            for parent in info.parents.iter().filter(|p| !p.hgid.is_null()) {
+                let parent_position = keys.iter().position(|k| k == parent).unwrap();
+                assert!(parent_position > position, "{:?} before its child", parent);
+            }
+        }
+
+        // Adding the history in another order gives the same result.
+        let ancestors = store.get_ancestors(&key("f", "4"))?;
+        let mut reversed = Ancestors::new();
+        for (key, info) in sorted.iter().rev() {
+            reversed.insert(key.clone(), info.clone());
+        }
+        assert_eq!(
+            sort_ancestors_topo(reversed),
+            sort_ancestors_topo(ancestors)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_early_stop() -> Result<()> {
+        let store = merge_graph()?;
+
+        let first = store.get_ancestors_iter(&key("f", "4")).next().unwrap()?;
+        assert_eq!(first.0, key("f", "4"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ancestors() -> Result<()> {
+        let store = merge_graph()?;
+
+        let ancestors = store.get_ancestors(&key("f", "2"))?;
+        assert_eq!(ancestors.len(), 2);
+        assert_eq!(
+            ancestors.get(&key("f", "2")),
+            store.get_node_info(&key("f", "2"))?.as_ref()
+        );
+        assert!(ancestors.contains_key(&key("f", "1")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ancestors_with_limit() -> Result<()> {
+        let store = merge_graph()?;
+
+        let limited = store.get_ancestors_with_limit(&key("f", "4"), 0)?;
+        assert_eq!(limited.ancestors.len(), 1);
+        assert!(limited.truncated);
+
+        // Both parents of the merge are at depth 1.
+        let limited = store.get_ancestors_with_limit(&key("f", "4"), 1)?;
+        assert_eq!(limited.ancestors.len(), 3);
+        assert!(limited.ancestors.contains_key(&key("f", "2")));
+        assert!(limited.ancestors.contains_key(&key("f", "3")));
+        assert!(limited.truncated);
+
+        // The root is reached through both branches, and its null parents don't count.
+        let limited = store.get_ancestors_with_limit(&key("f", "4"), 2)?;
+        assert_eq!(limited.ancestors.len(), 4);
+        assert!(!limited.truncated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ancestors_excluding() -> Result<()> {
+        let store = merge_graph()?;
+
+        // Pruning one side of the merge still reaches the root through the other one.
+        let stop = HashSet::from([key("f", "2")]);
+        let ancestors = store.get_ancestors_excluding(&key("f", "4"), &stop)?;
+        let mut keys = ancestors.into_keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec![key("f", "1"), key("f", "3"), key("f", "4")]);
+
+        let stop = HashSet::from([key("f", "1")]);
+        let ancestors = store.get_ancestors_excluding(&key("f", "4"), &stop)?;
+        assert_eq!(ancestors.len(), 3);
+        assert!(!ancestors.contains_key(&key("f", "1")));
+
+        let stop = HashSet::from([key("f", "2"), key("f", "3")]);
+        let ancestors = store.get_ancestors_excluding(&key("f", "4"), &stop)?;
+        assert_eq!(
+            ancestors.into_keys().collect::<Vec<_>>(),
+            vec![key("f", "4")]
+        );
+
+        let stop = HashSet::from([key("f", "4")]);
+        assert!(
+            store
+                .get_ancestors_excluding(&key("f", "4"), &stop)?
+                .is_empty()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_null_p1() -> Result<()> {
+        // A node whose only real parent is the second one is still walked through.
+        let store = MemoryHgIdHistoryStore::new();
+        store.add(
+            &key("f", "1"),
+            &NodeInfo::new_root(repo_path_buf("f"), hgid("9")),
+        )?;
+        store.add(
+            &key("f", "2"),
+            &NodeInfo {
+                parents: [null_key("f"), key("f", "1")],
+                linknode: hgid("9"),
+            },
+        )?;
+
+        let ancestors = store.get_ancestors(&key("f", "2"))?;
+        assert_eq!(ancestors.len(), 2);
+        assert!(ancestors.contains_key(&key("f", "1")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cycle() -> Result<()> {
+        let store = MemoryHgIdHistoryStore::new();
+        let info = |parent: Key| NodeInfo {
+            parents: [parent, null_key("f")],
+            linknode: hgid("9"),
+        };
+        store.add(&key("f", "1"), &info(key("f", "2")))?;
+        store.add(&key("f", "2"), &info(key("f", "1")))?;
+
+        let err = store.get_ancestors(&key("f", "2")).unwrap_err();
+        let cycle = err
+            .downcast_ref::<HistoryCycle>()
+            .expect("not a HistoryCycle");
+        assert_eq!(cycle.0, key("f", "2"));
+
+        // Reaching a node twice through a merge isn't a cycle.
+        let store = merge_graph()?;
+        assert_eq!(store.get_ancestors(&key("f", "4"))?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_parent() -> Result<()> {
+        let store = MemoryHgIdHistoryStore::new();
+        store.add(
+            &key("f", "2"),
+            &NodeInfo {
+                parents: [key("f", "1"), null_key("f")],
+                linknode: hgid("9"),
+            },
+        )?;
+
+        let mut iter = store.get_ancestors_iter(&key("f", "2"));
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+        assert!(store.get_ancestors(&key("f", "2")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_ancestors() -> Result<()> {
+        let store = merge_graph()?;
+        let left = store.get_ancestors(&key("f", "2"))?;
+        let right = store.get_ancestors(&key("f", "3"))?;
+
+        let merged = merge_ancestors(left.clone(), right)?;
+        let mut keys = merged.into_keys().collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(keys, vec![key("f", "1"), key("f", "2"), key("f", "3")]);
+
+        let mut conflicting = Ancestors::new();
+        conflicting.insert(
+            key("f", "2"),
+            NodeInfo {
+                parents: [null_key("f"), null_key("f")],
+                linknode: hgid("9"),
+            },
+        );
+        let err = merge_ancestors(left, conflicting).unwrap_err();
+        let conflict = err
+            .downcast_ref::<ConflictingNodeInfo>()
+            .expect("not a ConflictingNodeInfo");
+        assert_eq!(conflict.key, key("f", "2"));
+        Ok(())
+    }
+
+    quickcheck! {
+        fn test_merge_ancestors_with_itself(entries: Vec<(Key, Key, Key, HgId)>) -> bool {
+            let ancestors = entries
+                .into_iter()
+                .map(|(key, p1, p2, linknode)| {
+                    (key, NodeInfo {
+                        parents: [p1, p2],
+                        linknode,
+                    })
+                })
+                .collect::<Ancestors>();
+            merge_ancestors(ancestors.clone(), ancestors.clone()).ok() == Some(ancestors)
+        }
+    }
+}
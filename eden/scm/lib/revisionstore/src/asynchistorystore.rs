@@ -0,0 +1,170 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! Async flavour of `HgIdHistoryStore`, along with the adapters converting between the two.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_runtime::block_on;
+use async_runtime::spawn_blocking;
+use async_trait::async_trait;
+use types::Key;
+use types::NodeInfo;
+
+use crate::ancestors::Ancestors;
+use crate::historystore::HgIdHistoryStore;
+use crate::localstore::LocalStore;
+use crate::types::StoreKey;
+
+#[async_trait]
+pub trait AsyncHgIdHistoryStore: Send + Sync {
+    async fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>>;
+    async fn get_ancestors(&self, key: &Key) -> Result<Ancestors>;
+    async fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>>;
+    async fn refresh(&self) -> Result<()>;
+}
+
+/// Exposes a `HgIdHistoryStore` as an `AsyncHgIdHistoryStore`. Every call runs on the blocking
+/// thread pool of the async runtime, so that the store can't stall the executor.
+pub struct AsyncHistoryStoreAdapter<T: ?Sized> {
+    store: Arc<T>,
+}
+
+impl<T: HgIdHistoryStore + ?Sized + 'static> AsyncHistoryStoreAdapter<T> {
+    pub fn new(store: Arc<T>) -> Self {
+        Self { store }
+    }
+
+    async fn run<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(Arc<T>) -> Result<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let store = self.store.clone();
+        spawn_blocking(move || f(store)).await?
+    }
+}
+
+#[async_trait]
+impl<T: HgIdHistoryStore + ?Sized + 'static> AsyncHgIdHistoryStore for AsyncHistoryStoreAdapter<T> {
+    async fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+        let key = key.clone();
+        self.run(move |store| store.get_node_info(&key)).await
+    }
+
+    async fn get_ancestors(&self, key: &Key) -> Result<Ancestors> {
+        let key = key.clone();
+        self.run(move |store| store.get_ancestors(&key)).await
+    }
+
+    async fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        let keys = keys.to_vec();
+        self.run(move |store| store.get_missing(&keys)).await
+    }
+
+    async fn refresh(&self) -> Result<()> {
+        self.run(|store| store.refresh()).await
+    }
+}
+
+/// Exposes an `AsyncHgIdHistoryStore` as a `HgIdHistoryStore`, blocking on the async runtime.
+///
+/// This must not be used from within an async context, as blocking on the runtime from one of its
+/// own threads panics.
+pub struct SyncHistoryStoreAdapter<T> {
+    store: T,
+}
+
+impl<T: AsyncHgIdHistoryStore> SyncHistoryStoreAdapter<T> {
+    pub fn new(store: T) -> Self {
+        Self { store }
+    }
+}
+
+impl<T: AsyncHgIdHistoryStore> HgIdHistoryStore for SyncHistoryStoreAdapter<T> {
+    fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+        block_on(self.store.get_node_info(key))
+    }
+
+    fn refresh(&self) -> Result<()> {
+        block_on(self.store.refresh())
+    }
+}
+
+impl<T: AsyncHgIdHistoryStore> LocalStore for SyncHistoryStoreAdapter<T> {
+    fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        block_on(self.store.get_missing(keys))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use types::testutil::*;
+
+    use super::*;
+    use crate::historystore::HgIdMutableHistoryStore;
+    use crate::memoryhistorystore::MemoryHgIdHistoryStore;
+
+    fn store() -> Result<Arc<MemoryHgIdHistoryStore>> {
+        let store = MemoryHgIdHistoryStore::new();
+        store.add(
+            &key("a", "1"),
+            &NodeInfo {
+                parents: [null_key("a"), null_key("a")],
+                linknode: hgid("9"),
+            },
+        )?;
+        store.add(
+            &key("a", "2"),
+            &NodeInfo {
+                parents: [key("a", "1"), null_key("a")],
+                linknode: hgid("9"),
+            },
+        )?;
+        Ok(Arc::new(store))
+    }
+
+    #[test]
+    fn test_async_adapter() -> Result<()> {
+        let inner = store()?;
+        let store = AsyncHistoryStoreAdapter::new(inner.clone());
+
+        assert_eq!(
+            block_on(store.get_node_info(&key("a", "2")))?,
+            inner.get_node_info(&key("a", "2"))?
+        );
+        assert_eq!(
+            block_on(store.get_ancestors(&key("a", "2")))?,
+            inner.get_ancestors(&key("a", "2"))?
+        );
+
+        let keys = vec![StoreKey::hgid(key("a", "1")), StoreKey::hgid(key("a", "3"))];
+        assert_eq!(
+            block_on(store.get_missing(&keys))?,
+            vec![StoreKey::hgid(key("a", "3"))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let inner = store()?;
+        let store = SyncHistoryStoreAdapter::new(AsyncHistoryStoreAdapter::new(inner.clone()));
+
+        assert_eq!(
+            store.get_node_info(&key("a", "1"))?,
+            inner.get_node_info(&key("a", "1"))?
+        );
+        assert_eq!(
+            store.get_ancestors(&key("a", "2"))?,
+            inner.get_ancestors(&key("a", "2"))?
+        );
+        assert_eq!(store.get_node_info(&key("a", "3"))?, None);
+        Ok(())
+    }
+}
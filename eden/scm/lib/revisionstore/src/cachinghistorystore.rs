@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! History store wrapper keeping the most recently used `NodeInfo`s in memory, for workloads such
+//! as annotate that keep asking for the same keys.
+
+use anyhow::Result;
+use lru_cache::LruCache;
+use parking_lot::Mutex;
+use types::Key;
+use types::NodeInfo;
+
+use crate::historystore::HgIdHistoryStore;
+use crate::localstore::LocalStore;
+use crate::types::StoreKey;
+
+pub struct CachingHgIdHistoryStore<S> {
+    inner: S,
+    cache: Mutex<LruCache<Key, NodeInfo>>,
+}
+
+impl<S: HgIdHistoryStore> CachingHgIdHistoryStore<S> {
+    /// Wrap `inner`, keeping at most `capacity` entries in memory. Only successful lookups are
+    /// cached, since a missing key may be added to the inner store later on.
+    pub fn new(inner: S, capacity: usize) -> Self {
+        Self {
+            inner,
+            cache: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: HgIdHistoryStore> HgIdHistoryStore for CachingHgIdHistoryStore<S> {
+    fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+        if let Some(info) = self.cache.lock().get_mut(key) {
+            return Ok(Some(info.clone()));
+        }
+
+        let info = self.inner.get_node_info(key)?;
+        if let Some(info) = &info {
+            self.cache.lock().insert(key.clone(), info.clone());
+        }
+        Ok(info)
+    }
+
+    fn refresh(&self) -> Result<()> {
+        self.inner.refresh()
+    }
+
+    fn get_node_info_batch(&self, keys: &[Key]) -> Result<Vec<Option<NodeInfo>>> {
+        let mut results = {
+            let mut cache = self.cache.lock();
+            keys.iter()
+                .map(|key| cache.get_mut(key).cloned())
+                .collect::<Vec<_>>()
+        };
+
+        let missing = results
+            .iter()
+            .enumerate()
+            .filter_map(|(i, info)| if info.is_none() { Some(i) } else { None })
+            .collect::<Vec<_>>();
+        if missing.is_empty() {
+            return Ok(results);
+        }
+
+        let missing_keys = missing.iter().map(|i| keys[*i].clone()).collect::<Vec<_>>();
+        let fetched = self.inner.get_node_info_batch(&missing_keys)?;
+
+        let mut cache = self.cache.lock();
+        for (i, info) in missing.into_iter().zip(fetched) {
+            if let Some(info) = &info {
+                cache.insert(keys[i].clone(), info.clone());
+            }
+            results[i] = info;
+        }
+        Ok(results)
+    }
+}
+
+impl<S: HgIdHistoryStore> LocalStore for CachingHgIdHistoryStore<S> {
+    fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        let unknown = {
+            let mut cache = self.cache.lock();
+            keys.iter()
+                .filter(|k| match k {
+                    StoreKey::HgId(key) => !cache.contains_key(key),
+                    StoreKey::Content(_, _) => true,
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        if unknown.is_empty() {
+            return Ok(unknown);
+        }
+        self.inner.get_missing(&unknown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use types::testutil::*;
+
+    use super::*;
+    use crate::historystore::HgIdMutableHistoryStore;
+    use crate::memoryhistorystore::MemoryHgIdHistoryStore;
+
+    #[derive(Default)]
+    struct CountingHistoryStore {
+        store: MemoryHgIdHistoryStore,
+        get_node_info: AtomicUsize,
+        get_missing: AtomicUsize,
+    }
+
+    impl HgIdHistoryStore for CountingHistoryStore {
+        fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+            self.get_node_info.fetch_add(1, Ordering::Relaxed);
+            self.store.get_node_info(key)
+        }
+
+        fn refresh(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LocalStore for CountingHistoryStore {
+        fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+            self.get_missing.fetch_add(1, Ordering::Relaxed);
+            self.store.get_missing(keys)
+        }
+    }
+
+    fn nodeinfo() -> NodeInfo {
+        NodeInfo {
+            parents: [key("a", "1"), null_key("a")],
+            linknode: hgid("9"),
+        }
+    }
+
+    #[test]
+    fn test_cache_hit() -> Result<()> {
+        let inner = CountingHistoryStore::default();
+        inner.store.add(&key("a", "2"), &nodeinfo())?;
+        let store = CachingHgIdHistoryStore::new(inner, 10);
+
+        assert_eq!(store.get_node_info(&key("a", "2"))?, Some(nodeinfo()));
+        assert_eq!(store.get_node_info(&key("a", "2"))?, Some(nodeinfo()));
+        assert_eq!(store.inner().get_node_info.load(Ordering::Relaxed), 1);
+
+        // Missing keys aren't cached.
+        assert_eq!(store.get_node_info(&key("a", "3"))?, None);
+        assert_eq!(store.get_node_info(&key("a", "3"))?, None);
+        assert_eq!(store.inner().get_node_info.load(Ordering::Relaxed), 3);
+
+        // Cached keys are known to be present.
+        let missing = store.get_missing(&[StoreKey::hgid(key("a", "2"))])?;
+        assert!(missing.is_empty());
+        assert_eq!(store.inner().get_missing.load(Ordering::Relaxed), 0);
+
+        let missing =
+            store.get_missing(&[StoreKey::hgid(key("a", "2")), StoreKey::hgid(key("a", "3"))])?;
+        assert_eq!(missing, vec![StoreKey::hgid(key("a", "3"))]);
+        assert_eq!(store.inner().get_missing.load(Ordering::Relaxed), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_eviction() -> Result<()> {
+        let inner = CountingHistoryStore::default();
+        inner.store.add(&key("a", "2"), &nodeinfo())?;
+        inner.store.add(&key("a", "3"), &nodeinfo())?;
+        let store = CachingHgIdHistoryStore::new(inner, 1);
+
+        store.get_node_info(&key("a", "2"))?;
+        store.get_node_info(&key("a", "3"))?;
+        store.get_node_info(&key("a", "2"))?;
+        assert_eq!(store.inner().get_node_info.load(Ordering::Relaxed), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch() -> Result<()> {
+        let inner = CountingHistoryStore::default();
+        inner.store.add(&key("a", "2"), &nodeinfo())?;
+        inner.store.add(&key("a", "3"), &nodeinfo())?;
+        let store = CachingHgIdHistoryStore::new(inner, 10);
+
+        store.get_node_info(&key("a", "2"))?;
+        let keys = vec![key("a", "2"), key("a", "3"), key("a", "4")];
+        assert_eq!(
+            store.get_node_info_batch(&keys)?,
+            vec![Some(nodeinfo()), Some(nodeinfo()), None]
+        );
+        // Only the keys that weren't cached were forwarded.
+        assert_eq!(store.inner().get_node_info.load(Ordering::Relaxed), 3);
+        Ok(())
+    }
+}
@@ -77,6 +77,12 @@ impl HgIdHistoryStore for EdenApiHistoryStore {
     fn refresh(&self) -> Result<()> {
         Ok(())
     }
+
+    fn get_node_info_batch(&self, keys: &[Key]) -> Result<Vec<Option<NodeInfo>>> {
+        let store_keys: Vec<_> = keys.iter().cloned().map(StoreKey::hgid).collect();
+        self.prefetch(&store_keys)?;
+        self.store.get_node_info_batch(keys)
+    }
 }
 
 impl LocalStore for EdenApiHistoryStore {
@@ -14,12 +14,39 @@ use http::status::StatusCode;
 use http_client::HttpClientError;
 use http_client::Method;
 use thiserror::Error;
+use types::Key;
+use types::NodeInfo;
 use url::Url;
 
 #[derive(Debug, Error)]
 #[error("Empty Mutable Pack")]
 pub struct EmptyMutablePack;
 
+/// A key was found to be its own ancestor, which can only happen with corrupted history data.
+#[derive(Debug, Error)]
+#[error("History cycle detected at {0}")]
+pub struct HistoryCycle(pub Key);
+
+/// A key has different `NodeInfo`s in two histories of it, which can only happen with corrupted
+/// history data.
+#[derive(Debug, Error)]
+#[error("Conflicting history for {key}: {first:?} and {second:?}")]
+pub struct ConflictingNodeInfo {
+    pub key: Key,
+    pub first: NodeInfo,
+    pub second: NodeInfo,
+}
+
+/// The key isn't present in the store.
+#[derive(Debug, Error)]
+#[error("Key {0} not found")]
+pub struct KeyNotFound(pub Key);
+
+/// The store can't enumerate its keys, e.g. because it's backed by a remote service.
+#[derive(Debug, Error)]
+#[error("Key enumeration is not supported by this store")]
+pub struct IterKeysUnsupported;
+
 #[derive(Error, Debug)]
 #[error("Fetch failed: {} {}", .url, .method)]
 pub struct FetchError {
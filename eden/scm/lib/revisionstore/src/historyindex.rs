@@ -9,8 +9,12 @@ use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fs::File;
+use std::io;
+use std::io::BufWriter;
 use std::io::Cursor;
 use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::io::Write;
 use std::path::Path;
 
@@ -298,6 +302,17 @@ impl HistoryIndex {
         writer: &mut T,
         nodes: &HashMap<&RepoPath, HashMap<Key, NodeLocation>>,
         file_name: &RepoPath,
+    ) -> Result<()> {
+        let file_nodes = nodes.get(file_name).ok_or_else(|| {
+            HistoryIndexError(format!("unabled to find nodes for {:?}", file_name))
+        })?;
+        <HistoryIndex>::write_file_nodes(writer, file_name, file_nodes)
+    }
+
+    fn write_file_nodes<T: Write>(
+        writer: &mut T,
+        file_name: &RepoPath,
+        file_nodes: &HashMap<Key, NodeLocation>,
     ) -> Result<()> {
         // Write the filename
         let file_name_slice = file_name.as_byte_slice();
@@ -305,9 +320,6 @@ impl HistoryIndex {
         writer.write_all(file_name_slice)?;
 
         // Write each hgid, in sorted order so the can be bisected
-        let file_nodes = nodes.get(file_name).ok_or_else(|| {
-            HistoryIndexError(format!("unabled to find nodes for {:?}", file_name))
-        })?;
         let mut file_nodes: Vec<(&Key, &NodeLocation)> =
             file_nodes.iter().collect::<Vec<(&Key, &NodeLocation)>>();
         file_nodes.sort_by_key(|x| x.0.hgid);
@@ -409,6 +421,125 @@ impl HistoryIndex {
     }
 }
 
+/// Writes a history index one file at a time, as the file sections of a pack are written, so
+/// that only a fixed-size summary of each file is held in memory. The node entries of the files
+/// come after all the file entries in the index, so they are staged in a temporary file until
+/// the last file is added.
+pub(crate) struct HistoryIndexWriter {
+    staged_nodes: BufWriter<File>,
+    staged_len: u64,
+    files: Vec<StagedFile>,
+}
+
+struct StagedFile {
+    file_hash: HgId,
+    section_location: FileSectionLocation,
+    node_count: usize,
+    /// Where the file name and the node entries of the file are in the staging file.
+    staged_offset: u64,
+    staged_size: u64,
+}
+
+impl HistoryIndexWriter {
+    /// Create a writer staging the node entries in a temporary file in `dir`.
+    pub fn new(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            staged_nodes: BufWriter::new(tempfile::tempfile_in(dir)?),
+            staged_len: 0,
+            files: Vec::new(),
+        })
+    }
+
+    /// Add the file `file_name`, whose section is at `section_location` in the pack, with the
+    /// location of its nodes. Each file must be added once.
+    pub fn add_file(
+        &mut self,
+        file_name: &RepoPath,
+        section_location: FileSectionLocation,
+        file_nodes: &HashMap<Key, NodeLocation>,
+    ) -> Result<()> {
+        let mut buf = Vec::with_capacity(
+            2 + file_name.as_byte_slice().len() + file_nodes.len() * NODE_ENTRY_LEN,
+        );
+        <HistoryIndex>::write_file_nodes(&mut buf, file_name, file_nodes)?;
+        self.staged_nodes.write_all(&buf)?;
+
+        self.files.push(StagedFile {
+            file_hash: sha1(file_name.as_byte_slice()),
+            section_location,
+            node_count: file_nodes.len(),
+            staged_offset: self.staged_len,
+            staged_size: buf.len() as u64,
+        });
+        self.staged_len += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Write the index of the added files, identical to the one `HistoryIndex::write` writes for
+    /// the same files.
+    pub fn finish<T: Write>(self, writer: &mut T) -> Result<()> {
+        let Self {
+            staged_nodes,
+            mut files,
+            ..
+        } = self;
+        let mut staged_nodes = staged_nodes.into_inner()?;
+
+        // Write header
+        let options = HistoryIndexOptions {
+            version: HistoryPackVersion::One,
+            large: files.len() > SMALL_FANOUT_CUTOFF,
+        };
+        options.write(writer)?;
+
+        // They must be written in sorted order so they can be bisected.
+        files.sort_by_key(|file| file.file_hash);
+
+        // Write the fanout table
+        FanoutTable::write(
+            writer,
+            if options.large { 2 } else { 1 },
+            &mut files.iter().map(|file| &file.file_hash),
+            FILE_ENTRY_LEN,
+            None,
+        )?;
+
+        // Write out the number of files in the file portion.
+        writer.write_u64::<BigEndian>(files.len() as u64)?;
+
+        // Write out the file section entries, the first hgid index starting after the header,
+        // fanout, file count, file section, and hgid count.
+        let mut hgid_offset: usize =
+            2 + FanoutTable::get_size(options.large) + 8 + (files.len() * FILE_ENTRY_LEN) + 8;
+        let mut hgid_count = 0;
+        for file in files.iter() {
+            let hgid_section_size = file.node_count * NODE_ENTRY_LEN;
+            FileIndexEntry {
+                hgid: file.file_hash,
+                file_section_offset: file.section_location.offset,
+                file_section_size: file.section_location.size,
+                hgid_index_offset: hgid_offset as u32,
+                hgid_index_size: hgid_section_size as u32,
+            }
+            .write(writer)?;
+
+            hgid_offset += file.staged_size as usize;
+            hgid_count += file.node_count;
+        }
+
+        // Write the total number of nodes
+        writer.write_u64::<BigEndian>(hgid_count as u64)?;
+
+        // For each file, copy its staged hgid index
+        for file in files.iter() {
+            staged_nodes.seek(SeekFrom::Start(file.staged_offset))?;
+            io::copy(&mut (&staged_nodes).take(file.staged_size), writer)?;
+        }
+
+        Ok(())
+    }
+}
+
 fn sha1(value: &[u8]) -> HgId {
     let mut hasher = Sha1::new();
     hasher.update(value);
@@ -518,6 +649,37 @@ mod tests {
 
             true
         }
+
+        fn test_index_writer(data: Vec<(RepoPathBuf, (FileSectionLocation, HashMap<Key, NodeLocation>))>) -> bool {
+            let mut file_sections: Vec<(&RepoPath, FileSectionLocation)> = vec![];
+            let mut file_nodes: HashMap<&RepoPath, HashMap<Key, NodeLocation>> = HashMap::new();
+            for &(ref path, (ref location, ref nodes)) in data.iter() {
+                if file_nodes.contains_key(path.as_repo_path()) {
+                    continue;
+                }
+                file_sections.push((path, location.clone()));
+                let hgid_map = nodes
+                    .iter()
+                    .map(|(key, location)| {
+                        (Key::new(path.clone(), key.hgid.clone()), location.clone())
+                    })
+                    .collect();
+                file_nodes.insert(path, hgid_map);
+            }
+
+            let mut expected = vec![];
+            HistoryIndex::write(&mut expected, &file_sections, &file_nodes).unwrap();
+
+            let tempdir = tempfile::tempdir().unwrap();
+            let mut writer = HistoryIndexWriter::new(tempdir.path()).unwrap();
+            for (path, location) in file_sections.iter() {
+                writer.add_file(path, location.clone(), &file_nodes[path]).unwrap();
+            }
+            let mut written = vec![];
+            writer.finish(&mut written).unwrap();
+
+            expected == written
+        }
     }
 
     // TODO: test write() when duplicate files and duplicate nodes passed
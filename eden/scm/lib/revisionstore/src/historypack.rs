@@ -353,6 +353,10 @@ impl HgIdHistoryStore for HistoryPack {
     fn refresh(&self) -> Result<()> {
         Ok(())
     }
+
+    fn iter_keys(&self) -> Result<Box<dyn Iterator<Item = Result<Key>> + '_>> {
+        Ok(Box::new(HistoryPackIterator::new(self)))
+    }
 }
 
 impl StoreFromPath for HistoryPack {
@@ -483,6 +487,7 @@ pub mod tests {
     use types::RepoPathBuf;
 
     use super::*;
+    use crate::error::KeyNotFound;
     use crate::historystore::HgIdMutableHistoryStore;
     use crate::mutablehistorypack::MutableHistoryPack;
 
@@ -595,6 +600,55 @@ pub mod tests {
         assert_eq!(iter_keys, keys,);
     }
 
+    #[test]
+    fn test_iter_keys() -> Result<()> {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let tempdir = TempDir::new()?;
+
+        let nodes = get_nodes(&mut rng);
+
+        let pack = make_historypack(&tempdir, &nodes);
+
+        let mut keys: Vec<Key> = nodes.keys().cloned().collect();
+        keys.sort_unstable();
+        let mut iter_keys = pack.iter_keys()?.collect::<Result<Vec<Key>>>()?;
+        iter_keys.sort_unstable();
+        assert_eq!(iter_keys, keys);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_linknodes() -> Result<()> {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let tempdir = TempDir::new()?;
+
+        let nodes = get_nodes(&mut rng);
+
+        let pack = make_historypack(&tempdir, &nodes);
+
+        let (keys, linknodes): (Vec<Key>, Vec<HgId>) = nodes
+            .iter()
+            .map(|(key, info)| (key.clone(), info.linknode))
+            .unzip();
+        for (key, linknode) in keys.iter().zip(&linknodes) {
+            assert_eq!(pack.get_linknode(key)?, *linknode);
+        }
+        assert_eq!(pack.get_linknodes(&keys)?, linknodes);
+
+        let missing_key = key("missing", "f0f0f0");
+        let err = pack.get_linknode(&missing_key).unwrap_err();
+        assert!(err.downcast_ref::<KeyNotFound>().is_some());
+
+        let mut keys = keys;
+        keys.push(missing_key.clone());
+        let err = pack.get_linknodes(&keys).unwrap_err();
+        match err.downcast_ref::<KeyNotFound>() {
+            Some(KeyNotFound(key)) => assert_eq!(key, &missing_key),
+            None => panic!("unexpected error: {:?}", err),
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_open_v0() {
         let mut rng = ChaChaRng::from_seed([0u8; 32]);
@@ -0,0 +1,576 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! A history pack writer for histories too large to be held in memory.
+//!
+//! `MutableHistoryPack` keeps every added node in memory until it is flushed. The
+//! `HistoryPackWriter` instead spills the nodes to temporary files once too many of them are
+//! buffered, each spilled run being sorted by path and indexed by the offset of the section of
+//! each path, so that looking up a node only reads the section of its path. When flushed, the
+//! runs are merged back one path at a time, and the index entries of each path are written out
+//! once its section is, so only the history of a single file, and a fixed-size summary of each
+//! file already written, are held in memory. The resulting pack is identical, byte for byte, to
+//! the one a `MutableHistoryPack` would write for the same nodes.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::iter::Peekable;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use byteorder::WriteBytesExt;
+use parking_lot::Mutex;
+use sha1::Digest;
+use sha1::Sha1;
+use tempfile::NamedTempFile;
+use thiserror::Error;
+use types::Key;
+use types::NodeInfo;
+use types::RepoPathBuf;
+
+use crate::error::EmptyMutablePack;
+use crate::historyindex::FileSectionLocation;
+use crate::historyindex::HistoryIndexWriter;
+use crate::historypack::HistoryPackVersion;
+use crate::historystore::HgIdHistoryStore;
+use crate::historystore::HgIdMutableHistoryStore;
+use crate::localstore::LocalStore;
+use crate::mutablehistorypack::write_section;
+use crate::mutablepack::MutablePack;
+use crate::packwriter::PackWriter;
+use crate::types::StoreKey;
+
+#[derive(Debug, Error)]
+#[error("History Pack Writer Error: {0:?}")]
+struct HistoryPackWriterError(String);
+
+/// The nodes of a file, as spilled to disk.
+type Section = (RepoPathBuf, Vec<(Key, NodeInfo)>);
+
+/// Nodes spilled to a temporary file, as a sequence of sections sorted by path.
+struct Run {
+    file: File,
+    /// The path of each section, with its offset in the file.
+    sections: Vec<(RepoPathBuf, u64)>,
+}
+
+impl Run {
+    fn write(dir: &Path, sections: impl Iterator<Item = Section>) -> Result<Self> {
+        let file = tempfile::tempfile_in(dir)?;
+        let mut writer = BufWriter::new(&file);
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        for section in sections {
+            let buf = bincode::serialize(&section)?;
+            writer.write_all(&buf)?;
+            offsets.push((section.0, offset));
+            offset += buf.len() as u64;
+        }
+        writer.flush()?;
+        drop(writer);
+
+        Ok(Self {
+            file,
+            sections: offsets,
+        })
+    }
+
+    fn read(&self) -> Result<impl Iterator<Item = Result<Section>> + '_> {
+        (&self.file).seek(SeekFrom::Start(0))?;
+        let mut reader = BufReader::new(&self.file);
+        Ok((0..self.sections.len())
+            .map(move |_| Ok(bincode::deserialize_from::<_, Section>(&mut reader)?)))
+    }
+
+    fn get(&self, key: &Key) -> Result<Option<NodeInfo>> {
+        let offset = match self
+            .sections
+            .binary_search_by(|(file_name, _)| file_name.cmp(&key.path))
+        {
+            Ok(index) => self.sections[index].1,
+            Err(_) => return Ok(None),
+        };
+        (&self.file).seek(SeekFrom::Start(offset))?;
+        let (_, entries) = bincode::deserialize_from::<_, Section>(BufReader::new(&self.file))?;
+        Ok(entries
+            .into_iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, info)| info))
+    }
+}
+
+struct HistoryPackWriterInner {
+    version: HistoryPackVersion,
+    dir: PathBuf,
+    max_buffered: usize,
+    buffered: usize,
+    mem_index: HashMap<RepoPathBuf, HashMap<Key, NodeInfo>>,
+    /// The spilled nodes, oldest first.
+    runs: Vec<Run>,
+}
+
+pub struct HistoryPackWriter {
+    dir: PathBuf,
+    version: HistoryPackVersion,
+    max_buffered: usize,
+    inner: Mutex<Option<HistoryPackWriterInner>>,
+}
+
+impl HistoryPackWriterInner {
+    fn new(dir: &Path, version: HistoryPackVersion, max_buffered: usize) -> Result<Self> {
+        if !dir.is_dir() {
+            return Err(HistoryPackWriterError(format!(
+                "cannot create history pack writer in non-directory '{:?}'",
+                dir
+            ))
+            .into());
+        }
+
+        Ok(Self {
+            version,
+            dir: dir.to_path_buf(),
+            max_buffered,
+            buffered: 0,
+            mem_index: HashMap::new(),
+            runs: Vec::new(),
+        })
+    }
+
+    fn add(&mut self, key: &Key, info: &NodeInfo) -> Result<()> {
+        // Loops in the graph aren't allowed. Since this is a logic error in the code, let's
+        // assert.
+        assert_ne!(key.hgid, info.parents[0].hgid);
+        assert_ne!(key.hgid, info.parents[1].hgid);
+
+        let entries = self
+            .mem_index
+            .entry(key.path.clone())
+            .or_insert_with(HashMap::new);
+        if entries.insert(key.clone(), info.clone()).is_none() {
+            self.buffered += 1;
+        }
+
+        if self.buffered >= self.max_buffered {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Move the buffered nodes to a new run on disk.
+    fn spill(&mut self) -> Result<()> {
+        let run = Run::write(&self.dir, self.take_sections().into_iter())?;
+        self.runs.push(run);
+        Ok(())
+    }
+
+    /// Take the buffered nodes, sorted by path.
+    fn take_sections(&mut self) -> Vec<Section> {
+        let mut sections = self
+            .mem_index
+            .drain()
+            .map(|(file_name, entries)| (file_name, entries.into_iter().collect()))
+            .collect::<Vec<Section>>();
+        sections.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        self.buffered = 0;
+        sections
+    }
+
+    fn get(&self, key: &Key) -> Result<Option<NodeInfo>> {
+        if let Some(info) = self
+            .mem_index
+            .get(&key.path)
+            .and_then(|nodes| nodes.get(key))
+        {
+            return Ok(Some(info.clone()));
+        }
+
+        // The most recent runs overwrite the oldest ones.
+        for run in self.runs.iter().rev() {
+            if let Some(info) = run.get(key)? {
+                return Ok(Some(info));
+            }
+        }
+        Ok(None)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        let mut keys = self
+            .mem_index
+            .values()
+            .flat_map(|nodes| nodes.keys().cloned())
+            .collect::<Vec<_>>();
+        for run in self.runs.iter() {
+            for section in run.read()? {
+                let (_, entries) = section?;
+                keys.extend(entries.into_iter().map(|(key, _)| key));
+            }
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+impl HistoryPackWriter {
+    /// Create a writer for a history pack in `dir`, which holds at most `max_buffered` nodes in
+    /// memory before spilling them to disk.
+    pub fn new(dir: impl AsRef<Path>, version: HistoryPackVersion, max_buffered: usize) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            version,
+            max_buffered: max_buffered.max(1),
+            inner: Mutex::new(None),
+        }
+    }
+
+    fn get_pack<'a>(
+        &self,
+        inner: &'a mut Option<HistoryPackWriterInner>,
+    ) -> Result<&'a mut HistoryPackWriterInner> {
+        if inner.is_none() {
+            inner.replace(HistoryPackWriterInner::new(
+                &self.dir,
+                self.version.clone(),
+                self.max_buffered,
+            )?);
+        }
+        Ok(inner.as_mut().unwrap())
+    }
+}
+
+impl HgIdMutableHistoryStore for HistoryPackWriter {
+    fn add(&self, key: &Key, info: &NodeInfo) -> Result<()> {
+        info.validate()?;
+        let mut guard = self.inner.lock();
+        let pack = self.get_pack(&mut guard)?;
+        pack.add(key, info)
+    }
+
+    fn add_many(&self, entries: &[(Key, NodeInfo)]) -> Result<()> {
+        for (_key, info) in entries {
+            info.validate()?;
+        }
+        let mut guard = self.inner.lock();
+        let pack = self.get_pack(&mut guard)?;
+        for (key, info) in entries {
+            pack.add(key, info)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<Option<Vec<PathBuf>>> {
+        let mut guard = self.inner.lock();
+        let old_inner = (*guard).take();
+
+        if let Some(old_inner) = old_inner {
+            Ok(match old_inner.close_pack()? {
+                Some(pack) => Some(vec![pack]),
+                None => Some(vec![]),
+            })
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl MutablePack for HistoryPackWriterInner {
+    fn build_files(mut self) -> Result<(NamedTempFile, NamedTempFile, PathBuf)> {
+        if self.mem_index.is_empty() && self.runs.is_empty() {
+            return Err(EmptyMutablePack.into());
+        }
+
+        let mut data_file = PackWriter::new(NamedTempFile::new_in(&self.dir)?);
+        let mut hasher = Sha1::new();
+
+        // Write the header
+        let version_u8: u8 = self.version.clone().into();
+        data_file.write_u8(version_u8)?;
+        hasher.update(&[version_u8]);
+
+        // The index entries of each file are written out as soon as its section is.
+        let mut index = HistoryIndexWriter::new(&self.dir)?;
+
+        // Merge the runs with the nodes still buffered, which are the most recent ones.
+        let buffered = self.take_sections();
+        let mut sources = Vec::with_capacity(self.runs.len() + 1);
+        for run in self.runs.iter() {
+            let source: Box<dyn Iterator<Item = Result<Section>> + '_> = Box::new(run.read()?);
+            sources.push(source.peekable());
+        }
+        let source: Box<dyn Iterator<Item = Result<Section>> + '_> =
+            Box::new(buffered.into_iter().map(Ok));
+        sources.push(source.peekable());
+
+        // Write the historypack
+        let mut section_buf = Vec::new();
+        let mut section_offset = data_file.bytes_written();
+        // - In sorted order for deterministic hashes.
+        while let Some((file_name, hgid_map)) = next_section(&mut sources)? {
+            let hgid_locations = write_section(
+                &mut section_buf,
+                &file_name,
+                &hgid_map,
+                section_offset as usize,
+            )?;
+            hasher.update(&section_buf);
+            data_file.write_all(&section_buf)?;
+
+            let section_location = FileSectionLocation {
+                offset: section_offset,
+                size: section_buf.len() as u64,
+            };
+            index.add_file(&file_name, section_location, &hgid_locations)?;
+
+            section_offset += section_buf.len() as u64;
+            section_buf.clear();
+        }
+
+        let mut index_file = PackWriter::new(NamedTempFile::new_in(&self.dir)?);
+        index.finish(&mut index_file)?;
+
+        Ok((
+            data_file.into_inner()?,
+            index_file.into_inner()?,
+            self.dir.join(hex::encode(hasher.finalize())),
+        ))
+    }
+
+    fn extension(&self) -> &'static str {
+        "hist"
+    }
+}
+
+/// Take the nodes of the smallest path at the head of `sources`. When a node is in several
+/// sources, the one from the latest source wins.
+fn next_section<I>(
+    sources: &mut [Peekable<I>],
+) -> Result<Option<(RepoPathBuf, HashMap<Key, NodeInfo>)>>
+where
+    I: Iterator<Item = Result<Section>>,
+{
+    let mut file_name: Option<RepoPathBuf> = None;
+    for source in sources.iter_mut() {
+        if let Some(Err(_)) = source.peek() {
+            return Err(source.next().unwrap().unwrap_err());
+        }
+        if let Some(Ok((path, _))) = source.peek() {
+            if file_name.as_ref().map_or(true, |name| path < name) {
+                file_name = Some(path.clone());
+            }
+        }
+    }
+
+    let file_name = match file_name {
+        Some(file_name) => file_name,
+        None => return Ok(None),
+    };
+
+    let mut hgid_map = HashMap::new();
+    for source in sources.iter_mut() {
+        if matches!(source.peek(), Some(Ok((path, _))) if *path == file_name) {
+            let (_, entries) = source.next().unwrap()?;
+            hgid_map.extend(entries);
+        }
+    }
+    Ok(Some((file_name, hgid_map)))
+}
+
+impl MutablePack for HistoryPackWriter {
+    fn build_files(self) -> Result<(NamedTempFile, NamedTempFile, PathBuf)> {
+        let old_inner = (*self.inner.lock()).take();
+        if let Some(old_inner) = old_inner {
+            old_inner.build_files()
+        } else {
+            Err(EmptyMutablePack.into())
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        "hist"
+    }
+}
+
+impl HgIdHistoryStore for HistoryPackWriter {
+    fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+        match self.inner.lock().as_ref() {
+            Some(pack) => pack.get(key),
+            None => Ok(None),
+        }
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn iter_keys(&self) -> Result<Box<dyn Iterator<Item = Result<Key>> + '_>> {
+        let keys = match self.inner.lock().as_ref() {
+            Some(pack) => pack.keys()?,
+            None => vec![],
+        };
+        Ok(Box::new(keys.into_iter().map(Ok)))
+    }
+}
+
+impl LocalStore for HistoryPackWriter {
+    fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        let guard = self.inner.lock();
+        let pack = match guard.as_ref() {
+            Some(pack) => pack,
+            None => return Ok(keys.to_vec()),
+        };
+
+        let mut missing = Vec::new();
+        for k in keys {
+            let is_missing = match k {
+                StoreKey::HgId(k) => pack.get(k)?.is_none(),
+                StoreKey::Content(_, _) => true,
+            };
+            if is_missing {
+                missing.push(k.clone());
+            }
+        }
+        Ok(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use tempfile::tempdir;
+    use types::hgid::HgId;
+
+    use super::*;
+    use crate::mutablehistorypack::MutableHistoryPack;
+
+    fn make_entries(rng: &mut ChaChaRng) -> Vec<(Key, NodeInfo)> {
+        let mut entries = Vec::new();
+        for file in 0..5 {
+            let path = RepoPathBuf::from_string(format!("dir/file{}", file)).unwrap();
+            let null_key = Key::new(path.clone(), HgId::null_id().clone());
+            let mut previous: Vec<Key> = Vec::new();
+            for i in 0..8 {
+                let key = Key::new(path.clone(), HgId::random(rng));
+                let p1 = previous.last().cloned().unwrap_or_else(|| null_key.clone());
+                let p2 = if i % 3 == 2 {
+                    previous[previous.len() - 2].clone()
+                } else {
+                    null_key.clone()
+                };
+                let info = NodeInfo {
+                    parents: [p1, p2],
+                    linknode: HgId::random(rng),
+                };
+                entries.push((key.clone(), info));
+                previous.push(key);
+            }
+        }
+        entries
+    }
+
+    #[test]
+    fn test_same_bytes_as_mutable_pack() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let mut entries = make_entries(&mut rng);
+        entries.shuffle(&mut rng);
+
+        let batch_tempdir = tempdir().unwrap();
+        let batch = MutableHistoryPack::new(batch_tempdir.path(), HistoryPackVersion::One);
+        batch.add_many(&entries).unwrap();
+
+        // Small enough for the nodes of each file to be spread over several runs.
+        let streaming_tempdir = tempdir().unwrap();
+        let streaming =
+            HistoryPackWriter::new(streaming_tempdir.path(), HistoryPackVersion::One, 7);
+        for (key, info) in entries.iter() {
+            streaming.add(key, info).unwrap();
+        }
+        assert!(streaming.inner.lock().as_ref().unwrap().runs.len() > 1);
+
+        for (key, info) in entries.iter() {
+            assert_eq!(streaming.get_node_info(key).unwrap().as_ref(), Some(info));
+        }
+        assert_eq!(streaming.iter_keys().unwrap().count(), entries.len());
+
+        let batch_path = &batch.flush().unwrap().unwrap()[0];
+        let streaming_path = &streaming.flush().unwrap().unwrap()[0];
+        assert_eq!(batch_path.file_name(), streaming_path.file_name());
+        for extension in ["histpack", "histidx"] {
+            assert_eq!(
+                fs::read(batch_path.with_extension(extension)).unwrap(),
+                fs::read(streaming_path.with_extension(extension)).unwrap(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_readd_after_spill() {
+        let mut rng = ChaChaRng::from_seed([1u8; 32]);
+        let entries = make_entries(&mut rng);
+
+        let streaming_tempdir = tempdir().unwrap();
+        let streaming =
+            HistoryPackWriter::new(streaming_tempdir.path(), HistoryPackVersion::One, 4);
+        streaming.add_many(&entries).unwrap();
+        // Adding the same nodes again must not duplicate them in the pack.
+        streaming.add_many(&entries).unwrap();
+        assert_eq!(streaming.iter_keys().unwrap().count(), entries.len());
+
+        let batch_tempdir = tempdir().unwrap();
+        let batch = MutableHistoryPack::new(batch_tempdir.path(), HistoryPackVersion::One);
+        batch.add_many(&entries).unwrap();
+
+        let batch_path = &batch.flush().unwrap().unwrap()[0];
+        let streaming_path = &streaming.flush().unwrap().unwrap()[0];
+        assert_eq!(batch_path.file_name(), streaming_path.file_name());
+    }
+
+    #[test]
+    fn test_add_null_linknode() {
+        // Written by the client for the nodes whose linkrev was invalidated.
+        let mut rng = ChaChaRng::from_seed([2u8; 32]);
+        let entries: Vec<_> = make_entries(&mut rng)
+            .into_iter()
+            .map(|(key, info)| {
+                let info = NodeInfo {
+                    linknode: HgId::null_id().clone(),
+                    ..info
+                };
+                (key, info)
+            })
+            .collect();
+
+        let streaming_tempdir = tempdir().unwrap();
+        let streaming =
+            HistoryPackWriter::new(streaming_tempdir.path(), HistoryPackVersion::One, 4);
+        let (first, rest) = entries.split_first().unwrap();
+        streaming.add(&first.0, &first.1).unwrap();
+        streaming.add_many(rest).unwrap();
+        for (key, info) in entries.iter() {
+            assert_eq!(streaming.get_node_info(key).unwrap().as_ref(), Some(info));
+        }
+        assert!(streaming.flush().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_empty() {
+        let tempdir = tempdir().unwrap();
+        let streaming = HistoryPackWriter::new(tempdir.path(), HistoryPackVersion::One, 4);
+        assert!(streaming.flush().unwrap().is_none());
+        drop(streaming);
+        assert_eq!(fs::read_dir(tempdir.path()).unwrap().count(), 0);
+    }
+}
@@ -5,20 +5,121 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::HashSet;
 use std::ops::Deref;
 use std::path::PathBuf;
 
 use anyhow::Result;
 use edenapi_types::HistoryEntry;
+use types::HgId;
 use types::Key;
 use types::NodeInfo;
 
+use crate::ancestors::sort_ancestors_topo;
+use crate::ancestors::AncestorIterator;
+use crate::ancestors::Ancestors;
+use crate::ancestors::LimitedAncestors;
+use crate::error::IterKeysUnsupported;
+use crate::error::KeyNotFound;
 use crate::localstore::LocalStore;
 use crate::types::StoreKey;
 
 pub trait HgIdHistoryStore: LocalStore + Send + Sync {
     fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>>;
     fn refresh(&self) -> Result<()>;
+
+    /// Batched version of `get_node_info`. The results are in the same order as the `keys`,
+    /// with `None` for the keys that aren't present.
+    ///
+    /// Stores backed by a remote service should override it to fetch all the keys at once.
+    fn get_node_info_batch(&self, keys: &[Key]) -> Result<Vec<Option<NodeInfo>>> {
+        keys.iter().map(|key| self.get_node_info(key)).collect()
+    }
+
+    /// Return the linknode of `key`, i.e. the commit that introduced this revision of the file,
+    /// failing with `KeyNotFound` if the store doesn't have it.
+    fn get_linknode(&self, key: &Key) -> Result<HgId> {
+        match self.get_node_info(key)? {
+            Some(info) => Ok(info.linknode),
+            None => Err(KeyNotFound(key.clone()).into()),
+        }
+    }
+
+    /// Batched version of `get_linknode`. The results are in the same order as the `keys`, and
+    /// the first key that isn't present fails the whole batch with `KeyNotFound`.
+    ///
+    /// Stores that can fetch the linknodes without the parents should override it.
+    fn get_linknodes(&self, keys: &[Key]) -> Result<Vec<HgId>> {
+        self.get_node_info_batch(keys)?
+            .into_iter()
+            .zip(keys)
+            .map(|(info, key)| match info {
+                Some(info) => Ok(info.linknode),
+                None => Err(KeyNotFound(key.clone()).into()),
+            })
+            .collect()
+    }
+
+    /// Enumerate all the keys known to the store, for maintenance tooling such as verifying the
+    /// integrity of the store.
+    ///
+    /// Stores that can't enumerate their keys, remote ones in particular, return an
+    /// `IterKeysUnsupported` error.
+    fn iter_keys(&self) -> Result<Box<dyn Iterator<Item = Result<Key>> + '_>> {
+        Err(IterKeysUnsupported.into())
+    }
+
+    /// Lazily walk the history of `key` in breadth-first order, starting with `key` itself.
+    ///
+    /// Unlike `get_ancestors`, nothing is fetched past the point where the caller stops iterating.
+    fn get_ancestors_iter(&self, key: &Key) -> AncestorIterator<'_, Self>
+    where
+        Self: Sized,
+    {
+        AncestorIterator::new(self, key.clone())
+    }
+
+    /// Return the full history of `key`, including `key` itself.
+    fn get_ancestors(&self, key: &Key) -> Result<Ancestors>
+    where
+        Self: Sized,
+    {
+        self.get_ancestors_iter(key).collect()
+    }
+
+    /// Return the full history of `key` sorted so that every node comes before its parents,
+    /// starting with `key` itself. See `sort_ancestors_topo` for how merges are ordered.
+    fn get_ancestors_topo(&self, key: &Key) -> Result<Vec<(Key, NodeInfo)>>
+    where
+        Self: Sized,
+    {
+        Ok(sort_ancestors_topo(self.get_ancestors(key)?))
+    }
+
+    /// Return the history of `key` without the nodes of `stop` and the ancestors only reachable
+    /// through them, e.g. to skip the history a caller already has.
+    fn get_ancestors_excluding(&self, key: &Key, stop: &HashSet<Key>) -> Result<Ancestors>
+    where
+        Self: Sized,
+    {
+        self.get_ancestors_iter(key)
+            .with_stop(stop.clone())
+            .collect()
+    }
+
+    /// Return the ancestors of `key` that are at most `max_depth` parents away from it, with a
+    /// depth of 0 returning only `key` itself.
+    fn get_ancestors_with_limit(&self, key: &Key, max_depth: usize) -> Result<LimitedAncestors>
+    where
+        Self: Sized,
+    {
+        let mut iter = self.get_ancestors_iter(key).with_max_depth(max_depth);
+        let ancestors = iter.by_ref().collect::<Result<Ancestors>>()?;
+        Ok(LimitedAncestors {
+            ancestors,
+            truncated: iter.truncated(),
+        })
+    }
 }
 
 pub trait HgIdMutableHistoryStore: HgIdHistoryStore + Send + Sync {
@@ -28,6 +129,15 @@ pub trait HgIdMutableHistoryStore: HgIdHistoryStore + Send + Sync {
     fn add_entry(&self, entry: &HistoryEntry) -> Result<()> {
         self.add(&entry.key, &entry.nodeinfo)
     }
+
+    /// Add many nodes at once, e.g. when rebuilding the history fetched from a remote. Stores
+    /// for which adding the nodes one at a time is costly should override it.
+    fn add_many(&self, entries: &[(Key, NodeInfo)]) -> Result<()> {
+        for (key, info) in entries {
+            self.add(key, info)?;
+        }
+        Ok(())
+    }
 }
 
 /// The `RemoteHistoryStore` trait indicates that data can fetched over the network. Care must be
@@ -51,6 +161,22 @@ impl<T: HgIdHistoryStore + ?Sized, U: Deref<Target = T> + Send + Sync> HgIdHisto
     fn refresh(&self) -> Result<()> {
         T::refresh(self)
     }
+
+    fn get_node_info_batch(&self, keys: &[Key]) -> Result<Vec<Option<NodeInfo>>> {
+        T::get_node_info_batch(self, keys)
+    }
+
+    fn get_linknode(&self, key: &Key) -> Result<HgId> {
+        T::get_linknode(self, key)
+    }
+
+    fn get_linknodes(&self, keys: &[Key]) -> Result<Vec<HgId>> {
+        T::get_linknodes(self, keys)
+    }
+
+    fn iter_keys(&self) -> Result<Box<dyn Iterator<Item = Result<Key>> + '_>> {
+        T::iter_keys(self)
+    }
 }
 
 impl<T: HgIdMutableHistoryStore + ?Sized, U: Deref<Target = T> + Send + Sync>
@@ -63,6 +189,10 @@ impl<T: HgIdMutableHistoryStore + ?Sized, U: Deref<Target = T> + Send + Sync>
     fn flush(&self) -> Result<Option<Vec<PathBuf>>> {
         T::flush(self)
     }
+
+    fn add_many(&self, entries: &[(Key, NodeInfo)]) -> Result<()> {
+        T::add_many(self, entries)
+    }
 }
 
 impl<T: RemoteHistoryStore + ?Sized, U: Deref<Target = T> + Send + Sync> RemoteHistoryStore for U {
@@ -266,10 +266,15 @@ impl HgIdHistoryStore for IndexedLogHgIdHistoryStore {
     fn refresh(&self) -> Result<()> {
         Ok(())
     }
+
+    fn iter_keys(&self) -> Result<Box<dyn Iterator<Item = Result<Key>> + '_>> {
+        Ok(Box::new(self.to_keys().into_iter()))
+    }
 }
 
 impl HgIdMutableHistoryStore for IndexedLogHgIdHistoryStore {
     fn add(&self, key: &Key, info: &NodeInfo) -> Result<()> {
+        info.validate()?;
         let entry = Entry::new(key, info);
         entry.write_to_log(&self.log)
     }
@@ -347,6 +352,23 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_add_null_linknode() -> Result<()> {
+        // Written by the client for the nodes whose linkrev was invalidated.
+        let tempdir = TempDir::new()?;
+        let log = IndexedLogHgIdHistoryStore::new(&tempdir, &empty_config(), StoreType::Shared)?;
+        let k = key("a", "1");
+        let nodeinfo = NodeInfo {
+            parents: [key("a", "2"), null_key("a")],
+            linknode: HgId::null_id().clone(),
+        };
+        log.add(&k, &nodeinfo)?;
+        log.flush()?;
+
+        assert_eq!(log.get_node_info(&k)?, Some(nodeinfo));
+        Ok(())
+    }
+
     #[test]
     fn test_corrupted() -> Result<()> {
         let tempdir = TempDir::new()?;
@@ -122,6 +122,9 @@
 //! The produced stores must implement the `HgIdDataStore` trait.
 //!
 
+mod ancestors;
+mod asynchistorystore;
+mod cachinghistorystore;
 mod contentstore;
 mod dataindex;
 #[cfg(all(fbcode_build, target_os = "linux"))]
@@ -133,6 +136,7 @@ mod indexedloghistorystore;
 mod indexedlogutil;
 mod lfs;
 mod memcache;
+mod memoryhistorystore;
 mod metadatastore;
 mod missing;
 mod redacted;
@@ -147,6 +151,7 @@ pub mod datastore;
 pub mod edenapi;
 pub mod error;
 pub mod historypack;
+pub mod historypackwriter;
 pub mod historystore;
 pub mod indexedlogauxstore;
 pub mod indexedlogdatastore;
@@ -165,6 +170,15 @@ pub mod util;
 
 pub use revisionstore_types::*;
 
+pub use crate::ancestors::merge_ancestors;
+pub use crate::ancestors::sort_ancestors_topo;
+pub use crate::ancestors::AncestorIterator;
+pub use crate::ancestors::Ancestors;
+pub use crate::ancestors::LimitedAncestors;
+pub use crate::asynchistorystore::AsyncHgIdHistoryStore;
+pub use crate::asynchistorystore::AsyncHistoryStoreAdapter;
+pub use crate::asynchistorystore::SyncHistoryStoreAdapter;
+pub use crate::cachinghistorystore::CachingHgIdHistoryStore;
 pub use crate::contentstore::ContentStore;
 pub use crate::contentstore::ContentStoreBuilder;
 pub use crate::datapack::DataEntry;
@@ -184,6 +198,7 @@ pub use crate::edenapi::EdenApiTreeStore;
 pub use crate::historypack::HistoryEntry;
 pub use crate::historypack::HistoryPack;
 pub use crate::historypack::HistoryPackVersion;
+pub use crate::historypackwriter::HistoryPackWriter;
 pub use crate::historystore::HgIdHistoryStore;
 pub use crate::historystore::HgIdMutableHistoryStore;
 pub use crate::historystore::RemoteHistoryStore;
@@ -195,6 +210,7 @@ pub use crate::indexedlogutil::StoreType;
 pub use crate::localstore::ExtStoredPolicy;
 pub use crate::localstore::LocalStore;
 pub use crate::memcache::MemcacheStore;
+pub use crate::memoryhistorystore::MemoryHgIdHistoryStore;
 pub use crate::metadatastore::MetadataStore;
 pub use crate::metadatastore::MetadataStoreBuilder;
 pub use crate::multiplexstore::MultiplexDeltaStore;
@@ -0,0 +1,176 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! In-memory history store, used to build history data programmatically (tests, conversion
+//! tools) without writing a pack by hand.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use parking_lot::RwLock;
+use types::Key;
+use types::NodeInfo;
+
+use crate::historystore::HgIdHistoryStore;
+use crate::historystore::HgIdMutableHistoryStore;
+use crate::localstore::LocalStore;
+use crate::repack::ToKeys;
+use crate::types::StoreKey;
+
+#[derive(Default)]
+pub struct MemoryHgIdHistoryStore {
+    map: RwLock<HashMap<Key, NodeInfo>>,
+}
+
+impl MemoryHgIdHistoryStore {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.read().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.read().is_empty()
+    }
+}
+
+impl From<HashMap<Key, NodeInfo>> for MemoryHgIdHistoryStore {
+    fn from(map: HashMap<Key, NodeInfo>) -> Self {
+        Self {
+            map: RwLock::new(map),
+        }
+    }
+}
+
+impl HgIdHistoryStore for MemoryHgIdHistoryStore {
+    fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+        Ok(self.map.read().get(key).cloned())
+    }
+
+    fn refresh(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn iter_keys(&self) -> Result<Box<dyn Iterator<Item = Result<Key>> + '_>> {
+        Ok(Box::new(self.to_keys().into_iter()))
+    }
+}
+
+impl HgIdMutableHistoryStore for MemoryHgIdHistoryStore {
+    fn add(&self, key: &Key, info: &NodeInfo) -> Result<()> {
+        info.validate()?;
+        self.map.write().insert(key.clone(), info.clone());
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<Option<Vec<PathBuf>>> {
+        Ok(None)
+    }
+}
+
+impl LocalStore for MemoryHgIdHistoryStore {
+    fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+        let map = self.map.read();
+        Ok(keys
+            .iter()
+            .filter(|k| match k {
+                StoreKey::HgId(k) => !map.contains_key(k),
+                StoreKey::Content(_, _) => true,
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+impl ToKeys for MemoryHgIdHistoryStore {
+    fn to_keys(&self) -> Vec<Result<Key>> {
+        self.map.read().keys().cloned().map(Ok).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaChaRng;
+    use types::testutil::*;
+    use types::HgId;
+
+    use super::*;
+    use crate::historypack::tests::get_nodes;
+
+    #[test]
+    fn test_add_get_node_info() -> Result<()> {
+        let store = MemoryHgIdHistoryStore::new();
+        let k = key("a", "1");
+        let nodeinfo = NodeInfo {
+            parents: [key("a", "2"), null_key("a")],
+            linknode: hgid("3"),
+        };
+
+        store.add(&k, &nodeinfo)?;
+        assert_eq!(store.flush()?, None);
+
+        assert_eq!(store.get_node_info(&k)?, Some(nodeinfo));
+        assert_eq!(store.get_node_info(&key("a", "2"))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_null_linknode() -> Result<()> {
+        // Written by the client for the nodes whose linkrev was invalidated.
+        let store = MemoryHgIdHistoryStore::new();
+        let k = key("a", "1");
+        let nodeinfo = NodeInfo {
+            parents: [key("a", "2"), null_key("a")],
+            linknode: HgId::null_id().clone(),
+        };
+
+        store.add(&k, &nodeinfo)?;
+        assert_eq!(store.get_node_info(&k)?, Some(nodeinfo));
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip() -> Result<()> {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let nodes = get_nodes(&mut rng);
+
+        let store = MemoryHgIdHistoryStore::new();
+        for (key, info) in nodes.iter() {
+            store.add(key, info)?;
+        }
+
+        assert_eq!(store.len(), nodes.len());
+        for (key, info) in nodes.iter() {
+            assert_eq!(store.get_node_info(key)?.as_ref(), Some(info));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_missing() -> Result<()> {
+        let store = MemoryHgIdHistoryStore::new();
+        let k = key("a", "1");
+        store.add(
+            &k,
+            &NodeInfo {
+                parents: [null_key("a"), null_key("a")],
+                linknode: hgid("2"),
+            },
+        )?;
+
+        let missing = StoreKey::from(key("b", "3"));
+        assert_eq!(
+            store.get_missing(&[StoreKey::from(k), missing.clone()])?,
+            vec![missing]
+        );
+        Ok(())
+    }
+}
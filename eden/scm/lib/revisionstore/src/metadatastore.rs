@@ -124,6 +124,10 @@ impl HgIdHistoryStore for MetadataStore {
     fn refresh(&self) -> Result<()> {
         self.historystore.refresh()
     }
+
+    fn get_node_info_batch(&self, keys: &[Key]) -> Result<Vec<Option<NodeInfo>>> {
+        self.historystore.get_node_info_batch(keys)
+    }
 }
 
 impl RemoteHistoryStore for MetadataStore {
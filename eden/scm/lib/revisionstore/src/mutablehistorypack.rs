@@ -5,8 +5,8 @@
  * GNU General Public License version 2.
  */
 
+use std::collections::BTreeSet;
 use std::collections::HashMap;
-use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::io::Write;
 use std::path::Path;
@@ -71,57 +71,6 @@ impl MutableHistoryPackInner {
             mem_index: HashMap::new(),
         })
     }
-
-    fn write_section<'a>(
-        &self,
-        writer: &mut Vec<u8>,
-        file_name: &'a RepoPath,
-        hgid_map: &HashMap<Key, NodeInfo>,
-        section_offset: usize,
-        nodes: &mut HashMap<&'a RepoPath, HashMap<Key, NodeLocation>>,
-    ) -> Result<()> {
-        let mut hgid_locations = HashMap::<Key, NodeLocation>::with_capacity(hgid_map.len());
-
-        // Write section header
-        FileSectionHeader {
-            file_name: &file_name,
-            count: hgid_map.len() as u32,
-        }
-        .write(writer)?;
-
-        // Sort the nodes in topological order (ancestors first), as required by the histpack spec
-        let hgid_map = topo_sort(hgid_map)?;
-
-        // Write nodes
-        for (key, node_info) in hgid_map.iter() {
-            let p1 = &node_info.parents[0];
-            let copyfrom = if !p1.hgid.is_null() && p1.path != key.path {
-                Some(p1.path.as_ref())
-            } else {
-                None
-            };
-
-            let hgid_offset = section_offset + writer.len() as usize;
-            HistoryEntry::write(
-                writer,
-                &key.hgid,
-                &node_info.parents[0].hgid,
-                &node_info.parents[1].hgid,
-                &node_info.linknode,
-                &copyfrom,
-            )?;
-
-            hgid_locations.insert(
-                (*key).clone(),
-                NodeLocation {
-                    offset: hgid_offset as u64,
-                },
-            );
-        }
-
-        nodes.insert(file_name, hgid_locations);
-        Ok(())
-    }
 }
 
 impl MutableHistoryPack {
@@ -147,10 +96,8 @@ impl MutableHistoryPack {
     }
 }
 
-impl HgIdMutableHistoryStore for MutableHistoryPack {
-    fn add(&self, key: &Key, info: &NodeInfo) -> Result<()> {
-        let mut guard = self.inner.lock();
-        let pack = self.get_pack(&mut guard)?;
+impl MutableHistoryPackInner {
+    fn add(&mut self, key: &Key, info: &NodeInfo) {
         // Loops in the graph aren't allowed. Since this is a logic error in the code, let's
         // assert.
         assert_ne!(key.hgid, info.parents[0].hgid);
@@ -160,11 +107,34 @@ impl HgIdMutableHistoryStore for MutableHistoryPack {
         //     self.mem_index.entry(key.name()).or_insert_with(|| HashMap::new())
         // To get the inner map, then insert our new NodeInfo. Unfortunately it requires
         // key.name().clone() though. So we have to do it the long way to avoid the allocation.
-        let entries = pack
+        let entries = self
             .mem_index
             .entry(key.path.clone())
             .or_insert_with(HashMap::new);
         entries.insert(key.clone(), info.clone());
+    }
+}
+
+impl HgIdMutableHistoryStore for MutableHistoryPack {
+    fn add(&self, key: &Key, info: &NodeInfo) -> Result<()> {
+        info.validate()?;
+        let mut guard = self.inner.lock();
+        let pack = self.get_pack(&mut guard)?;
+        pack.add(key, info);
+        Ok(())
+    }
+
+    /// Add all the entries under a single lock. Their order doesn't matter, the pack is written
+    /// in a deterministic order when flushed.
+    fn add_many(&self, entries: &[(Key, NodeInfo)]) -> Result<()> {
+        for (_key, info) in entries {
+            info.validate()?;
+        }
+        let mut guard = self.inner.lock();
+        let pack = self.get_pack(&mut guard)?;
+        for (key, info) in entries {
+            pack.add(key, info);
+        }
         Ok(())
     }
 
@@ -209,13 +179,13 @@ impl MutablePack for MutableHistoryPackInner {
         keys.sort_unstable();
         for file_name in keys {
             let hgid_map = self.mem_index.get(file_name).unwrap();
-            self.write_section(
+            let hgid_locations = write_section(
                 &mut section_buf,
                 file_name,
                 hgid_map,
                 section_offset as usize,
-                &mut nodes,
             )?;
+            nodes.insert(file_name, hgid_locations);
             hasher.update(&section_buf);
             data_file.write_all(&section_buf)?;
 
@@ -260,12 +230,63 @@ impl MutablePack for MutableHistoryPack {
     }
 }
 
+/// Write the section of `file_name`, whose nodes are in `hgid_map`, to `writer`. Returns the
+/// location of each node, given that the section starts at `section_offset` in the pack.
+pub(crate) fn write_section(
+    writer: &mut Vec<u8>,
+    file_name: &RepoPath,
+    hgid_map: &HashMap<Key, NodeInfo>,
+    section_offset: usize,
+) -> Result<HashMap<Key, NodeLocation>> {
+    let mut hgid_locations = HashMap::<Key, NodeLocation>::with_capacity(hgid_map.len());
+
+    // Write section header
+    FileSectionHeader {
+        file_name,
+        count: hgid_map.len() as u32,
+    }
+    .write(writer)?;
+
+    // Sort the nodes in topological order (ancestors first), as required by the histpack spec
+    let hgid_map = topo_sort(hgid_map)?;
+
+    // Write nodes
+    for (key, node_info) in hgid_map.iter() {
+        let p1 = &node_info.parents[0];
+        let copyfrom = if !p1.hgid.is_null() && p1.path != key.path {
+            Some(p1.path.as_ref())
+        } else {
+            None
+        };
+
+        let hgid_offset = section_offset + writer.len() as usize;
+        HistoryEntry::write(
+            writer,
+            &key.hgid,
+            &node_info.parents[0].hgid,
+            &node_info.parents[1].hgid,
+            &node_info.linknode,
+            &copyfrom,
+        )?;
+
+        hgid_locations.insert(
+            (*key).clone(),
+            NodeLocation {
+                offset: hgid_offset as u64,
+            },
+        );
+    }
+
+    Ok(hgid_locations)
+}
+
 fn topo_sort(hgid_map: &HashMap<Key, NodeInfo>) -> Result<Vec<(&Key, &NodeInfo)>> {
     // Sorts the given keys into newest-first topological order
     let mut roots = Vec::<&Key>::new();
 
     // Child map will be used to perform an oldest-first walk later.
-    let mut child_map = HashMap::<&Key, HashSet<&Key>>::with_capacity(hgid_map.len());
+    // The children are kept sorted, so that siblings are written in a deterministic order.
+    let mut child_map = HashMap::<&Key, BTreeSet<&Key>>::with_capacity(hgid_map.len());
     // Parent count will be used to keep track of when all a commit's parents have been processed.
     let mut parent_counts = HashMap::with_capacity(hgid_map.len());
 
@@ -342,6 +363,18 @@ impl HgIdHistoryStore for MutableHistoryPack {
     fn refresh(&self) -> Result<()> {
         Ok(())
     }
+
+    fn iter_keys(&self) -> Result<Box<dyn Iterator<Item = Result<Key>> + '_>> {
+        let keys = match self.inner.lock().as_ref() {
+            Some(pack) => pack
+                .mem_index
+                .values()
+                .flat_map(|nodes| nodes.keys().cloned().map(Ok))
+                .collect(),
+            None => vec![],
+        };
+        Ok(Box::new(keys.into_iter()))
+    }
 }
 
 impl LocalStore for MutableHistoryPack {
@@ -375,6 +408,7 @@ mod tests {
     use rand_chacha::ChaChaRng;
     use tempfile::tempdir;
     use types::hgid::HgId;
+    use types::nodeinfo::InvalidNodeInfo;
     use types::testutil::key;
 
     use super::*;
@@ -452,6 +486,77 @@ mod tests {
         assert_eq!(actual_order, expected_order);
     }
 
+    #[test]
+    fn test_add_many() {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let null_key = Key::new(RepoPathBuf::new(), HgId::null_id().clone());
+
+        // Several children of the same parents, whose relative order in the pack is only
+        // decided by the sort.
+        let mut entries = Vec::<(Key, NodeInfo)>::new();
+        let root = Key::new(RepoPathBuf::new(), HgId::random(&mut rng));
+        entries.push((
+            root.clone(),
+            NodeInfo {
+                parents: [null_key.clone(), null_key.clone()],
+                linknode: HgId::random(&mut rng),
+            },
+        ));
+        for _ in 0..10 {
+            let key = Key::new(RepoPathBuf::new(), HgId::random(&mut rng));
+            let info = NodeInfo {
+                parents: [root.clone(), null_key.clone()],
+                linknode: HgId::random(&mut rng),
+            };
+            entries.push((key, info));
+        }
+
+        entries.shuffle(&mut rng);
+        let added_tempdir = tempdir().unwrap();
+        let added = MutableHistoryPack::new(added_tempdir.path(), HistoryPackVersion::One);
+        for (key, info) in entries.iter() {
+            added.add(&key, &info).unwrap();
+        }
+
+        entries.shuffle(&mut rng);
+        let added_many_tempdir = tempdir().unwrap();
+        let added_many =
+            MutableHistoryPack::new(added_many_tempdir.path(), HistoryPackVersion::One);
+        added_many.add_many(&entries).unwrap();
+
+        for (key, info) in entries.iter() {
+            assert_eq!(added.get_node_info(&key).unwrap().as_ref(), Some(info));
+            assert_eq!(added_many.get_node_info(&key).unwrap().as_ref(), Some(info));
+        }
+
+        // The packs are named after their content, so the same entries added in a different
+        // order must give the same pack.
+        let added_path = &added.flush().unwrap().unwrap()[0];
+        let added_many_path = &added_many.flush().unwrap().unwrap()[0];
+        assert_eq!(added_path.file_name(), added_many_path.file_name());
+    }
+
+    #[test]
+    fn test_add_null_linknode() {
+        // Written by the client for the nodes whose linkrev was invalidated.
+        let tempdir = tempdir().unwrap();
+        let muthistorypack = MutableHistoryPack::new(tempdir.path(), HistoryPackVersion::One);
+
+        let k = key("a", "1");
+        let nodeinfo = NodeInfo {
+            parents: [
+                key("a", "2"),
+                Key::new(k.path.clone(), HgId::null_id().clone()),
+            ],
+            linknode: HgId::null_id().clone(),
+        };
+        muthistorypack.add(&k, &nodeinfo).unwrap();
+        muthistorypack
+            .add_many(&[(k.clone(), nodeinfo.clone())])
+            .unwrap();
+        assert_eq!(muthistorypack.get_node_info(&k).unwrap(), Some(nodeinfo));
+    }
+
     #[test]
     #[should_panic]
     fn test_loop() {
@@ -460,13 +565,33 @@ mod tests {
 
         let k = key("a", "1");
         let nodeinfo = NodeInfo {
-            parents: [k.clone(), k.clone()],
+            parents: [k.clone(), Key::new(k.path.clone(), HgId::null_id().clone())],
             linknode: Default::default(),
         };
 
         muthistorypack.add(&k, &nodeinfo).unwrap();
     }
 
+    #[test]
+    fn test_add_duplicate_parents() {
+        let tempdir = tempdir().unwrap();
+        let muthistorypack = MutableHistoryPack::new(tempdir.path(), HistoryPackVersion::One);
+
+        let k = key("a", "1");
+        let p = key("a", "2");
+        let nodeinfo = NodeInfo {
+            parents: [p.clone(), p.clone()],
+            linknode: Default::default(),
+        };
+
+        let err = muthistorypack.add(&k, &nodeinfo).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<InvalidNodeInfo>(),
+            Some(&InvalidNodeInfo::DuplicateParents(p))
+        );
+        assert!(muthistorypack.flush().unwrap().is_none());
+    }
+
     #[test]
     fn test_empty() {
         let tempdir = tempdir().unwrap();
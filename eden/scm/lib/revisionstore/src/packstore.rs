@@ -42,6 +42,7 @@ use crate::localstore::StoreFromPath;
 use crate::mutabledatapack::MutableDataPack;
 use crate::mutablehistorypack::MutableHistoryPack;
 use crate::repack::Repackable;
+use crate::repack::ToKeys;
 use crate::types::StoreKey;
 use crate::uniondatastore::UnionHgIdDataStore;
 use crate::unionhistorystore::UnionHgIdHistoryStore;
@@ -498,6 +499,18 @@ impl HgIdHistoryStore for HistoryPackStore {
         inner.last_scanned.replace(None);
         Ok(())
     }
+
+    fn iter_keys(&self) -> Result<Box<dyn Iterator<Item = Result<Key>> + '_>> {
+        let inner = self.inner.lock();
+        inner.try_scan()?;
+        let keys = inner
+            .packs
+            .borrow()
+            .iter()
+            .flat_map(|pack| pack.to_keys())
+            .collect::<Vec<_>>();
+        Ok(Box::new(keys.into_iter()))
+    }
 }
 
 struct MutableDataPackStoreInner {
@@ -689,6 +702,18 @@ impl HgIdMutableHistoryStore for MutableHistoryPackStore {
         Ok(())
     }
 
+    fn add_many(&self, entries: &[(Key, NodeInfo)]) -> Result<()> {
+        self.inner.mutable_pack.add_many(entries)?;
+        let pending = self
+            .pending
+            .fetch_add(entries.len() as u64, Ordering::SeqCst)
+            + entries.len() as u64;
+        if pending >= self.max_pending {
+            self.inner_flush()?;
+        }
+        Ok(())
+    }
+
     /// Flush the current mutable historypack to disk and add it to the `PackStore`.
     fn flush(&self) -> Result<Option<Vec<PathBuf>>> {
         self.inner_flush()?;
@@ -938,6 +963,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_histpack_iter_keys() -> Result<()> {
+        let mut rng = ChaChaRng::from_seed([0u8; 32]);
+        let tempdir = TempDir::new()?;
+        let store = HistoryPackStore::new(&tempdir, CorruptionPolicy::REMOVE, None);
+
+        let nodes = get_nodes(&mut rng);
+        make_historypack(&tempdir, &nodes);
+
+        // Every enumerated key can be looked up, as a verify command would.
+        let mut keys = store.iter_keys()?.collect::<Result<Vec<Key>>>()?;
+        for key in keys.iter() {
+            assert_eq!(store.get_node_info(key)?.as_ref(), nodes.get(key));
+        }
+
+        keys.sort_unstable();
+        let mut expected: Vec<Key> = nodes.keys().cloned().collect();
+        expected.sort_unstable();
+        assert_eq!(keys, expected);
+
+        Ok(())
+    }
+
     #[test]
     fn test_lrustore_order() -> Result<()> {
         let tempdir = TempDir::new()?;
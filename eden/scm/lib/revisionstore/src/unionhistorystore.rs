@@ -35,6 +35,33 @@ impl<T: HgIdHistoryStore> HgIdHistoryStore for UnionHgIdHistoryStore<T> {
         }
         Ok(())
     }
+
+    fn get_node_info_batch(&self, keys: &[Key]) -> Result<Vec<Option<NodeInfo>>> {
+        let mut results = vec![None; keys.len()];
+        // Indices of the keys that haven't been found yet.
+        let mut missing: Vec<usize> = (0..keys.len()).collect();
+        for store in self {
+            if missing.is_empty() {
+                break;
+            }
+
+            let missing_keys: Vec<Key> = missing.iter().map(|i| keys[*i].clone()).collect();
+            let found = store.get_node_info_batch(&missing_keys)?;
+            missing = missing
+                .into_iter()
+                .zip(found)
+                .filter_map(|(i, info)| match info {
+                    Some(info) => {
+                        results[i] = Some(info);
+                        None
+                    }
+                    None => Some(i),
+                })
+                .collect();
+        }
+
+        Ok(results)
+    }
 }
 
 impl<T: RemoteHistoryStore> RemoteHistoryStore for UnionHgIdHistoryStore<T> {
@@ -59,12 +86,19 @@ impl<T: RemoteHistoryStore> RemoteHistoryStore for UnionHgIdHistoryStore<T> {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
     use quickcheck::quickcheck;
     use thiserror::Error;
+    use types::testutil::*;
 
     use super::*;
+    use crate::error::IterKeysUnsupported;
     use crate::localstore::LocalStore;
     use crate::types::StoreKey;
+    use crate::HgIdMutableHistoryStore;
+    use crate::MemoryHgIdHistoryStore;
 
     struct BadHgIdHistoryStore;
 
@@ -106,6 +140,159 @@ mod tests {
         }
     }
 
+    fn nodeinfo(parent: Key) -> NodeInfo {
+        NodeInfo {
+            parents: [parent, null_key("a")],
+            linknode: hgid("9"),
+        }
+    }
+
+    /// Two stores that disagree on `key("a", "2")`, with its parent only known to the second one.
+    fn boxed_union() -> Result<UnionHgIdHistoryStore<Box<dyn HgIdHistoryStore>>> {
+        let local = MemoryHgIdHistoryStore::new();
+        local.add(&key("a", "2"), &nodeinfo(key("a", "1")))?;
+
+        let remote = MemoryHgIdHistoryStore::new();
+        remote.add(&key("a", "2"), &nodeinfo(key("a", "0")))?;
+        remote.add(&key("a", "1"), &nodeinfo(null_key("a")))?;
+        remote.add(&key("a", "0"), &nodeinfo(null_key("a")))?;
+
+        Ok(vec![
+            Box::new(local) as Box<dyn HgIdHistoryStore>,
+            Box::new(remote),
+        ]
+        .into_iter()
+        .collect())
+    }
+
+    #[test]
+    fn test_get_node_info_precedence() -> Result<()> {
+        let unionstore = boxed_union()?;
+        assert_eq!(
+            unionstore.get_node_info(&key("a", "2"))?,
+            Some(nodeinfo(key("a", "1")))
+        );
+        assert_eq!(
+            unionstore.get_node_info(&key("a", "1"))?,
+            Some(nodeinfo(null_key("a")))
+        );
+        assert_eq!(unionstore.get_node_info(&key("a", "3"))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_missing_intersection() -> Result<()> {
+        let unionstore = boxed_union()?;
+        let keys = vec![
+            StoreKey::hgid(key("a", "2")),
+            StoreKey::hgid(key("a", "1")),
+            StoreKey::hgid(key("a", "3")),
+        ];
+        assert_eq!(
+            unionstore.get_missing(&keys)?,
+            vec![StoreKey::hgid(key("a", "3"))]
+        );
+        Ok(())
+    }
+
+    struct CountingHistoryStore {
+        store: MemoryHgIdHistoryStore,
+        get_missing: AtomicUsize,
+    }
+
+    impl CountingHistoryStore {
+        fn new(keys: &[Key]) -> Result<Self> {
+            let store = MemoryHgIdHistoryStore::new();
+            for key in keys {
+                store.add(key, &nodeinfo(null_key("a")))?;
+            }
+            Ok(Self {
+                store,
+                get_missing: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    impl HgIdHistoryStore for CountingHistoryStore {
+        fn get_node_info(&self, key: &Key) -> Result<Option<NodeInfo>> {
+            self.store.get_node_info(key)
+        }
+
+        fn refresh(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LocalStore for CountingHistoryStore {
+        fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
+            self.get_missing.fetch_add(1, Ordering::Relaxed);
+            self.store.get_missing(keys)
+        }
+    }
+
+    #[test]
+    fn test_get_missing_short_circuit() -> Result<()> {
+        let unionstore: UnionHgIdHistoryStore<CountingHistoryStore> = vec![
+            CountingHistoryStore::new(&[key("a", "1"), key("a", "2")])?,
+            CountingHistoryStore::new(&[key("a", "2"), key("a", "3")])?,
+            CountingHistoryStore::new(&[key("a", "3"), key("a", "4")])?,
+        ]
+        .into_iter()
+        .collect();
+        let calls = |unionstore: &UnionHgIdHistoryStore<CountingHistoryStore>| {
+            unionstore
+                .into_iter()
+                .map(|store| store.get_missing.load(Ordering::Relaxed))
+                .collect::<Vec<_>>()
+        };
+
+        // Found by the first two stores, the third one isn't asked.
+        let keys = vec![StoreKey::hgid(key("a", "1")), StoreKey::hgid(key("a", "3"))];
+        assert!(unionstore.get_missing(&keys)?.is_empty());
+        assert_eq!(calls(&unionstore), vec![1, 1, 0]);
+
+        // Missing everywhere.
+        let keys = vec![StoreKey::hgid(key("a", "4")), StoreKey::hgid(key("a", "5"))];
+        assert_eq!(
+            unionstore.get_missing(&keys)?,
+            vec![StoreKey::hgid(key("a", "5"))]
+        );
+        assert_eq!(calls(&unionstore), vec![2, 2, 1]);
+
+        // Found by the first store only.
+        let keys = vec![StoreKey::hgid(key("a", "2"))];
+        assert!(unionstore.get_missing(&keys)?.is_empty());
+        assert_eq!(calls(&unionstore), vec![3, 2, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_ancestors_merge() -> Result<()> {
+        let unionstore = boxed_union()?;
+        let ancestors = unionstore.get_ancestors(&key("a", "2"))?;
+
+        // The history of the first store wins, so `key("a", "0")` is never reached.
+        assert_eq!(ancestors.len(), 2);
+        assert_eq!(
+            ancestors.get(&key("a", "2")),
+            Some(&nodeinfo(key("a", "1")))
+        );
+        assert_eq!(
+            ancestors.get(&key("a", "1")),
+            Some(&nodeinfo(null_key("a")))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_keys_unsupported() {
+        let err = match EmptyHgIdHistoryStore.iter_keys() {
+            Ok(_) => panic!("the store can't enumerate its keys"),
+            Err(err) => err,
+        };
+        assert!(err.downcast_ref::<IterKeysUnsupported>().is_some());
+    }
+
     quickcheck! {
         fn test_empty_unionstore_get_node_info(key: Key) -> bool {
             match UnionHgIdHistoryStore::<EmptyHgIdHistoryStore>::new().get_node_info(&key) {
@@ -142,6 +329,30 @@ mod tests {
             keys == unionstore.get_missing(&keys).unwrap()
         }
 
+        fn test_get_node_info_batch(entries: Vec<(Key, NodeInfo)>, absent: Vec<Key>) -> bool {
+            let mut unionstore = UnionHgIdHistoryStore::new();
+            let first = MemoryHgIdHistoryStore::new();
+            let second = MemoryHgIdHistoryStore::new();
+            for (i, (key, info)) in entries.iter().enumerate() {
+                let store = if i % 2 == 0 { &first } else { &second };
+                store.add(key, info).unwrap();
+            }
+            unionstore.add(first);
+            unionstore.add(second);
+
+            let keys: Vec<Key> = entries
+                .iter()
+                .map(|(key, _)| key.clone())
+                .chain(absent.into_iter())
+                .collect();
+            let batch = unionstore.get_node_info_batch(&keys).unwrap();
+            let single: Vec<_> = keys
+                .iter()
+                .map(|key| unionstore.get_node_info(key).unwrap())
+                .collect();
+            batch == single
+        }
+
         fn test_bad_historystore_get_missing(keys: Vec<StoreKey>) -> bool {
             let mut unionstore = UnionHgIdHistoryStore::new();
             unionstore.add(BadHgIdHistoryStore);
@@ -31,14 +31,27 @@ impl<T> UnionStore<T> {
     }
 }
 
+/// Build a union from an ordered list of stores, the first store having precedence.
+impl<T> FromIterator<T> for UnionStore<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        UnionStore {
+            stores: iter.into_iter().collect(),
+        }
+    }
+}
+
 impl<T: LocalStore> LocalStore for UnionStore<T> {
+    /// Return the keys missing from every store. Each store is only asked about the keys that
+    /// the previous ones lack, and the remaining stores are skipped once all keys were found.
     fn get_missing(&self, keys: &[StoreKey]) -> Result<Vec<StoreKey>> {
-        let initial_keys = Ok(keys.to_vec());
-        self.into_iter()
-            .fold(initial_keys, |missing_keys, store| match missing_keys {
-                Ok(missing_keys) => store.get_missing(&missing_keys),
-                Err(e) => Err(e),
-            })
+        let mut missing_keys = keys.to_vec();
+        for store in self {
+            missing_keys = store.get_missing(&missing_keys)?;
+            if missing_keys.is_empty() {
+                break;
+            }
+        }
+        Ok(missing_keys)
     }
 }
 
@@ -77,6 +77,12 @@ impl HgId {
         &NULL_ID
     }
 
+    /// An owned nullid, for places that need a value rather than a reference.
+    pub const fn null() -> Self {
+        NULL_ID
+    }
+
+    /// Whether this is the nullid, which stands for a missing parent in history graphs.
     pub fn is_null(&self) -> bool {
         self == &NULL_ID
     }
@@ -266,9 +272,19 @@ mod tests {
         assert_eq!(decode::<Hex>(&cbor_hex).unwrap().0, id);
     }
 
+    #[test]
+    fn test_null() {
+        assert!(HgId::null().is_null());
+        assert_eq!(HgId::null(), NULL_ID);
+    }
+
     quickcheck! {
         fn test_from_slice(hgid: HgId) -> bool {
             hgid == HgId::from_slice(hgid.as_ref()).expect("from_slice")
         }
+
+        fn test_is_null(hgid: HgId) -> bool {
+            hgid.is_null() == hgid.as_ref().iter().all(|b| *b == 0)
+        }
     }
 }
@@ -7,9 +7,11 @@
 
 use serde_derive::Deserialize;
 use serde_derive::Serialize;
+use thiserror::Error;
 
 use crate::hgid::HgId;
 use crate::key::Key;
+use crate::path::RepoPathBuf;
 
 #[derive(
     Clone,
@@ -28,6 +30,48 @@ pub struct NodeInfo {
     pub linknode: HgId,
 }
 
+#[derive(Debug, Error, PartialEq)]
+pub enum InvalidNodeInfo {
+    #[error("both parents are {0}")]
+    DuplicateParents(Key),
+    #[error("null linknode for a node with parents")]
+    NullLinknode,
+}
+
+impl NodeInfo {
+    /// Constructs a `NodeInfo`, rejecting the combinations that can't describe a real node,
+    /// including a null linknode for a node with parents.
+    pub fn new(parents: [Key; 2], linknode: HgId) -> Result<Self, InvalidNodeInfo> {
+        let info = NodeInfo { parents, linknode };
+        info.validate()?;
+        let [p1, p2] = &info.parents;
+        if info.linknode.is_null() && !(p1.hgid.is_null() && p2.hgid.is_null()) {
+            return Err(InvalidNodeInfo::NullLinknode);
+        }
+        Ok(info)
+    }
+
+    /// Constructs the `NodeInfo` of a node without parents, such as the first revision of a file.
+    pub fn new_root(path: RepoPathBuf, linknode: HgId) -> Self {
+        let null = Key::new(path, HgId::null());
+        NodeInfo {
+            parents: [null.clone(), null],
+            linknode,
+        }
+    }
+
+    /// Checks that the two parents are distinct unless null. Unlike `new`, the linknode isn't
+    /// checked: the stores hold nodes whose linkrev was invalidated by the client, with a null
+    /// linknode.
+    pub fn validate(&self) -> Result<(), InvalidNodeInfo> {
+        let [p1, p2] = &self.parents;
+        if !p1.hgid.is_null() && p1 == p2 {
+            return Err(InvalidNodeInfo::DuplicateParents(p1.clone()));
+        }
+        Ok(())
+    }
+}
+
 #[cfg(any(test, feature = "for-tests"))]
 use quickcheck::Arbitrary;
 #[cfg(any(test, feature = "for-tests"))]
@@ -42,3 +86,39 @@ impl Arbitrary for NodeInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::*;
+
+    #[test]
+    fn test_new() {
+        let info = NodeInfo::new([key("a", "1"), key("a", "2")], hgid("3")).unwrap();
+        assert_eq!(info.parents, [key("a", "1"), key("a", "2")]);
+
+        assert_eq!(
+            NodeInfo::new([key("a", "1"), key("a", "1")], hgid("3")),
+            Err(InvalidNodeInfo::DuplicateParents(key("a", "1")))
+        );
+        assert_eq!(
+            NodeInfo::new([key("a", "1"), null_key("a")], HgId::null_id().clone()),
+            Err(InvalidNodeInfo::NullLinknode)
+        );
+        assert!(NodeInfo::new([null_key("a"), null_key("a")], HgId::null_id().clone()).is_ok());
+
+        // The stores still accept a null linknode
+        let info = NodeInfo {
+            parents: [key("a", "1"), null_key("a")],
+            linknode: HgId::null_id().clone(),
+        };
+        assert!(info.validate().is_ok());
+    }
+
+    #[test]
+    fn test_new_root() {
+        let info = NodeInfo::new_root(repo_path_buf("a"), hgid("3"));
+        assert_eq!(info.parents, [null_key("a"), null_key("a")]);
+        assert!(info.validate().is_ok());
+    }
+}
@@ -61,7 +61,7 @@ pub fn key(path: &str, hexnode: &str) -> Key {
 
 /// The null hgid id is special and it's semantics vary. A null key contains a null hgid id.
 pub fn null_key(path: &str) -> Key {
-    Key::new(repo_path_buf(path), HgId::null_id().clone())
+    Key::new(repo_path_buf(path), HgId::null())
 }
 
 pub fn generate_repo_paths(count: usize, qc_gen: &mut Gen) -> Vec<RepoPathBuf> {